@@ -0,0 +1,1009 @@
+//! Builders for constructing NITF files from scratch
+//!
+//! Each header builder starts from a valid subheader filled with the spec defaults,
+//! and every setter checks that the value fits the width of its field. The
+//! [NitfBuilder] collects headers and segment data into a [Nitf] which is ready to
+//! be written with [Nitf::write_to()].
+//! ```
+//! use nitf_rs::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+//! use nitf_rs::headers::image_hdr::ImageRepresentation;
+//!
+//! let header = NitfHeaderBuilder::new()?.ftitle("Example")?.build();
+//! let image = ImageHeaderBuilder::new()?
+//!     .size(2, 3)?
+//!     .irep(ImageRepresentation::MONO)?
+//!     .band("M", "")?
+//!     .build()?;
+//! let nitf = NitfBuilder::new(header)
+//!     .image(image, vec![0u8, 1, 2, 3, 4, 5])
+//!     .build()?;
+//!
+//! let mut out = vec![];
+//! nitf.write_to(&mut out)?;
+//! assert_eq!(nitf.nitf_header.meta.fl.val, out.len() as u64);
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::geo::LatLon;
+use crate::headers::data_extension_hdr::OverflowedHeaderType;
+use crate::headers::graphic_hdr::Color;
+use crate::headers::image_hdr::{
+    Band, Compression, CoordinateRepresentation, ImageRepresentation, Mode, PixelJustification,
+    PixelValueType,
+};
+use crate::headers::text_hdr::TextFormat;
+use crate::headers::{
+    DataExtensionHeader, GraphicHeader, ImageHeader, NitfHeader, ReservedExtensionHeader,
+    TextHeader,
+};
+use crate::segments::{NitfSegment, SegmentData};
+use crate::types::{zero_fill, Classification, ExtendedSubheader, FieldValue, NitfField, Security};
+use crate::{Nitf, NitfError, NitfResult};
+
+/// Largest block dimension which can be written, larger single blocks use 0
+const MAX_BLOCK_SIZE: u32 = 8192;
+
+/// Assemble a [Nitf] from a file header and segments
+#[derive(Debug)]
+pub struct NitfBuilder {
+    nitf: Nitf,
+}
+
+/// Build a [NitfHeader] with spec defaults
+#[derive(Debug)]
+pub struct NitfHeaderBuilder {
+    header: NitfHeader,
+}
+
+/// Build a [Security] block, unclassified and otherwise blank by default
+#[derive(Debug)]
+pub struct SecurityBuilder {
+    security: Security,
+}
+
+/// Build an [ImageHeader] with spec defaults
+///
+/// The number of bands and block geometry are filled in by
+/// [build()](ImageHeaderBuilder::build) if they are not set explicitly.
+#[derive(Debug)]
+pub struct ImageHeaderBuilder {
+    header: ImageHeader,
+    block_size: Option<(u32, u32)>,
+}
+
+/// Build a [GraphicHeader] with spec defaults
+#[derive(Debug)]
+pub struct GraphicHeaderBuilder {
+    header: GraphicHeader,
+}
+
+/// Build a [TextHeader] with spec defaults
+#[derive(Debug)]
+pub struct TextHeaderBuilder {
+    header: TextHeader,
+}
+
+/// Build a [DataExtensionHeader] with spec defaults
+#[derive(Debug)]
+pub struct DataExtensionHeaderBuilder {
+    header: DataExtensionHeader,
+}
+
+/// Build a [ReservedExtensionHeader] with spec defaults
+#[derive(Debug)]
+pub struct ReservedExtensionHeaderBuilder {
+    header: ReservedExtensionHeader,
+}
+
+// FUNCTIONS
+/// Current UTC time formatted as CCYYMMDDhhmmss
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Helper function for setting an extended subheader and its length/overflow fields
+fn set_extended<L>(
+    data: &[u8],
+    length: &mut NitfField<L>,
+    overflow: &mut NitfField<u16>,
+    subheader: &mut ExtendedSubheader,
+    names: [&str; 2],
+    length_width: u8,
+) -> NitfResult<()>
+where
+    L: FieldValue,
+    <L as FromStr>::Err: Debug,
+{
+    if data.is_empty() {
+        length.set_num(0, length_width, names[0])?;
+    } else {
+        // Length includes the 3 byte overflow field
+        length.set_num(data.len() + 3, length_width, names[0])?;
+        overflow.set_num(0, 3u8, names[1])?;
+    }
+    subheader.tre = data.to_vec();
+    subheader.size = data.len();
+    Ok(())
+}
+
+impl NitfBuilder {
+    pub fn new(header: NitfHeader) -> Self {
+        let mut nitf = Nitf::default();
+        nitf.nitf_header.meta = header;
+        Self { nitf }
+    }
+
+    /// Add an image segment
+    pub fn image(mut self, meta: ImageHeader, data: impl Into<SegmentData>) -> Self {
+        self.nitf.image_segments.push(segment(meta, data));
+        self
+    }
+
+    /// Add a graphic segment
+    pub fn graphic(mut self, meta: GraphicHeader, data: impl Into<SegmentData>) -> Self {
+        self.nitf.graphic_segments.push(segment(meta, data));
+        self
+    }
+
+    /// Add a text segment
+    pub fn text(mut self, meta: TextHeader, data: impl Into<SegmentData>) -> Self {
+        self.nitf.text_segments.push(segment(meta, data));
+        self
+    }
+
+    /// Add a data extension segment
+    pub fn data_extension(
+        mut self,
+        meta: DataExtensionHeader,
+        data: impl Into<SegmentData>,
+    ) -> Self {
+        self.nitf.data_extension_segments.push(segment(meta, data));
+        self
+    }
+
+    /// Add a reserved extension segment
+    pub fn reserved_extension(
+        mut self,
+        meta: ReservedExtensionHeader,
+        data: impl Into<SegmentData>,
+    ) -> Self {
+        self.nitf
+            .reserved_extension_segments
+            .push(segment(meta, data));
+        self
+    }
+
+    /// Compute the file header lengths and segment offsets
+    ///
+    /// CLEVEL is raised to the level required by the segments, see [Nitf::update_clevel()].
+    pub fn build(mut self) -> NitfResult<Nitf> {
+        self.nitf.update_header()?;
+        self.nitf.update_clevel()?;
+        Ok(self.nitf)
+    }
+}
+
+/// Helper function for wrapping a header and its data into a segment
+fn segment<T: crate::headers::NitfSegmentHeader>(
+    meta: T,
+    data: impl Into<SegmentData>,
+) -> NitfSegment<T> {
+    let data = data.into();
+    NitfSegment {
+        meta,
+        data_size: data.len(),
+        data,
+        header_offset: 0,
+        header_size: 0,
+        data_offset: 0,
+        damaged: false,
+    }
+}
+
+impl NitfHeaderBuilder {
+    pub fn new() -> NitfResult<Self> {
+        let mut header = NitfHeader::default();
+        header.fhdr.set_string("NITF", 4u8, "FHDR")?;
+        header.fver.set_string("02.10", 5u8, "FVER")?;
+        header.clevel.set_num(3, 2u8, "CLEVEL")?;
+        header.stype.set_string("BF01", 4u8, "STYPE")?;
+        header.ostaid.set_string("", 10u8, "OSTAID")?;
+        header.fdt.set_string(&timestamp(), 14u8, "FDT")?;
+        header.ftitle.set_string("", 80u8, "FTITLE")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.fscop.set_num(0, 5u8, "FSCOP")?;
+        header.fscpys.set_num(0, 5u8, "FSCPYS")?;
+        header.encryp.set_num(0, 1u8, "ENCRYP")?;
+        header.fbkgc = vec![NitfField::default(); 3];
+        for color in header.fbkgc.iter_mut() {
+            color.set_bytes(&[0], "FBKGC");
+        }
+        header.oname.set_string("", 24u8, "ONAME")?;
+        header.ophone.set_string("", 18u8, "OPHONE")?;
+        header.udhdl.set_num(0, 5u8, "UDHDL")?;
+        header.xhdl.set_num(0, 5u8, "XHDL")?;
+        // Counts, length tables, HL and FL
+        header.set_segment_sizes(&[], &[], &[], &[], &[], &[])?;
+        Ok(Self { header })
+    }
+
+    /// Complexity Level (CLEVEL)
+    pub fn clevel(mut self, clevel: u8) -> NitfResult<Self> {
+        self.header.clevel.set_num(clevel, 2u8, "CLEVEL")?;
+        Ok(self)
+    }
+
+    /// Originating Station ID (OSTAID)
+    pub fn ostaid(mut self, ostaid: &str) -> NitfResult<Self> {
+        self.header.ostaid.set_string(ostaid, 10u8, "OSTAID")?;
+        Ok(self)
+    }
+
+    /// File Date and Time (FDT), formatted as CCYYMMDDhhmmss
+    pub fn fdt(mut self, fdt: &str) -> NitfResult<Self> {
+        self.header.fdt.set_string(fdt, 14u8, "FDT")?;
+        Ok(self)
+    }
+
+    /// File Title (FTITLE)
+    pub fn ftitle(mut self, ftitle: &str) -> NitfResult<Self> {
+        self.header.ftitle.set_string(ftitle, 80u8, "FTITLE")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// File Copy Number (FSCOP) and File Number of Copies (FSCPYS)
+    pub fn copies(mut self, fscop: u32, fscpys: u32) -> NitfResult<Self> {
+        self.header.fscop.set_num(fscop, 5u8, "FSCOP")?;
+        self.header.fscpys.set_num(fscpys, 5u8, "FSCPYS")?;
+        Ok(self)
+    }
+
+    /// File Background Color (FBKGC)
+    pub fn fbkgc(mut self, red: u8, green: u8, blue: u8) -> Self {
+        for (color, value) in self.header.fbkgc.iter_mut().zip([red, green, blue]) {
+            color.set_bytes(&[value], "FBKGC");
+        }
+        self
+    }
+
+    /// Originator's Name (ONAME)
+    pub fn oname(mut self, oname: &str) -> NitfResult<Self> {
+        self.header.oname.set_string(oname, 24u8, "ONAME")?;
+        Ok(self)
+    }
+
+    /// Originator's Phone Number (OPHONE)
+    pub fn ophone(mut self, ophone: &str) -> NitfResult<Self> {
+        self.header.ophone.set_string(ophone, 18u8, "OPHONE")?;
+        Ok(self)
+    }
+
+    /// User Defined Header Data (UDHD)
+    pub fn udhd(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.udhdl,
+            &mut hdr.udhofl,
+            &mut hdr.udhd,
+            ["UDHDL", "UDHOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    /// Extended Header Data (XHD)
+    pub fn xhd(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.xhdl,
+            &mut hdr.xhdlofl,
+            &mut hdr.xhd,
+            ["XHDL", "XHDLOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> NitfHeader {
+        self.header
+    }
+}
+
+impl SecurityBuilder {
+    pub fn new() -> NitfResult<Self> {
+        let mut security = Security::default();
+        security.clas.set_string("U", 1u8, "CLAS")?;
+        security.clsy.set_string("", 2u8, "CLSY")?;
+        security.code.set_string("", 11u8, "CODE")?;
+        security.ctlh.set_string("", 2u8, "CTLH")?;
+        security.rel.set_string("", 20u8, "REL")?;
+        security.dctp.set_string("", 2u8, "DCTP")?;
+        security.dcdt.set_string("", 8u8, "DCDT")?;
+        security.dcxm.set_string("", 4u8, "DCXM")?;
+        security.dg.set_string("", 1u8, "DG")?;
+        security.dgdt.set_string("", 8u8, "DGDT")?;
+        security.cltx.set_string("", 43u8, "CLTX")?;
+        security.catp.set_string("", 1u8, "CATP")?;
+        security.caut.set_string("", 40u8, "CAUT")?;
+        security.crsn.set_string("", 1u8, "CRSN")?;
+        security.srdt.set_string("", 8u8, "SRDT")?;
+        security.ctln.set_string("", 15u8, "CTLN")?;
+        Ok(Self { security })
+    }
+
+    /// Security Classification (CLAS)
+    pub fn clas(mut self, clas: Classification) -> NitfResult<Self> {
+        self.security
+            .clas
+            .set_string(&clas.to_string(), 1u8, "CLAS")?;
+        Ok(self)
+    }
+
+    /// Classification Security System (CLSY)
+    pub fn clsy(mut self, clsy: &str) -> NitfResult<Self> {
+        self.security.clsy.set_string(clsy, 2u8, "CLSY")?;
+        Ok(self)
+    }
+
+    /// Codewords (CODE)
+    pub fn code(mut self, code: &str) -> NitfResult<Self> {
+        self.security.code.set_string(code, 11u8, "CODE")?;
+        Ok(self)
+    }
+
+    /// Control and Handling (CTLH)
+    pub fn ctlh(mut self, ctlh: &str) -> NitfResult<Self> {
+        self.security.ctlh.set_string(ctlh, 2u8, "CTLH")?;
+        Ok(self)
+    }
+
+    /// Releasing Instructions (REL)
+    pub fn rel(mut self, rel: &str) -> NitfResult<Self> {
+        self.security.rel.set_string(rel, 20u8, "REL")?;
+        Ok(self)
+    }
+
+    /// Declassification Type (DCTP) and Date (DCDT)
+    pub fn declassification(mut self, dctp: &str, dcdt: &str) -> NitfResult<Self> {
+        self.security.dctp.set_string(dctp, 2u8, "DCTP")?;
+        self.security.dcdt.set_string(dcdt, 8u8, "DCDT")?;
+        Ok(self)
+    }
+
+    /// Declassification Exemption (DCXM)
+    pub fn dcxm(mut self, dcxm: &str) -> NitfResult<Self> {
+        self.security.dcxm.set_string(dcxm, 4u8, "DCXM")?;
+        Ok(self)
+    }
+
+    /// Downgrade (DG) and Downgrade Date (DGDT)
+    pub fn downgrade(mut self, dg: &str, dgdt: &str) -> NitfResult<Self> {
+        self.security.dg.set_string(dg, 1u8, "DG")?;
+        self.security.dgdt.set_string(dgdt, 8u8, "DGDT")?;
+        Ok(self)
+    }
+
+    /// Classification Text (CLTX)
+    pub fn cltx(mut self, cltx: &str) -> NitfResult<Self> {
+        self.security.cltx.set_string(cltx, 43u8, "CLTX")?;
+        Ok(self)
+    }
+
+    /// Classification Authority Type (CATP) and Authority (CAUT)
+    pub fn authority(mut self, catp: &str, caut: &str) -> NitfResult<Self> {
+        self.security.catp.set_string(catp, 1u8, "CATP")?;
+        self.security.caut.set_string(caut, 40u8, "CAUT")?;
+        Ok(self)
+    }
+
+    /// Classification Reason (CRSN)
+    pub fn crsn(mut self, crsn: &str) -> NitfResult<Self> {
+        self.security.crsn.set_string(crsn, 1u8, "CRSN")?;
+        Ok(self)
+    }
+
+    /// Security Source Date (SRDT)
+    pub fn srdt(mut self, srdt: &str) -> NitfResult<Self> {
+        self.security.srdt.set_string(srdt, 8u8, "SRDT")?;
+        Ok(self)
+    }
+
+    /// Security Control Number (CTLN)
+    pub fn ctln(mut self, ctln: &str) -> NitfResult<Self> {
+        self.security.ctln.set_string(ctln, 15u8, "CTLN")?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Security {
+        self.security
+    }
+}
+
+impl ImageHeaderBuilder {
+    pub fn new() -> NitfResult<Self> {
+        let mut header = ImageHeader::default();
+        header.im.set_string("IM", 2u8, "IM")?;
+        header.iid1.set_string("", 10u8, "IID1")?;
+        header.idatim.set_string(&timestamp(), 14u8, "IDATIM")?;
+        header.tgtid.set_string("", 17u8, "TGTID")?;
+        header.iid2.set_string("", 80u8, "IID2")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.encryp.set_num(0, 1u8, "ENCRYP")?;
+        header.isorce.set_string("", 42u8, "ISORCE")?;
+        header.nrows.set_num(0, 8u8, "NROWS")?;
+        header.ncols.set_num(0, 8u8, "NCOLS")?;
+        header.pvtype.set_string("INT", 3u8, "PVTYPE")?;
+        header.irep.set_string("MONO", 8u8, "IREP")?;
+        header.icat.set_string("VIS", 8u8, "ICAT")?;
+        header.abpp.set_num(8, 2u8, "ABPP")?;
+        header.pjust.set_string("R", 1u8, "PJUST")?;
+        header.icords.set_string("", 1u8, "ICORDS")?;
+        header.nicom.set_num(0, 1u8, "NICOM")?;
+        header.ic.set_string("NC", 2u8, "IC")?;
+        header.isync.set_num(0, 1u8, "ISYNC")?;
+        header.imode.set_string("B", 1u8, "IMODE")?;
+        header.nbpp.set_num(8, 2u8, "NBPP")?;
+        header.idlvl.set_num(1, 3u8, "IDLVL")?;
+        header.ialvl.set_num(0, 3u8, "IALVL")?;
+        header.iloc.set_num(0, 10u8, "ILOC")?;
+        header.imag.set_string("1.0", 4u8, "IMAG")?;
+        header.udidl.set_num(0, 5u8, "UDIDL")?;
+        header.ixshdl.set_num(0, 5u8, "IXSHDL")?;
+        Ok(Self {
+            header,
+            block_size: None,
+        })
+    }
+
+    /// Image Identifier 1 (IID1)
+    pub fn iid1(mut self, iid1: &str) -> NitfResult<Self> {
+        self.header.iid1.set_string(iid1, 10u8, "IID1")?;
+        Ok(self)
+    }
+
+    /// Image Date and Time (IDATIM), formatted as CCYYMMDDhhmmss
+    pub fn idatim(mut self, idatim: &str) -> NitfResult<Self> {
+        self.header.idatim.set_string(idatim, 14u8, "IDATIM")?;
+        Ok(self)
+    }
+
+    /// Target Identifier (TGTID)
+    pub fn tgtid(mut self, tgtid: &str) -> NitfResult<Self> {
+        self.header.tgtid.set_string(tgtid, 17u8, "TGTID")?;
+        Ok(self)
+    }
+
+    /// Image Identifier 2 (IID2)
+    pub fn iid2(mut self, iid2: &str) -> NitfResult<Self> {
+        self.header.iid2.set_string(iid2, 80u8, "IID2")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// Image Source (ISORCE)
+    pub fn isorce(mut self, isorce: &str) -> NitfResult<Self> {
+        self.header.isorce.set_string(isorce, 42u8, "ISORCE")?;
+        Ok(self)
+    }
+
+    /// Number of significant rows (NROWS) and columns (NCOLS)
+    pub fn size(mut self, nrows: u32, ncols: u32) -> NitfResult<Self> {
+        self.header.nrows.set_num(nrows, 8u8, "NROWS")?;
+        self.header.ncols.set_num(ncols, 8u8, "NCOLS")?;
+        Ok(self)
+    }
+
+    /// Pixel Value Type (PVTYPE)
+    pub fn pvtype(mut self, pvtype: PixelValueType) -> NitfResult<Self> {
+        self.header
+            .pvtype
+            .set_string(&pvtype.to_string(), 3u8, "PVTYPE")?;
+        Ok(self)
+    }
+
+    /// Image Representation (IREP)
+    pub fn irep(mut self, irep: ImageRepresentation) -> NitfResult<Self> {
+        self.header
+            .irep
+            .set_string(&irep.to_string(), 8u8, "IREP")?;
+        Ok(self)
+    }
+
+    /// Image Category (ICAT)
+    pub fn icat(mut self, icat: &str) -> NitfResult<Self> {
+        self.header.icat.set_string(icat, 8u8, "ICAT")?;
+        Ok(self)
+    }
+
+    /// Number of Bits Per Pixel (NBPP), also used as the Actual Bits-Per-Pixel (ABPP)
+    pub fn nbpp(mut self, nbpp: u8) -> NitfResult<Self> {
+        self.header.nbpp.set_num(nbpp, 2u8, "NBPP")?;
+        self.header.abpp.set_num(nbpp, 2u8, "ABPP")?;
+        Ok(self)
+    }
+
+    /// Actual Bits-Per-Pixel (ABPP) and Pixel Justification (PJUST)
+    pub fn abpp(mut self, abpp: u8, pjust: PixelJustification) -> NitfResult<Self> {
+        self.header.abpp.set_num(abpp, 2u8, "ABPP")?;
+        self.header
+            .pjust
+            .set_string(&pjust.to_string(), 1u8, "PJUST")?;
+        Ok(self)
+    }
+
+    /// Image Coordinate Representation (ICORDS) and Geographic Location (IGEOLO)
+    pub fn igeolo(
+        mut self,
+        icords: CoordinateRepresentation,
+        igeolo: [&str; 4],
+    ) -> NitfResult<Self> {
+        self.header
+            .icords
+            .set_string(&icords.to_string(), 1u8, "ICORDS")?;
+        self.header.igeolo.clear();
+        if icords != CoordinateRepresentation::DEFAULT {
+            for corner in igeolo {
+                let mut geoloc = NitfField::default();
+                geoloc.set_string(corner, 15u8, "IGEOLO")?;
+                self.header.igeolo.push(geoloc);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Image Coordinate Representation (ICORDS) and corners encoded into IGEOLO
+    ///
+    /// See [geo](crate::geo) for the supported representations.
+    pub fn corners(
+        mut self,
+        icords: CoordinateRepresentation,
+        corners: &[LatLon; 4],
+    ) -> NitfResult<Self> {
+        self.header.set_corners(icords, corners)?;
+        Ok(self)
+    }
+
+    /// Add an Image Comment (ICOM)
+    pub fn comment(mut self, icom: &str) -> NitfResult<Self> {
+        let mut comment = NitfField::default();
+        comment.set_string(icom, 80u8, "ICOM")?;
+        self.header.icoms.push(comment);
+        self.header
+            .nicom
+            .set_num(self.header.icoms.len(), 1u8, "NICOM")?;
+        Ok(self)
+    }
+
+    /// Image Compression (IC) and Compression Rate Code (COMRAT)
+    ///
+    /// `comrat` is ignored for uncompressed images
+    pub fn ic(mut self, ic: Compression, comrat: &str) -> NitfResult<Self> {
+        self.header.ic.set_string(&ic.to_string(), 2u8, "IC")?;
+        self.header.comrat.set_string(comrat, 4u8, "COMRAT")?;
+        Ok(self)
+    }
+
+    /// Add a band with the given Band Representation (IREPBAND) and Subcategory (ISUBCAT)
+    pub fn band(mut self, irepband: &str, isubcat: &str) -> NitfResult<Self> {
+        let mut band = Band::default();
+        band.irepband.set_string(irepband, 2u8, "IREPBAND")?;
+        band.isubcat.set_string(isubcat, 6u8, "ISUBCAT")?;
+        band.ifc.set_string("N", 1u8, "IFC")?;
+        band.imflt.set_string("", 3u8, "IMFLT")?;
+        band.nluts.set_num(0, 1u8, "NLUTS")?;
+        self.header.bands.push(band);
+        Ok(self)
+    }
+
+    /// Look-Up-Tables (LUTD) of the most recently added band
+    ///
+    /// All tables must have the same number of entries (NELUT), up to 65536.
+    pub fn luts(mut self, luts: &[&[u8]]) -> NitfResult<Self> {
+        let nelut = luts.first().map_or(0, |lut| lut.len());
+        if luts.len() > 4 {
+            return Err(NitfError::InvalidValue(
+                "NLUTS".to_string(),
+                luts.len().to_string(),
+            ));
+        }
+        if let Some(lut) = luts.iter().find(|lut| lut.len() != nelut) {
+            return Err(NitfError::InvalidValue(
+                "NELUT".to_string(),
+                format!("{nelut}/{}", lut.len()),
+            ));
+        }
+        if !luts.is_empty() && !(1..=65536).contains(&nelut) {
+            return Err(NitfError::InvalidValue(
+                "NELUT".to_string(),
+                nelut.to_string(),
+            ));
+        }
+        let Some(band) = self.header.bands.last_mut() else {
+            return Err(NitfError::Fatal("LUTD without a band".to_string()));
+        };
+        band.nluts.set_num(luts.len(), 1u8, "NLUTS")?;
+        band.nelut.set_num(nelut, 5u8, "NELUT")?;
+        band.lutd = luts.iter().map(|lut| lut.to_vec()).collect();
+        Ok(self)
+    }
+
+    /// Number of bands added so far
+    pub(crate) fn n_bands(&self) -> usize {
+        self.header.bands.len()
+    }
+
+    /// Image Mode (IMODE)
+    pub fn imode(mut self, imode: Mode) -> NitfResult<Self> {
+        self.header
+            .imode
+            .set_string(&imode.to_string(), 1u8, "IMODE")?;
+        Ok(self)
+    }
+
+    /// Number of Pixels Per Block Horizontal (NPPBH) and Vertical (NPPBV)
+    ///
+    /// By default the image is written as a single block
+    pub fn block_size(mut self, nppbh: u32, nppbv: u32) -> NitfResult<Self> {
+        if nppbh == 0 || nppbv == 0 || nppbh > MAX_BLOCK_SIZE || nppbv > MAX_BLOCK_SIZE {
+            return Err(NitfError::InvalidValue(
+                "NPPBH/NPPBV".to_string(),
+                format!("{nppbh}/{nppbv}"),
+            ));
+        }
+        self.block_size = Some((nppbh, nppbv));
+        Ok(self)
+    }
+
+    /// Image Display Level (IDLVL) and Attachment Level (IALVL)
+    pub fn levels(mut self, idlvl: u16, ialvl: u16) -> NitfResult<Self> {
+        self.header.idlvl.set_num(idlvl, 3u8, "IDLVL")?;
+        self.header.ialvl.set_num(ialvl, 3u8, "IALVL")?;
+        Ok(self)
+    }
+
+    /// Image Location (ILOC), relative to the attachment level
+    pub fn iloc(mut self, row: i32, col: i32) -> NitfResult<Self> {
+        let location = format!("{}{}", zero_fill(row, 5), zero_fill(col, 5));
+        self.header.iloc.set_string(&location, 10u8, "ILOC")?;
+        Ok(self)
+    }
+
+    /// Image Magnification (IMAG)
+    pub fn imag(mut self, imag: &str) -> NitfResult<Self> {
+        self.header.imag.set_string(imag, 4u8, "IMAG")?;
+        Ok(self)
+    }
+
+    /// User Defined Image Data (UDID)
+    pub fn udid(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.udidl,
+            &mut hdr.udofl,
+            &mut hdr.udid,
+            ["UDIDL", "UDOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    /// Image Extended Subheader Data (IXSHD)
+    pub fn ixshd(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.ixshdl,
+            &mut hdr.ixsofl,
+            &mut hdr.ixshd,
+            ["IXSHDL", "IXSOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    /// Fill in the band count and block geometry
+    pub fn build(mut self) -> NitfResult<ImageHeader> {
+        if self.header.bands.is_empty() {
+            self = self.band("", "")?;
+        }
+        let hdr = &mut self.header;
+        let n_bands = hdr.bands.len();
+        if n_bands < 10 {
+            hdr.nbands.set_num(n_bands, 1u8, "NBANDS")?;
+        } else {
+            hdr.nbands.set_num(0, 1u8, "NBANDS")?;
+            hdr.xbands.set_num(n_bands, 5u8, "XBANDS")?;
+        }
+
+        let (nrows, ncols) = (hdr.nrows.val, hdr.ncols.val);
+        let (nppbh, nppbv) = self.block_size.unwrap_or((ncols, nrows));
+        let nbpr = ncols.div_ceil(nppbh.max(1)).max(1);
+        let nbpc = nrows.div_ceil(nppbv.max(1)).max(1);
+        // A single block larger than the maximum block size is written as 0
+        let nppbh = if nppbh > MAX_BLOCK_SIZE { 0 } else { nppbh };
+        let nppbv = if nppbv > MAX_BLOCK_SIZE { 0 } else { nppbv };
+        hdr.nbpr.set_num(nbpr, 4u8, "NBPR")?;
+        hdr.nbpc.set_num(nbpc, 4u8, "NBPC")?;
+        hdr.nppbh.set_num(nppbh, 4u8, "NPPBH")?;
+        hdr.nppbv.set_num(nppbv, 4u8, "NPPBV")?;
+        Ok(self.header)
+    }
+}
+
+impl GraphicHeaderBuilder {
+    pub fn new() -> NitfResult<Self> {
+        let mut header = GraphicHeader::default();
+        header.sy.set_string("SY", 2u8, "SY")?;
+        header.sid.set_string("", 10u8, "SID")?;
+        header.sname.set_string("", 20u8, "SNAME")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.encryp.set_num(0, 1u8, "ENCRYP")?;
+        header.sfmt.set_string("C", 1u8, "SFMT")?;
+        header.sstruct.set_num(0, 13u8, "SSTRUCT")?;
+        header.sdlvl.set_num(1, 3u8, "SDLVL")?;
+        header.salvl.set_num(0, 3u8, "SALVL")?;
+        header.sloc.set_num(0, 10u8, "SLOC")?;
+        header.sbnd1.set_num(0, 10u8, "SBND1")?;
+        header.scolor.set_string("C", 1u8, "SCOLOR")?;
+        header.sbnd2.set_num(0, 10u8, "SBND2")?;
+        header.sres2.set_num(0, 2u8, "SRES2")?;
+        header.sxshdl.set_num(0, 5u8, "SXSHDL")?;
+        Ok(Self { header })
+    }
+
+    /// Graphic Identifier (SID)
+    pub fn sid(mut self, sid: &str) -> NitfResult<Self> {
+        self.header.sid.set_string(sid, 10u8, "SID")?;
+        Ok(self)
+    }
+
+    /// Graphic Name (SNAME)
+    pub fn sname(mut self, sname: &str) -> NitfResult<Self> {
+        self.header.sname.set_string(sname, 20u8, "SNAME")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// Graphic Display Level (SDLVL) and Attachment Level (SALVL)
+    pub fn levels(mut self, sdlvl: u16, salvl: u16) -> NitfResult<Self> {
+        self.header.sdlvl.set_num(sdlvl, 3u8, "SDLVL")?;
+        self.header.salvl.set_num(salvl, 3u8, "SALVL")?;
+        Ok(self)
+    }
+
+    /// Graphic Location (SLOC), formatted as rrrrrccccc
+    pub fn sloc(mut self, sloc: &str) -> NitfResult<Self> {
+        self.header.sloc.set_string(sloc, 10u8, "SLOC")?;
+        Ok(self)
+    }
+
+    /// First (SBND1) and Second (SBND2) Graphic Bound Locations, formatted as rrrrrccccc
+    pub fn bounds(mut self, sbnd1: &str, sbnd2: &str) -> NitfResult<Self> {
+        self.header.sbnd1.set_string(sbnd1, 10u8, "SBND1")?;
+        self.header.sbnd2.set_string(sbnd2, 10u8, "SBND2")?;
+        Ok(self)
+    }
+
+    /// Graphic Color (SCOLOR)
+    pub fn scolor(mut self, scolor: Color) -> NitfResult<Self> {
+        self.header
+            .scolor
+            .set_string(&scolor.to_string(), 1u8, "SCOLOR")?;
+        Ok(self)
+    }
+
+    /// Graphic Extended Subheader Data (SXSHD)
+    pub fn sxshd(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.sxshdl,
+            &mut hdr.sxsofl,
+            &mut hdr.sxshd,
+            ["SXSHDL", "SXSOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> GraphicHeader {
+        self.header
+    }
+}
+
+impl TextHeaderBuilder {
+    pub fn new() -> NitfResult<Self> {
+        let mut header = TextHeader::default();
+        header.te.set_string("TE", 2u8, "TE")?;
+        header.textid.set_string("", 7u8, "TEXTID")?;
+        header.txtalvl.set_num(0, 3u8, "TXTALVL")?;
+        header.txtdt.set_string(&timestamp(), 14u8, "TXTDT")?;
+        header.txttitl.set_string("", 80u8, "TXTTITL")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.encryp.set_num(0, 1u8, "ENCRYP")?;
+        header.txtfmt.set_string("STA", 3u8, "TXTFMT")?;
+        header.txshdl.set_num(0, 5u8, "TXSHDL")?;
+        Ok(Self { header })
+    }
+
+    /// Text Identifier (TEXTID)
+    pub fn textid(mut self, textid: &str) -> NitfResult<Self> {
+        self.header.textid.set_string(textid, 7u8, "TEXTID")?;
+        Ok(self)
+    }
+
+    /// Text Attachment Level (TXTALVL)
+    pub fn txtalvl(mut self, txtalvl: u16) -> NitfResult<Self> {
+        self.header.txtalvl.set_num(txtalvl, 3u8, "TXTALVL")?;
+        Ok(self)
+    }
+
+    /// Text Date and Time (TXTDT), formatted as CCYYMMDDhhmmss
+    pub fn txtdt(mut self, txtdt: &str) -> NitfResult<Self> {
+        self.header.txtdt.set_string(txtdt, 14u8, "TXTDT")?;
+        Ok(self)
+    }
+
+    /// Text Title (TXTTITL)
+    pub fn txttitl(mut self, txttitl: &str) -> NitfResult<Self> {
+        self.header.txttitl.set_string(txttitl, 80u8, "TXTTITL")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// Text Format (TXTFMT)
+    pub fn txtfmt(mut self, txtfmt: TextFormat) -> NitfResult<Self> {
+        self.header
+            .txtfmt
+            .set_string(&txtfmt.to_string(), 3u8, "TXTFMT")?;
+        Ok(self)
+    }
+
+    /// Text Extended Subheader Data (TXSHD)
+    pub fn txshd(mut self, data: &[u8]) -> NitfResult<Self> {
+        let hdr = &mut self.header;
+        set_extended(
+            data,
+            &mut hdr.txshdl,
+            &mut hdr.txsofl,
+            &mut hdr.txshd,
+            ["TXSHDL", "TXSOFL"],
+            5,
+        )?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> TextHeader {
+        self.header
+    }
+}
+
+impl DataExtensionHeaderBuilder {
+    pub fn new(desid: &str) -> NitfResult<Self> {
+        let mut header = DataExtensionHeader::default();
+        header.de.set_string("DE", 2u8, "DE")?;
+        header.desid.set_string(desid, 25u8, "DESID")?;
+        header.desver.set_num(1, 2u8, "DESVER")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.desshl.set_num(0, 4u8, "DESSHL")?;
+        Ok(Self { header })
+    }
+
+    /// Version of the Data Definition (DESVER)
+    pub fn desver(mut self, desver: u8) -> NitfResult<Self> {
+        self.header.desver.set_num(desver, 2u8, "DESVER")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// Overflowed Header Type (DESOFLW) and Data Item Overflowed (DESITEM)
+    ///
+    /// Only written when DESID is `TRE_OVERFLOW`
+    pub fn overflow(mut self, desoflw: OverflowedHeaderType, desitem: u16) -> NitfResult<Self> {
+        self.header
+            .desoflw
+            .set_string(&desoflw.to_string(), 6u8, "DESOFLW")?;
+        self.header.desitem.set_num(desitem, 3u8, "DESITEM")?;
+        Ok(self)
+    }
+
+    /// User-defined Subheader Fields (DESSHF)
+    pub fn desshf(mut self, data: &[u8]) -> NitfResult<Self> {
+        self.header.desshl.set_num(data.len(), 4u8, "DESSHL")?;
+        self.header.desshf.tre = data.to_vec();
+        self.header.desshf.size = data.len();
+        Ok(self)
+    }
+
+    pub fn build(self) -> DataExtensionHeader {
+        self.header
+    }
+}
+
+impl ReservedExtensionHeaderBuilder {
+    pub fn new(resid: &str) -> NitfResult<Self> {
+        let mut header = ReservedExtensionHeader::default();
+        header.re.set_string("RE", 2u8, "RE")?;
+        header.resid.set_string(resid, 25u8, "RESID")?;
+        header.resver.set_num(1, 2u8, "RESVER")?;
+        header.security = SecurityBuilder::new()?.build();
+        header.resshl.set_num(0, 4u8, "RESSHL")?;
+        Ok(Self { header })
+    }
+
+    /// Version of the Data Definition (RESVER)
+    pub fn resver(mut self, resver: u8) -> NitfResult<Self> {
+        self.header.resver.set_num(resver, 2u8, "RESVER")?;
+        Ok(self)
+    }
+
+    /// Security information
+    pub fn security(mut self, security: Security) -> Self {
+        self.header.security = security;
+        self
+    }
+
+    /// User-defined Subheader Fields (RESSHF)
+    pub fn resshf(mut self, data: &[u8]) -> NitfResult<Self> {
+        self.header.resshl.set_num(data.len(), 4u8, "RESSHL")?;
+        self.header.resshf.tre = data.to_vec();
+        self.header.resshf.size = data.len();
+        Ok(self)
+    }
+
+    pub fn build(self) -> ReservedExtensionHeader {
+        self.header
+    }
+}
@@ -0,0 +1,216 @@
+//! Complexity level (CLEVEL) of a file
+//!
+//! The complexity level is the lowest of 03, 05, 06, 07 and 09 whose limits, from
+//! MIL-STD-2500C table A-10, the file stays within. The limits checked are those
+//! of the file size, the extent of the common coordinate system (CCS), the image
+//! and block sizes, the number of bands and the number of image segments and
+//! display levels.
+//! ```
+//! use nitf_rs::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+//! use nitf_rs::clevel::Criterion;
+//! use nitf_rs::image::ImageEncoder;
+//!
+//! // A 3000 column image needs CLEVEL 05, which the builder fills in
+//! let pixels = vec![0u8; 3000];
+//! let (header, data) = ImageEncoder::new(1, 3000, &[&pixels])?
+//!     .encode(ImageHeaderBuilder::new()?.iid1("WIDE")?)?;
+//! let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.clevel(3)?.build())
+//!     .image(header, data)
+//!     .build()?;
+//! assert_eq!(nitf.nitf_header.meta.clevel.val, 5);
+//!
+//! let requirement = nitf.required_clevel()?;
+//! assert_eq!(requirement.criterion, Criterion::CcsExtent);
+//! println!("{requirement}");
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use std::fmt::Display;
+
+use crate::{Nitf, NitfResult};
+
+/// Complexity levels, in increasing order
+pub const LEVELS: [u8; 5] = [3, 5, 6, 7, 9];
+
+/// Largest CCS extent, in rows or columns, of each level below 09
+const CCS_EXTENT: [u64; 4] = [2048, 8192, 65536, 99_999_999];
+/// Largest file size, in bytes, of each level below 09
+const FILE_SIZE: [u64; 4] = [52_428_799, 1_073_741_823, 2_147_483_647, 10_737_418_239];
+/// Largest number of rows or columns of an image of each level below 09
+const IMAGE_SIZE: [u64; 4] = [2048, 8192, 65536, 99_999_999];
+/// Largest NPPBH or NPPBV of each level below 09
+const BLOCK_SIZE: [u64; 4] = [2048, 8192, 8192, 8192];
+/// Largest number of bands of an image of each level below 09
+const BANDS: [u64; 4] = [9, 255, 999, 999];
+/// Largest number of image segments of each level below 09
+const IMAGE_SEGMENTS: [u64; 4] = [20, 100, 100, 100];
+/// Largest number of display levels of each level below 09
+const DISPLAY_LEVELS: [u64; 4] = [32, 64, 99, 128];
+
+/// Limit which determines a complexity level
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Criterion {
+    /// Rows or columns of the CCS covered by images and graphics
+    CcsExtent,
+    /// File length (FL)
+    FileSize,
+    /// Rows or columns of an image
+    ImageSize,
+    /// Rows or columns of an image block, where a single block over 8192 is 09
+    BlockSize,
+    /// Bands of an image
+    Bands,
+    /// Number of image segments
+    ImageSegments,
+    /// Number of image, graphic and label display levels
+    DisplayLevels,
+}
+
+impl Display for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::CcsExtent => "CCS extent",
+            Self::FileSize => "file size",
+            Self::ImageSize => "image size",
+            Self::BlockSize => "block size",
+            Self::Bands => "number of bands",
+            Self::ImageSegments => "number of image segments",
+            Self::DisplayLevels => "number of display levels",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Complexity level required by a criterion
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Requirement {
+    /// Lowest complexity level allowing `value`
+    pub level: u8,
+    pub criterion: Criterion,
+    /// Value of the criterion in the file
+    pub value: u64,
+    /// Image segment (zero indexed) the value is from, for per-image criteria
+    pub segment: Option<usize>,
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CLEVEL {:02} for {} {}",
+            self.level, self.criterion, self.value
+        )?;
+        if let Some(segment) = self.segment {
+            write!(f, " of image segment {segment}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Requirement {
+    fn new(criterion: Criterion, value: u64, limits: &[u64; 4], segment: Option<usize>) -> Self {
+        let level = limits
+            .iter()
+            .zip(LEVELS)
+            .find(|(limit, _)| value <= **limit)
+            .map_or(9, |(_, level)| level);
+        Self {
+            level,
+            criterion,
+            value,
+            segment,
+        }
+    }
+}
+
+impl Nitf {
+    /// The complexity level required by each criterion, per image for the image criteria
+    ///
+    /// The file size is taken from FL, so the header should be up to date (see
+    /// [Nitf::update_header()]). Errors if the image or graphic positions cannot
+    /// be resolved, see [Nitf::image_positions()].
+    pub fn clevel_requirements(&self) -> NitfResult<Vec<Requirement>> {
+        use Criterion::*;
+        let header = &self.nitf_header.meta;
+        let mut requirements = vec![
+            Requirement::new(FileSize, header.fl.val, &FILE_SIZE, None),
+            Requirement::new(CcsExtent, self.ccs_extent()?, &CCS_EXTENT, None),
+        ];
+        for (index, segment) in self.image_segments.iter().enumerate() {
+            let meta = &segment.meta;
+            let size = meta.nrows.val.max(meta.ncols.val) as u64;
+            let (nppbh, nppbv) = (meta.nppbh.val as u64, meta.nppbv.val as u64);
+            // A block size of 0 is a single block of more than 8192 pixels
+            let block_size = match nppbh == 0 || nppbv == 0 {
+                true => u64::MAX,
+                false => nppbh.max(nppbv),
+            };
+            requirements.extend([
+                Requirement::new(ImageSize, size, &IMAGE_SIZE, Some(index)),
+                Requirement::new(BlockSize, block_size, &BLOCK_SIZE, Some(index)),
+                Requirement::new(Bands, meta.bands.len() as u64, &BANDS, Some(index)),
+            ]);
+        }
+        let n_images = self.image_segments.len() as u64;
+        let n_levels = n_images + (self.graphic_segments.len() + self.label_segments.len()) as u64;
+        requirements.extend([
+            Requirement::new(ImageSegments, n_images, &IMAGE_SEGMENTS, None),
+            Requirement::new(DisplayLevels, n_levels, &DISPLAY_LEVELS, None),
+        ]);
+        Ok(requirements)
+    }
+
+    /// The minimum complexity level of the file, and the first criterion requiring it
+    pub fn required_clevel(&self) -> NitfResult<Requirement> {
+        let requirements = self.clevel_requirements()?;
+        // The file size comes first and is always present
+        let highest = requirements[1..]
+            .iter()
+            .fold(requirements[0], |highest, req| {
+                match req.level > highest.level {
+                    true => *req,
+                    false => highest,
+                }
+            });
+        Ok(highest)
+    }
+
+    /// Raise CLEVEL to [required_clevel()](Self::required_clevel) if it is declared lower
+    ///
+    /// A declared level above the required one is left as is. Returns the level
+    /// of the file.
+    pub fn update_clevel(&mut self) -> NitfResult<u8> {
+        let required = self.required_clevel()?.level;
+        let clevel = &mut self.nitf_header.meta.clevel;
+        if clevel.val < required {
+            clevel.set_num(required, 2u8, "CLEVEL")?;
+        }
+        Ok(clevel.val)
+    }
+
+    /// Largest row or column, from the CCS origin, covered by an image or graphic
+    fn ccs_extent(&self) -> NitfResult<u64> {
+        let images = self
+            .image_positions()?
+            .into_iter()
+            .zip(&self.image_segments)
+            .map(|((row, col), segment)| {
+                let meta = &segment.meta;
+                (row + meta.nrows.val as i64, col + meta.ncols.val as i64)
+            });
+        let graphics = self
+            .graphic_positions()?
+            .into_iter()
+            .zip(&self.graphic_segments)
+            .map(|((row, col), segment)| {
+                // SBND2 is relative to the same origin as SLOC
+                let (location, bound) = (&segment.meta.sloc.val, &segment.meta.sbnd2.val);
+                let row = row - location.row as i64 + bound.row as i64;
+                (row, col - location.col as i64 + bound.col as i64)
+            });
+        Ok(images
+            .chain(graphics)
+            .map(|(row, col)| row.max(col).max(0) as u64)
+            .max()
+            .unwrap_or(0))
+    }
+}
@@ -0,0 +1,436 @@
+//! Image corner coordinates (ICORDS and IGEOLO)
+//!
+//! IGEOLO holds the four image corners, in the order (first row, first column),
+//! (first row, last column), (last row, last column), (last row, first column).
+//! Depending on ICORDS each corner is stored as
+//! - `G`: degrees, minutes and seconds, `ddmmssXdddmmssY`
+//! - `D`: decimal degrees, `±dd.ddd±ddd.ddd`
+//! - `N`/`S`: UTM zone, easting and northing, `zzeeeeeennnnnnn`, in the northern
+//!   or southern hemisphere
+//! - `U`: MGRS, `zzBJKeeeeennnnn`
+//!
+//! UTM and MGRS coordinates are converted using the WGS84 ellipsoid. Polar (UPS)
+//! coordinates are not supported.
+//! ```
+//! use nitf_rs::builder::ImageHeaderBuilder;
+//! use nitf_rs::geo::LatLon;
+//! use nitf_rs::headers::image_hdr::CoordinateRepresentation;
+//!
+//! let corners = [
+//!     LatLon::new(38.9, -77.1),
+//!     LatLon::new(38.9, -77.0),
+//!     LatLon::new(38.8, -77.0),
+//!     LatLon::new(38.8, -77.1),
+//! ];
+//! let header = ImageHeaderBuilder::new()?
+//!     .corners(CoordinateRepresentation::U, &corners)?
+//!     .build()?;
+//! assert_eq!(header.igeolo[0].string, "18SUJ1789207775");
+//! let decoded = header.corners()?.unwrap();
+//! assert!((decoded[0].lat - 38.9).abs() < 1e-4);
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use crate::headers::image_hdr::CoordinateRepresentation;
+use crate::headers::ImageHeader;
+use crate::types::NitfField;
+use crate::{NitfError, NitfResult};
+
+/// Geographic coordinate in decimal degrees
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// UTM coordinate
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Utm {
+    /// Zone number, 1 through 60
+    pub zone: u8,
+    /// Northern hemisphere
+    pub north: bool,
+    /// Easting in meters, including the 500,000 m false easting
+    pub easting: f64,
+    /// Northing in meters, including the 10,000,000 m false northing in the
+    /// southern hemisphere
+    pub northing: f64,
+}
+
+// WGS84 ellipsoid
+const A: f64 = 6_378_137.0;
+const F: f64 = 1.0 / 298.257_223_563;
+const K0: f64 = 0.9996;
+const FALSE_EASTING: f64 = 500_000.0;
+const FALSE_NORTHING: f64 = 10_000_000.0;
+
+/// MGRS latitude bands, 8 degrees each starting at 80S
+const BANDS: &[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+/// MGRS 100 km column letters, for zone sets 1 through 3
+const COLUMNS: [&[u8]; 3] = [b"ABCDEFGH", b"JKLMNPQR", b"STUVWXYZ"];
+/// MGRS 100 km row letters
+const ROWS: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+
+fn e2() -> f64 {
+    F * (2.0 - F)
+}
+
+/// Meridian arc length from the equator to latitude `phi` (radians)
+fn meridian_arc(phi: f64) -> f64 {
+    let e2 = e2();
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    A * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+        - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+        + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+        - (35.0 * e6 / 3072.0) * (6.0 * phi).sin())
+}
+
+fn central_meridian(zone: u8) -> f64 {
+    (zone as f64 - 1.0) * 6.0 - 180.0 + 3.0
+}
+
+impl LatLon {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// UTM zone containing the point, including the Norway and Svalbard exceptions
+    pub fn utm_zone(&self) -> u8 {
+        let lon = (self.lon + 180.0).rem_euclid(360.0) - 180.0;
+        let mut zone = (((lon + 180.0) / 6.0).floor() as u8 + 1).min(60);
+        if (56.0..64.0).contains(&self.lat) && (3.0..12.0).contains(&lon) {
+            zone = 32;
+        }
+        if (72.0..=84.0).contains(&self.lat) {
+            zone = match lon {
+                lon if (0.0..9.0).contains(&lon) => 31,
+                lon if (9.0..21.0).contains(&lon) => 33,
+                lon if (21.0..33.0).contains(&lon) => 35,
+                lon if (33.0..42.0).contains(&lon) => 37,
+                _ => zone,
+            };
+        }
+        zone
+    }
+
+    /// Project into the UTM zone containing the point
+    pub fn to_utm(&self) -> Utm {
+        self.to_utm_zone(self.utm_zone(), self.lat >= 0.0)
+    }
+
+    /// Project into a given UTM zone and hemisphere
+    pub fn to_utm_zone(&self, zone: u8, north: bool) -> Utm {
+        let e2 = e2();
+        let ep2 = e2 / (1.0 - e2);
+        let phi = self.lat.to_radians();
+        let lambda = (self.lon - central_meridian(zone)).to_radians();
+        let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+        let n = A / (1.0 - e2 * sin * sin).sqrt();
+        let t = tan * tan;
+        let c = ep2 * cos * cos;
+        let a = cos * lambda;
+        let easting = K0
+            * n
+            * (a + (1.0 - t + c) * a.powi(3) / 6.0
+                + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0)
+            + FALSE_EASTING;
+        let mut northing = K0
+            * (meridian_arc(phi)
+                + n * tan
+                    * (a * a / 2.0
+                        + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                        + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+        if !north {
+            northing += FALSE_NORTHING;
+        }
+        Utm {
+            zone,
+            north,
+            easting,
+            northing,
+        }
+    }
+
+    /// MGRS string with 1 m precision, `zzBJKeeeeennnnn`
+    pub fn to_mgrs(&self) -> NitfResult<String> {
+        if !(-80.0..=84.0).contains(&self.lat) {
+            return Err(NitfError::Unsupported(format!(
+                "MGRS in polar regions (latitude {})",
+                self.lat
+            )));
+        }
+        let band = BANDS[(((self.lat + 80.0) / 8.0).floor() as usize).min(BANDS.len() - 1)];
+        let utm = self.to_utm();
+        // Truncate rather than round, so the point lies within the MGRS square
+        let (easting, northing) = (utm.easting.floor() as i64, utm.northing.floor() as i64);
+        let set = (utm.zone as usize - 1) % 3;
+        let column = COLUMNS[set][(easting / 100_000 - 1).clamp(0, 7) as usize];
+        let row_offset = if utm.zone.is_multiple_of(2) { 5 } else { 0 };
+        let row = ROWS[((northing / 100_000 + row_offset) % 20) as usize];
+        Ok(format!(
+            "{:02}{}{}{}{:05}{:05}",
+            utm.zone,
+            band as char,
+            column as char,
+            row as char,
+            easting % 100_000,
+            northing % 100_000
+        ))
+    }
+
+    /// Parse an MGRS string of the form `zzBJKeeeeennnnn`
+    pub fn from_mgrs(mgrs: &str) -> NitfResult<Self> {
+        let invalid = || NitfError::InvalidValue("MGRS".to_string(), mgrs.to_string());
+        let bytes = mgrs.trim().as_bytes();
+        if bytes.len() != 15 {
+            return Err(invalid());
+        }
+        let zone: u8 = parse(&bytes[..2]).ok_or_else(invalid)?;
+        let band = bytes[2].to_ascii_uppercase();
+        let band_index = BANDS.iter().position(|b| *b == band).ok_or_else(invalid)?;
+        if !(1..=60).contains(&zone) {
+            return Err(invalid());
+        }
+        let set = (zone as usize - 1) % 3;
+        let column = COLUMNS[set]
+            .iter()
+            .position(|c| *c == bytes[3].to_ascii_uppercase())
+            .ok_or_else(invalid)?;
+        let row = ROWS
+            .iter()
+            .position(|r| *r == bytes[4].to_ascii_uppercase())
+            .ok_or_else(invalid)?;
+        let easting_digits: f64 = parse(&bytes[5..10]).ok_or_else(invalid)?;
+        let northing_digits: f64 = parse(&bytes[10..15]).ok_or_else(invalid)?;
+        let row_offset = if zone.is_multiple_of(2) { 5 } else { 0 };
+        let row = (row + 20 - row_offset) % 20;
+        let easting = (column as f64 + 1.0) * 100_000.0 + easting_digits;
+        let mut northing = row as f64 * 100_000.0 + northing_digits;
+        // Rows repeat every 2,000 km, the latitude band picks the repetition
+        let north = band >= b'N';
+        let band_south = -80.0 + 8.0 * band_index as f64;
+        let band_min = LatLon::new(band_south, central_meridian(zone))
+            .to_utm_zone(zone, north)
+            .northing;
+        // Allow for the band edge dipping into the previous 100 km row
+        while northing < band_min - 100_000.0 {
+            northing += 2_000_000.0;
+        }
+        Ok(Utm {
+            zone,
+            north,
+            easting,
+            northing,
+        }
+        .to_lat_lon())
+    }
+}
+
+impl Utm {
+    /// Geographic coordinate of the UTM point
+    pub fn to_lat_lon(&self) -> LatLon {
+        let e2 = e2();
+        let ep2 = e2 / (1.0 - e2);
+        let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+        let x = self.easting - FALSE_EASTING;
+        let y = match self.north {
+            true => self.northing,
+            false => self.northing - FALSE_NORTHING,
+        };
+        let m = y / K0;
+        let mu = m / (A * (1.0 - e2 / 4.0 - 3.0 * e2 * e2 / 64.0 - 5.0 * e2.powi(3) / 256.0));
+        let phi1 = mu
+            + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+            + (21.0 * e1 * e1 / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+            + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+            + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+        let (sin, cos, tan) = (phi1.sin(), phi1.cos(), phi1.tan());
+        let n1 = A / (1.0 - e2 * sin * sin).sqrt();
+        let t1 = tan * tan;
+        let c1 = ep2 * cos * cos;
+        let r1 = A * (1.0 - e2) / (1.0 - e2 * sin * sin).powf(1.5);
+        let d = x / (n1 * K0);
+        let lat = phi1
+            - (n1 * tan / r1)
+                * (d * d / 2.0
+                    - (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1 * c1 - 9.0 * ep2) * d.powi(4) / 24.0
+                    + (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1 * t1
+                        - 252.0 * ep2
+                        - 3.0 * c1 * c1)
+                        * d.powi(6)
+                        / 720.0);
+        let lon = (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0
+            + (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1 * c1 + 8.0 * ep2 + 24.0 * t1 * t1)
+                * d.powi(5)
+                / 120.0)
+            / cos;
+        LatLon::new(
+            lat.to_degrees(),
+            central_meridian(self.zone) + lon.to_degrees(),
+        )
+    }
+}
+
+fn parse<T: std::str::FromStr>(bytes: &[u8]) -> Option<T> {
+    std::str::from_utf8(bytes).ok()?.trim().parse().ok()
+}
+
+/// Decode one IGEOLO corner
+pub fn parse_corner(icords: &CoordinateRepresentation, corner: &str) -> NitfResult<LatLon> {
+    let invalid = || NitfError::InvalidValue("IGEOLO".to_string(), corner.to_string());
+    let bytes = corner.as_bytes();
+    if bytes.len() != 15 {
+        return Err(invalid());
+    }
+    match icords {
+        CoordinateRepresentation::G => {
+            let dms = |degrees: &[u8], hemisphere: u8, negative: u8| -> Option<f64> {
+                let n = degrees.len();
+                let d: f64 = parse(&degrees[..n - 4])?;
+                let m: f64 = parse(&degrees[n - 4..n - 2])?;
+                let s: f64 = parse(&degrees[n - 2..])?;
+                let value = d + m / 60.0 + s / 3600.0;
+                match hemisphere.to_ascii_uppercase() {
+                    h if h == negative => Some(-value),
+                    b'N' | b'E' => Some(value),
+                    _ => None,
+                }
+            };
+            let lat = dms(&bytes[..6], bytes[6], b'S').ok_or_else(invalid)?;
+            let lon = dms(&bytes[7..14], bytes[14], b'W').ok_or_else(invalid)?;
+            Ok(LatLon::new(lat, lon))
+        }
+        CoordinateRepresentation::D => {
+            let lat = parse(&bytes[..7]).ok_or_else(invalid)?;
+            let lon = parse(&bytes[7..]).ok_or_else(invalid)?;
+            Ok(LatLon::new(lat, lon))
+        }
+        CoordinateRepresentation::N | CoordinateRepresentation::S => {
+            let zone: u8 = parse(&bytes[..2]).ok_or_else(invalid)?;
+            if !(1..=60).contains(&zone) {
+                return Err(invalid());
+            }
+            Ok(Utm {
+                zone,
+                north: *icords == CoordinateRepresentation::N,
+                easting: parse(&bytes[2..8]).ok_or_else(invalid)?,
+                northing: parse(&bytes[8..]).ok_or_else(invalid)?,
+            }
+            .to_lat_lon())
+        }
+        CoordinateRepresentation::U => LatLon::from_mgrs(corner),
+        other => Err(NitfError::Unsupported(format!("ICORDS {other:?}"))),
+    }
+}
+
+/// Encode one IGEOLO corner
+pub fn format_corner(icords: &CoordinateRepresentation, corner: &LatLon) -> NitfResult<String> {
+    let out_of_range = || {
+        NitfError::InvalidValue(
+            "IGEOLO".to_string(),
+            format!("{}, {} as {icords:?}", corner.lat, corner.lon),
+        )
+    };
+    if !(-90.0..=90.0).contains(&corner.lat) || !(-180.0..=180.0).contains(&corner.lon) {
+        return Err(out_of_range());
+    }
+    let formatted = match icords {
+        CoordinateRepresentation::G => {
+            let dms = |value: f64| {
+                let seconds = (value.abs() * 3600.0).round() as u64;
+                (seconds / 3600, (seconds / 60) % 60, seconds % 60)
+            };
+            let (lat_d, lat_m, lat_s) = dms(corner.lat);
+            let (lon_d, lon_m, lon_s) = dms(corner.lon);
+            format!(
+                "{lat_d:02}{lat_m:02}{lat_s:02}{}{lon_d:03}{lon_m:02}{lon_s:02}{}",
+                if corner.lat < 0.0 { 'S' } else { 'N' },
+                if corner.lon < 0.0 { 'W' } else { 'E' },
+            )
+        }
+        CoordinateRepresentation::D => format!("{:+07.3}{:+08.3}", corner.lat, corner.lon),
+        CoordinateRepresentation::N | CoordinateRepresentation::S => {
+            let north = *icords == CoordinateRepresentation::N;
+            let utm = corner.to_utm_zone(corner.utm_zone(), north);
+            let (easting, northing) = (utm.easting.round() as i64, utm.northing.round() as i64);
+            if !(0..1_000_000).contains(&easting) || !(0..10_000_000).contains(&northing) {
+                return Err(out_of_range());
+            }
+            format!("{:02}{easting:06}{northing:07}", utm.zone)
+        }
+        CoordinateRepresentation::U => corner.to_mgrs()?,
+        other => return Err(NitfError::Unsupported(format!("ICORDS {other:?}"))),
+    };
+    match formatted.len() {
+        15 => Ok(formatted),
+        _ => Err(out_of_range()),
+    }
+}
+
+impl ImageHeader {
+    /// Decoded image corners, `None` if ICORDS is blank (`N` in NITF 2.0)
+    pub fn corners(&self) -> NitfResult<Option<[LatLon; 4]>> {
+        if !self.has_igeolo() {
+            return Ok(None);
+        }
+        if self.igeolo.len() != 4 {
+            return Err(NitfError::Fatal(format!(
+                "IGEOLO, expected 4 corners but found {}",
+                self.igeolo.len()
+            )));
+        }
+        let mut corners = [LatLon::default(); 4];
+        for (corner, field) in corners.iter_mut().zip(&self.igeolo) {
+            // Use the raw bytes, the string has its whitespace trimmed
+            let text = String::from_utf8_lossy(&field.bytes);
+            *corner = parse_corner(&self.icords.val, &text)?;
+        }
+        Ok(Some(corners))
+    }
+
+    /// Image footprint as a closed polygon, the corners followed by the first corner again
+    pub fn footprint(&self) -> NitfResult<Option<Vec<LatLon>>> {
+        Ok(self.corners()?.map(|corners| {
+            let mut polygon = corners.to_vec();
+            polygon.push(corners[0]);
+            polygon
+        }))
+    }
+
+    /// Set ICORDS and encode `corners` into IGEOLO
+    ///
+    /// [CoordinateRepresentation::DEFAULT] removes the corners. NITF 2.0
+    /// headers only allow [G](CoordinateRepresentation::G),
+    /// [U](CoordinateRepresentation::U) and `DEFAULT`, which is written as `N`.
+    pub fn set_corners(
+        &mut self,
+        icords: CoordinateRepresentation,
+        corners: &[LatLon; 4],
+    ) -> NitfResult<()> {
+        let code = match (self.version().is_nitf20(), &icords) {
+            (true, CoordinateRepresentation::DEFAULT) => "N".to_string(),
+            (true, CoordinateRepresentation::G | CoordinateRepresentation::U) | (false, _) => {
+                icords.to_string()
+            }
+            (true, other) => {
+                return Err(NitfError::Unsupported(format!(
+                    "ICORDS {other:?} in {}",
+                    self.version()
+                )))
+            }
+        };
+        let mut igeolo = Vec::with_capacity(4);
+        for corner in corners {
+            if icords == CoordinateRepresentation::DEFAULT {
+                break;
+            }
+            let mut field = NitfField::default();
+            field.set_string(&format_corner(&icords, corner)?, 15u8, "IGEOLO")?;
+            igeolo.push(field);
+        }
+        self.icords.set_string(&code, 1u8, "ICORDS")?;
+        // The NITF 2.0 code N parses as UTM north, but means no coordinates
+        self.icords.val = icords;
+        self.igeolo = igeolo;
+        Ok(())
+    }
+}
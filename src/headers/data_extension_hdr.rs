@@ -1,10 +1,11 @@
 //! Data Extension segment subheader definition
 use std::fmt::Display;
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 use std::str::FromStr;
 
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::types::{ExtendedSubheader, FieldValue, NitfField, NitfVersion, Security};
 use crate::{NitfError, NitfResult};
 
 /// Metadata for Data Extension Segment
@@ -12,7 +13,7 @@ use crate::{NitfError, NitfResult};
 pub struct DataExtensionHeader {
     /// File Part Type
     pub de: NitfField<String>,
-    /// Unique DES Type Identifier
+    /// Unique DES Type Identifier (DESTAG in NITF 2.0)
     pub desid: NitfField<String>,
     /// Check on this registration
     /// Version of the Data Definition
@@ -43,25 +44,63 @@ pub enum OverflowedHeaderType {
     UDHD,
     /// Image subheader user defined image data overflow
     UDID,
+    /// Header extended header data overflow
+    XHD,
+    /// Label subheader extended subheader data overflow (NITF 2.0)
+    LXSHD,
 }
 
 impl NitfSegmentHeader for DataExtensionHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.de.read(reader, 2u8, "DE")?;
-        self.desid.read(reader, 25u8, "DESID")?;
-        self.desver.read(reader, 2u8, "DESVER")?;
-        self.security.read(reader)?;
-        if self.desid.string.trim() == "TRE_OVERFLOW" {
-            self.desoflw.read(reader, 6u8, "DESOFLW")?;
-            self.desitem.read(reader, 3u8, "DESITEM")?;
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.de.read(reader, session, 2u8, "DE")?;
+        self.desid.read(reader, session, 25u8, "DESID")?;
+        self.desver.read(reader, session, 2u8, "DESVER")?;
+        self.security.read_version(reader, session, version)?;
+        if self.is_overflow() {
+            self.desoflw.read(reader, session, 6u8, "DESOFLW")?;
+            self.desitem.read(reader, session, 3u8, "DESITEM")?;
         }
-        self.desshl.read(reader, 4u8, "DESSHL")?;
+        self.desshl.read(reader, session, 4u8, "DESSHL")?;
         if self.desshl.val != 0 {
             self.desshf
                 .read(reader, self.desshl.val as usize, "DESSHF")?;
         }
         Ok(())
     }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.de.write(writer)?;
+        self.desid.write(writer)?;
+        self.desver.write(writer)?;
+        self.security.write(writer)?;
+        if self.is_overflow() {
+            self.desoflw.write(writer)?;
+            self.desitem.write(writer)?;
+        }
+        self.desshl.write(writer)?;
+        if self.desshl.val != 0 {
+            self.desshf.write(writer)?;
+        }
+        Ok(())
+    }
+}
+impl DataExtensionHeader {
+    /// Whether this segment holds overflowing extended header data, which
+    /// adds the DESOFLW and DESITEM fields
+    pub fn is_overflow(&self) -> bool {
+        match self.security.version {
+            NitfVersion::Nitf20 => matches!(
+                self.desid.string.as_str(),
+                "Registered Extensions" | "Controlled Extensions"
+            ),
+            _ => self.desid.string == "TRE_OVERFLOW",
+        }
+    }
 }
 impl Display for DataExtensionHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -86,7 +125,24 @@ impl FromStr for OverflowedHeaderType {
             "TXSHD" => Ok(Self::TXSHD),
             "UDHD" => Ok(Self::UDHD),
             "UDID" => Ok(Self::UDID),
+            "XHD" => Ok(Self::XHD),
+            "LXSHD" => Ok(Self::LXSHD),
             _ => Err(NitfError::EnumError("OverflowedHeaderType")),
         }
     }
 }
+impl FieldValue for OverflowedHeaderType {}
+impl Display for OverflowedHeaderType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::IXSHD => "IXSHD",
+            Self::SXSHD => "SXSHD",
+            Self::TXSHD => "TXSHD",
+            Self::UDHD => "UDHD",
+            Self::UDID => "UDID",
+            Self::XHD => "XHD",
+            Self::LXSHD => "LXSHD",
+        };
+        write!(f, "{code}")
+    }
+}
@@ -1,12 +1,18 @@
 //! Graphic segment subheader definition
 use std::fmt::Display;
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 use std::str::FromStr;
 
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::types::{read_exact, ExtendedSubheader, FieldValue, NitfField, NitfVersion, Security};
 use crate::{NitfError, NitfResult};
 /// Header fields for Graphic Segment
+///
+/// NITF 2.0 symbol segments are read into the same structure. They share the
+/// identification, security, display/attachment level, location and color
+/// fields, use `sfmt` for the symbol type (STYPE), and store the remaining
+/// symbol fields in the members marked NITF 2.0, with the 2.1-only fields left empty.
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct GraphicHeader {
     /// File Part Type
@@ -19,7 +25,7 @@ pub struct GraphicHeader {
     pub security: Security,
     /// Encryption
     pub encryp: NitfField<String>,
-    /// Graphic Type
+    /// Graphic Type (STYPE in NITF 2.0)
     pub sfmt: NitfField<Format>,
     /// Reserved for Future Use
     pub sstruct: NitfField<u64>,
@@ -28,7 +34,7 @@ pub struct GraphicHeader {
     /// Graphic Attachment Level
     pub salvl: NitfField<u16>,
     /// Graphic Location
-    pub sloc: NitfField<String>, // TODO: Same image image ILOC type thing
+    pub sloc: NitfField<BoundLocation>,
     /// First Graphic Bound Location
     pub sbnd1: NitfField<BoundLocation>,
     /// Graphic Color
@@ -37,6 +43,24 @@ pub struct GraphicHeader {
     pub sbnd2: NitfField<BoundLocation>,
     /// Reserved for Future Use
     pub sres2: NitfField<u8>,
+    /// Number of Lines Per Symbol (NITF 2.0)
+    pub nlips: NitfField<u16>,
+    /// Number of Pixels Per Line (NITF 2.0)
+    pub npixpl: NitfField<u16>,
+    /// Line Width (NITF 2.0)
+    pub nwdth: NitfField<u16>,
+    /// Number of Bits Per Pixel (NITF 2.0)
+    pub nbpp: NitfField<u8>,
+    /// Second Symbol Location (NITF 2.0)
+    pub sloc2: NitfField<String>,
+    /// Symbol Number (NITF 2.0)
+    pub snum: NitfField<String>,
+    /// Symbol Rotation (NITF 2.0)
+    pub srot: NitfField<u16>,
+    /// Number of Look-Up-Table Entries (NITF 2.0)
+    pub nelut: NitfField<u16>,
+    /// Symbol Look-Up-Table, three bytes per entry (NITF 2.0)
+    pub dlut: Vec<u8>,
     /// Graphic Extended Subheader Data Length
     pub sxshdl: NitfField<u16>,
     /// Graphic Extended Subheader Overflow
@@ -53,15 +77,31 @@ impl Display for GraphicHeader {
         out_str += format!("SNAME: {}, ", self.sname).as_ref();
         out_str += format!("SECURITY: [{}], ", self.security).as_ref();
         out_str += format!("ENCRYP: {}, ", self.encryp).as_ref();
-        out_str += format!("SFMT: {}, ", self.sfmt).as_ref();
-        out_str += format!("SSTRUCT: {}, ", self.sstruct).as_ref();
-        out_str += format!("SDLVL: {}, ", self.sdlvl).as_ref();
-        out_str += format!("SALVL: {}, ", self.salvl).as_ref();
-        out_str += format!("SLOC: {}, ", self.sloc).as_ref();
-        out_str += format!("SBND1: {}, ", self.sbnd1).as_ref();
-        out_str += format!("SCOLOR: {}, ", self.scolor).as_ref();
-        out_str += format!("SBND2: {}, ", self.sbnd2).as_ref();
-        out_str += format!("SRES2: {}, ", self.sres2).as_ref();
+        if self.version().is_nitf20() {
+            out_str += format!("STYPE: {}, ", self.sfmt).as_ref();
+            out_str += format!("NLIPS: {}, ", self.nlips).as_ref();
+            out_str += format!("NPIXPL: {}, ", self.npixpl).as_ref();
+            out_str += format!("NWDTH: {}, ", self.nwdth).as_ref();
+            out_str += format!("NBPP: {}, ", self.nbpp).as_ref();
+            out_str += format!("SDLVL: {}, ", self.sdlvl).as_ref();
+            out_str += format!("SALVL: {}, ", self.salvl).as_ref();
+            out_str += format!("SLOC: {}, ", self.sloc).as_ref();
+            out_str += format!("SLOC2: {}, ", self.sloc2).as_ref();
+            out_str += format!("SCOLOR: {}, ", self.scolor).as_ref();
+            out_str += format!("SNUM: {}, ", self.snum).as_ref();
+            out_str += format!("SROT: {}, ", self.srot).as_ref();
+            out_str += format!("NELUT: {}, ", self.nelut).as_ref();
+        } else {
+            out_str += format!("SFMT: {}, ", self.sfmt).as_ref();
+            out_str += format!("SSTRUCT: {}, ", self.sstruct).as_ref();
+            out_str += format!("SDLVL: {}, ", self.sdlvl).as_ref();
+            out_str += format!("SALVL: {}, ", self.salvl).as_ref();
+            out_str += format!("SLOC: {}, ", self.sloc).as_ref();
+            out_str += format!("SBND1: {}, ", self.sbnd1).as_ref();
+            out_str += format!("SCOLOR: {}, ", self.scolor).as_ref();
+            out_str += format!("SBND2: {}, ", self.sbnd2).as_ref();
+            out_str += format!("SRES2: {}, ", self.sres2).as_ref();
+        }
         out_str += format!("SXSHDL: {}, ", self.sxshdl).as_ref();
         out_str += format!("SXSOFL: {}, ", self.sxsofl).as_ref();
         out_str += format!("[SXSHD: {}]", self.sxshd).as_ref();
@@ -69,38 +109,128 @@ impl Display for GraphicHeader {
     }
 }
 impl NitfSegmentHeader for GraphicHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.sy.read(reader, 2u8, "SY")?;
-        self.sid.read(reader, 10u8, "SID")?;
-        self.sname.read(reader, 20u8, "SNAME")?;
-        self.security.read(reader)?;
-        self.encryp.read(reader, 1u8, "ENCRYP")?;
-        self.sfmt.read(reader, 1u8, "SFMT")?;
-        self.sstruct.read(reader, 13u8, "SSTRUCT")?;
-        self.sdlvl.read(reader, 3u8, "SDLVL")?;
-        self.salvl.read(reader, 3u8, "SALVL")?;
-        self.sloc.read(reader, 10u8, "SLOC")?;
-        self.sbnd1.read(reader, 10u8, "SBND1")?;
-        self.scolor.read(reader, 1u8, "SCOLOR")?;
-        self.sbnd2.read(reader, 10u8, "SBND2")?;
-        self.sres2.read(reader, 2u8, "SRES2")?;
-        self.sxshdl.read(reader, 5u8, "SXSHDL")?;
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.sy.read(reader, session, 2u8, "SY")?;
+        self.sid.read(reader, session, 10u8, "SID")?;
+        self.sname.read(reader, session, 20u8, "SNAME")?;
+        self.security.read_version(reader, session, version)?;
+        self.encryp.read(reader, session, 1u8, "ENCRYP")?;
+        if version.is_nitf20() {
+            self.sfmt.read(reader, session, 1u8, "STYPE")?;
+            return self.read_nitf20(reader, session);
+        }
+        self.sfmt.read(reader, session, 1u8, "SFMT")?;
+        self.sstruct.read(reader, session, 13u8, "SSTRUCT")?;
+        self.sdlvl.read(reader, session, 3u8, "SDLVL")?;
+        self.salvl.read(reader, session, 3u8, "SALVL")?;
+        self.sloc.read(reader, session, 10u8, "SLOC")?;
+        self.sbnd1.read(reader, session, 10u8, "SBND1")?;
+        self.scolor.read(reader, session, 1u8, "SCOLOR")?;
+        self.sbnd2.read(reader, session, 10u8, "SBND2")?;
+        self.sres2.read(reader, session, 2u8, "SRES2")?;
+        self.read_extended(reader, session)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.sy.write(writer)?;
+        self.sid.write(writer)?;
+        self.sname.write(writer)?;
+        self.security.write(writer)?;
+        self.encryp.write(writer)?;
+        self.sfmt.write(writer)?;
+        if self.version().is_nitf20() {
+            self.nlips.write(writer)?;
+            self.npixpl.write(writer)?;
+            self.nwdth.write(writer)?;
+            self.nbpp.write(writer)?;
+            self.sdlvl.write(writer)?;
+            self.salvl.write(writer)?;
+            self.sloc.write(writer)?;
+            self.sloc2.write(writer)?;
+            self.scolor.write(writer)?;
+            self.snum.write(writer)?;
+            self.srot.write(writer)?;
+            self.nelut.write(writer)?;
+            writer.write_all(&self.dlut)?;
+        } else {
+            self.sstruct.write(writer)?;
+            self.sdlvl.write(writer)?;
+            self.salvl.write(writer)?;
+            self.sloc.write(writer)?;
+            self.sbnd1.write(writer)?;
+            self.scolor.write(writer)?;
+            self.sbnd2.write(writer)?;
+            self.sres2.write(writer)?;
+        }
+        self.sxshdl.write(writer)?;
+        if self.sxshdl.val != 0 {
+            self.sxsofl.write(writer)?;
+            self.sxshd.write(writer)?;
+        }
+        Ok(())
+    }
+}
+impl GraphicHeader {
+    /// File format version the header is laid out for
+    pub fn version(&self) -> NitfVersion {
+        self.security.version
+    }
+
+    /// Read the NITF 2.0 symbol fields following STYPE
+    fn read_nitf20<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<()> {
+        self.nlips.read(reader, session, 4u8, "NLIPS")?;
+        self.npixpl.read(reader, session, 4u8, "NPIXPL")?;
+        self.nwdth.read(reader, session, 4u8, "NWDTH")?;
+        self.nbpp.read(reader, session, 1u8, "NBPP")?;
+        self.sdlvl.read(reader, session, 3u8, "SDLVL")?;
+        self.salvl.read(reader, session, 3u8, "SALVL")?;
+        self.sloc.read(reader, session, 10u8, "SLOC")?;
+        self.sloc2.read(reader, session, 10u8, "SLOC2")?;
+        self.scolor.read(reader, session, 1u8, "SCOLOR")?;
+        self.snum.read(reader, session, 6u8, "SNUM")?;
+        self.srot.read(reader, session, 3u8, "SROT")?;
+        self.nelut.read(reader, session, 3u8, "NELUT")?;
+        self.dlut = vec![0; 3 * self.nelut.val as usize];
+        let offset = reader.stream_position()?;
+        read_exact(reader, &mut self.dlut, "DLUT", offset)?;
+        self.read_extended(reader, session)
+    }
+
+    fn read_extended<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<()> {
+        self.sxshdl.read(reader, session, 5u8, "SXSHDL")?;
         let gphx_data_length = self.sxshdl.val;
         if gphx_data_length != 0 {
-            self.sxsofl.read(reader, 3u8, "SXSOFL")?;
+            self.sxsofl.read(reader, session, 3u8, "SXSOFL")?;
             self.sxshd
-                .read(reader, (gphx_data_length - 3) as usize, "SXSHD")?;
+                .read(reader, self.sxshdl.extended_data_length("SXSHDL")?, "SXSHD")?;
         }
         Ok(())
     }
 }
 
-/// Graphic type. Right now standard only supports C
+/// Graphic type. NITF 2.1 only supports C, NITF 2.0 symbols may also be B or O
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub enum Format {
     #[default]
     /// Computer graphics metafile
     C,
+    /// Bit-mapped symbol (NITF 2.0)
+    B,
+    /// Object symbol (NITF 2.0)
+    O,
 }
 
 impl FromStr for Format {
@@ -108,12 +238,26 @@ impl FromStr for Format {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "C" => Ok(Self::C),
+            "B" => Ok(Self::B),
+            "O" => Ok(Self::O),
             _ => Err(NitfError::EnumError("Format")),
         }
     }
 }
+impl FieldValue for Format {}
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::C => "C",
+            Self::B => "B",
+            Self::O => "O",
+        };
+        write!(f, "{code}")
+    }
+}
 
-/// Graphic bound position relative to origin of coordinate system
+/// Row and column position relative to an origin, such as the location of a
+/// segment relative to the one it is attached to or a graphic bound
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct BoundLocation {
     pub row: i32,
@@ -124,7 +268,8 @@ impl FromStr for BoundLocation {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let n_char_tot = s.len();
-        if n_char_tot % 2 == 0 {
+        // Splitting at a byte offset needs single byte characters
+        if s.is_ascii() && n_char_tot.is_multiple_of(2) {
             let mut bounds = Self::default();
             let n_char = n_char_tot / 2;
             bounds.row = s[..n_char]
@@ -139,6 +284,7 @@ impl FromStr for BoundLocation {
         }
     }
 }
+impl FieldValue for BoundLocation {}
 
 /// Color type of graphics
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
@@ -160,3 +306,57 @@ impl FromStr for Color {
         }
     }
 }
+impl FieldValue for Color {}
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::C => "C",
+            Self::M => "M",
+        };
+        write!(f, "{code}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+    use crate::parse::ParseOptions;
+
+    #[test]
+    fn bound_location() {
+        let location: BoundLocation = "-0012-0034".parse().unwrap();
+        assert_eq!((location.row, location.col), (-12, -34));
+        assert!("00120034X".parse::<BoundLocation>().is_err());
+        // Multi-byte characters which straddle the middle of the field
+        assert!("0000é0000".parse::<BoundLocation>().is_err());
+        assert!("00000é000".parse::<BoundLocation>().is_err());
+    }
+
+    #[test]
+    fn non_ascii_iloc() -> NitfResult<()> {
+        let header = ImageHeaderBuilder::new()?.size(1, 1)?.band("M", "")?;
+        let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header.build()?, vec![0])
+            .build()?;
+        let mut bytes = vec![];
+        nitf.write_to(&mut bytes)?;
+        let (read, _) = ParseOptions::lenient().from_reader(&mut Cursor::new(&bytes))?;
+        let iloc = read.image_segments[0].meta.iloc.offset() as usize;
+        bytes[iloc..iloc + 10].copy_from_slice("0000é0000".as_bytes());
+
+        let (read, diagnostics) = ParseOptions::lenient().from_reader(&mut Cursor::new(&bytes))?;
+        assert_eq!(
+            read.image_segments[0].meta.iloc.val,
+            BoundLocation::default()
+        );
+        assert_eq!(diagnostics[0].field, "ILOC");
+        // Bytes which are not UTF-8 are read as Latin-1, which is not ASCII either
+        bytes[iloc + 4] = 0xE9;
+        let (_, diagnostics) = ParseOptions::lenient().from_reader(&mut Cursor::new(&bytes))?;
+        assert_eq!(diagnostics[0].field, "ILOC");
+        Ok(())
+    }
+}
@@ -1,25 +1,25 @@
 //! Image segment definition
-//!
-//! Need to implement data mask - which also means need to implement some kind of nicer parsing (enums, among other things)
 use std::fmt::Display;
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 use std::str::FromStr;
 
+use crate::headers::graphic_hdr::BoundLocation;
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::types::{read_exact, ExtendedSubheader, FieldValue, NitfField, NitfVersion, Security};
 use crate::{NitfError, NitfResult};
 /// Metadata for Image Segment subheader
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct ImageHeader {
     /// File Part Type
     pub im: NitfField<String>,
-    /// Image Identifier 1
+    /// Image Identifier 1 (IID in NITF 2.0)
     pub iid1: NitfField<String>,
     /// Image Date and Time
     pub idatim: NitfField<String>,
     /// Target Identifier
     pub tgtid: NitfField<String>,
-    /// Image Identifier 2
+    /// Image Identifier 2 (ITITLE in NITF 2.0)
     pub iid2: NitfField<String>,
     /// Security information
     pub security: Security,
@@ -42,6 +42,9 @@ pub struct ImageHeader {
     /// Pixel Justification
     pub pjust: NitfField<PixelJustification>,
     /// Image Coordinate Representation
+    ///
+    /// The NITF 2.0 code `N` (no coordinates) is given the value
+    /// [CoordinateRepresentation::DEFAULT], the same as a 2.1 blank.
     pub icords: NitfField<CoordinateRepresentation>,
     /// Image Geographic Location
     pub igeolo: Vec<NitfField<String>>, // TODO: Check this out
@@ -78,7 +81,7 @@ pub struct ImageHeader {
     /// Image Attachment Level
     pub ialvl: NitfField<u16>,
     /// Image Location
-    pub iloc: NitfField<String>, // TODO fix this or something
+    pub iloc: NitfField<BoundLocation>,
     /// Image Magnification
     pub imag: NitfField<String>,
     /// User Defined Image Data Length
@@ -86,7 +89,7 @@ pub struct ImageHeader {
     /// User Defined Overflow
     pub udofl: NitfField<u16>,
     /// User Defined Image Data
-    pub udid: NitfField<String>, // TODO
+    pub udid: ExtendedSubheader,
     /// Image Extended Subheader Data Length
     pub ixshdl: NitfField<u32>,
     /// Image Extended Subheader Overflow
@@ -109,9 +112,9 @@ pub struct Band {
     /// Number of Look-Up-Tables for the Image Band
     pub nluts: NitfField<u8>, //
     /// Number of Look-Up-Table Entries for the Image Band
-    pub nelut: NitfField<u16>,
-    /// Image Band Look-Up-Tables
-    pub lutd: Vec<NitfField<u8>>,
+    pub nelut: NitfField<u32>,
+    /// Image Band Look-Up-Tables, NLUTS tables of NELUT entries each
+    pub lutd: Vec<Vec<u8>>,
 }
 
 /// Pixel Value type options
@@ -182,6 +185,8 @@ pub enum CoordinateRepresentation {
     G,
     /// Decimal degrees
     D,
+    /// Geocentric (NITF 2.0)
+    C,
 }
 
 /// Image compression values
@@ -240,19 +245,24 @@ pub enum Mode {
 
 // FUNCTIONS
 /// Helper function for parsing bands
-fn read_bands(reader: &mut File, n_band: u32) -> NitfResult<Vec<Band>> {
+fn read_bands<R: Read + Seek>(
+    reader: &mut R,
+    session: &mut ParseSession,
+    n_band: u32,
+) -> NitfResult<Vec<Band>> {
     let mut bands: Vec<Band> = vec![Band::default(); n_band as usize];
     for band in &mut bands {
-        band.irepband.read(reader, 2u8, "IREPBAND")?;
-        band.isubcat.read(reader, 6u8, "ISUBCAT")?;
-        band.ifc.read(reader, 1u8, "IFC")?;
-        band.imflt.read(reader, 3u8, "IMFLT")?;
-        band.nluts.read(reader, 1u8, "NLUTS")?;
+        band.irepband.read(reader, session, 2u8, "IREPBAND")?;
+        band.isubcat.read(reader, session, 6u8, "ISUBCAT")?;
+        band.ifc.read(reader, session, 1u8, "IFC")?;
+        band.imflt.read(reader, session, 3u8, "IMFLT")?;
+        band.nluts.read(reader, session, 1u8, "NLUTS")?;
         if band.nluts.val != 0 {
-            band.nelut.read(reader, 5u8, "NELUT")?;
-            for _ in 0..band.nelut.val {
-                let mut lut: NitfField<u8> = NitfField::default();
-                lut.read(reader, 1u8, "LUDT")?;
+            band.nelut.read(reader, session, 5u8, "NELUT")?;
+            for _ in 0..band.nluts.val {
+                let mut lut = vec![0; band.nelut.val as usize];
+                let offset = reader.stream_position()?;
+                read_exact(reader, &mut lut, "LUTD", offset)?;
                 band.lutd.push(lut);
             }
         }
@@ -262,67 +272,181 @@ fn read_bands(reader: &mut File, n_band: u32) -> NitfResult<Vec<Band>> {
 
 // TRAIT IMPLEMENTATIONS
 impl NitfSegmentHeader for ImageHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.im.read(reader, 2u8, "IM")?;
-        self.iid1.read(reader, 10u8, "IID1")?;
-        self.idatim.read(reader, 14u8, "IDATIM")?;
-        self.tgtid.read(reader, 17u8, "TGTID")?;
-        self.iid2.read(reader, 80u8, "IID2")?;
-        self.security.read(reader)?;
-        self.encryp.read(reader, 1u8, "ENCRYP")?;
-        self.isorce.read(reader, 42u8, "ISORCE")?;
-        self.nrows.read(reader, 8u8, "NROWS")?;
-        self.ncols.read(reader, 8u8, "NCOLS")?;
-        self.pvtype.read(reader, 3u8, "PVTYPE")?;
-        self.irep.read(reader, 8u8, "IREP")?;
-        self.icat.read(reader, 8u8, "ICAT")?;
-        self.abpp.read(reader, 2u8, "ABPP")?;
-        self.pjust.read(reader, 1u8, "PJUST")?;
-        self.icords.read(reader, 1u8, "ICORDS")?;
-        for _ in 0..4 {
-            let mut geoloc: NitfField<String> = NitfField::default();
-            geoloc.read(reader, 15u8, "READ")?;
-            self.igeolo.push(geoloc);
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.im.read(reader, session, 2u8, "IM")?;
+        self.iid1.read(reader, session, 10u8, "IID1")?;
+        self.idatim.read(reader, session, 14u8, "IDATIM")?;
+        self.tgtid.read(reader, session, 17u8, "TGTID")?;
+        self.iid2.read(reader, session, 80u8, "IID2")?;
+        self.security.read_version(reader, session, version)?;
+        self.encryp.read(reader, session, 1u8, "ENCRYP")?;
+        self.isorce.read(reader, session, 42u8, "ISORCE")?;
+        self.nrows.read(reader, session, 8u8, "NROWS")?;
+        self.ncols.read(reader, session, 8u8, "NCOLS")?;
+        self.pvtype.read(reader, session, 3u8, "PVTYPE")?;
+        self.irep.read(reader, session, 8u8, "IREP")?;
+        self.icat.read(reader, session, 8u8, "ICAT")?;
+        self.abpp.read(reader, session, 2u8, "ABPP")?;
+        self.pjust.read(reader, session, 1u8, "PJUST")?;
+        self.icords.read(reader, session, 1u8, "ICORDS")?;
+        if version.is_nitf20() && self.icords.string == "N" {
+            self.icords.val = CoordinateRepresentation::DEFAULT;
+        }
+        if self.has_igeolo() {
+            for _ in 0..4 {
+                let mut geoloc: NitfField<String> = NitfField::default();
+                geoloc.read(reader, session, 15u8, "IGEOLO")?;
+                self.igeolo.push(geoloc);
+            }
         }
-        self.nicom.read(reader, 1u8, "NICOM")?;
+        self.nicom.read(reader, session, 1u8, "NICOM")?;
         for _ in 0..self.nicom.val {
             let mut comment: NitfField<String> = NitfField::default();
-            comment.read(reader, 80u8, "READ")?;
+            comment.read(reader, session, 80u8, "ICOM")?;
             self.icoms.push(comment);
         }
 
-        self.ic.read(reader, 2u8, "IC")?;
-        self.nbands.read(reader, 1u8, "NBANDS")?;
+        self.ic.read(reader, session, 2u8, "IC")?;
+        if self.has_comrat() {
+            self.comrat.read(reader, session, 4u8, "COMRAT")?;
+        }
+        self.nbands.read(reader, session, 1u8, "NBANDS")?;
         // If NBANDS = 0, use XBANDS
         if self.nbands.val != 0 {
-            self.bands = read_bands(reader, self.nbands.val as u32)?;
+            self.bands = read_bands(reader, session, self.nbands.val as u32)?;
         } else {
-            self.xbands.read(reader, 5u8, "XBANDS")?;
-            self.bands = read_bands(reader, self.xbands.val)?;
+            self.xbands.read(reader, session, 5u8, "XBANDS")?;
+            self.bands = read_bands(reader, session, self.xbands.val)?;
         }
-        self.isync.read(reader, 1u8, "ISYNC")?;
-        self.imode.read(reader, 1u8, "IMODE")?;
-        self.nbpr.read(reader, 4u8, "NBPR")?;
-        self.nbpc.read(reader, 4u8, "NBPC")?;
-        self.nppbh.read(reader, 4u8, "NPPBH")?;
-        self.nppbv.read(reader, 4u8, "NPPBV")?;
-        self.nbpp.read(reader, 2u8, "NBPP")?;
-        self.idlvl.read(reader, 3u8, "IDLVL")?;
-        self.ialvl.read(reader, 3u8, "IALVL")?;
-        self.iloc.read(reader, 10u8, "ILOC")?;
-        self.imag.read(reader, 4u8, "IMAG")?;
-        self.udidl.read(reader, 5u8, "UDIDL")?;
+        self.isync.read(reader, session, 1u8, "ISYNC")?;
+        self.imode.read(reader, session, 1u8, "IMODE")?;
+        self.nbpr.read(reader, session, 4u8, "NBPR")?;
+        self.nbpc.read(reader, session, 4u8, "NBPC")?;
+        self.nppbh.read(reader, session, 4u8, "NPPBH")?;
+        self.nppbv.read(reader, session, 4u8, "NPPBV")?;
+        self.nbpp.read(reader, session, 2u8, "NBPP")?;
+        self.idlvl.read(reader, session, 3u8, "IDLVL")?;
+        self.ialvl.read(reader, session, 3u8, "IALVL")?;
+        self.iloc.read(reader, session, 10u8, "ILOC")?;
+        self.imag.read(reader, session, 4u8, "IMAG")?;
+        self.udidl.read(reader, session, 5u8, "UDIDL")?;
         let udi_data_length = self.udidl.val;
         if udi_data_length != 0 {
-            self.udofl.read(reader, 3u8, "UDOFL")?;
-            self.udid.read(reader, udi_data_length - 3, "UDID")?;
+            self.udofl.read(reader, session, 3u8, "UDOFL")?;
+            self.udid
+                .read(reader, self.udidl.extended_data_length("UDIDL")?, "UDID")?;
         }
-        self.ixshdl.read(reader, 5u8, "IXSHDL")?;
+        self.ixshdl.read(reader, session, 5u8, "IXSHDL")?;
         let ixsh_data_length = self.ixshdl.val;
         if ixsh_data_length != 0 {
-            self.ixsofl.read(reader, 3u8, "IXSOFL")?;
+            self.ixsofl.read(reader, session, 3u8, "IXSOFL")?;
             self.ixshd
-                .read(reader, (ixsh_data_length - 3) as usize, "IXSHD")?;
+                .read(reader, self.ixshdl.extended_data_length("IXSHDL")?, "IXSHD")?;
+        }
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.im.write(writer)?;
+        self.iid1.write(writer)?;
+        self.idatim.write(writer)?;
+        self.tgtid.write(writer)?;
+        self.iid2.write(writer)?;
+        self.security.write(writer)?;
+        self.encryp.write(writer)?;
+        self.isorce.write(writer)?;
+        self.nrows.write(writer)?;
+        self.ncols.write(writer)?;
+        self.pvtype.write(writer)?;
+        self.irep.write(writer)?;
+        self.icat.write(writer)?;
+        self.abpp.write(writer)?;
+        self.pjust.write(writer)?;
+        self.icords.write(writer)?;
+        for geoloc in &self.igeolo {
+            geoloc.write(writer)?;
+        }
+        self.nicom.write(writer)?;
+        for comment in &self.icoms {
+            comment.write(writer)?;
+        }
+        self.ic.write(writer)?;
+        if self.has_comrat() {
+            self.comrat.write(writer)?;
+        }
+        self.nbands.write(writer)?;
+        if self.nbands.val == 0 {
+            self.xbands.write(writer)?;
+        }
+        for band in &self.bands {
+            band.write(writer)?;
+        }
+        self.isync.write(writer)?;
+        self.imode.write(writer)?;
+        self.nbpr.write(writer)?;
+        self.nbpc.write(writer)?;
+        self.nppbh.write(writer)?;
+        self.nppbv.write(writer)?;
+        self.nbpp.write(writer)?;
+        self.idlvl.write(writer)?;
+        self.ialvl.write(writer)?;
+        self.iloc.write(writer)?;
+        self.imag.write(writer)?;
+        self.udidl.write(writer)?;
+        if self.udidl.val != 0 {
+            self.udofl.write(writer)?;
+            self.udid.write(writer)?;
+        }
+        self.ixshdl.write(writer)?;
+        if self.ixshdl.val != 0 {
+            self.ixsofl.write(writer)?;
+            self.ixshd.write(writer)?;
+        }
+        Ok(())
+    }
+}
+impl ImageHeader {
+    /// Whether the image data starts with a mask table (`NM` and `M*` compression)
+    pub fn has_mask(&self) -> bool {
+        use Compression::*;
+        matches!(self.ic.val, NM | M1 | M3 | M4 | M5 | M6 | M7 | M8)
+    }
+
+    /// File format version the header is laid out for
+    pub fn version(&self) -> NitfVersion {
+        self.security.version
+    }
+
+    /// IGEOLO is omitted when ICORDS is a space, or `N` in NITF 2.0
+    pub(crate) fn has_igeolo(&self) -> bool {
+        match self.version() {
+            NitfVersion::Nitf20 => !matches!(self.icords.string.as_str(), "" | "N"),
+            _ => !self.icords.string.is_empty(),
+        }
+    }
+
+    /// COMRAT is only present for compressed images
+    fn has_comrat(&self) -> bool {
+        !matches!(self.ic.val, Compression::NC | Compression::NM)
+    }
+}
+impl Band {
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.irepband.write(writer)?;
+        self.isubcat.write(writer)?;
+        self.ifc.write(writer)?;
+        self.imflt.write(writer)?;
+        self.nluts.write(writer)?;
+        if self.nluts.val != 0 {
+            self.nelut.write(writer)?;
+            for lut in &self.lutd {
+                writer.write_all(lut)?;
+            }
         }
         Ok(())
     }
@@ -354,6 +478,9 @@ impl Display for ImageHeader {
             out_str += format!("[ICOM: {}], ", comment).as_ref();
         }
         out_str += format!("IC: {}, ", self.ic).as_ref();
+        if self.has_comrat() {
+            out_str += format!("COMRAT: {}, ", self.comrat).as_ref();
+        }
         out_str += format!("NBANDS: {}, ", self.nbands).as_ref();
         for band in &self.bands {
             out_str += format!("[BAND: {}], ", band).as_ref();
@@ -389,9 +516,6 @@ impl Display for Band {
         out_str += format!("IMFLT: {}, ", self.imflt).as_ref();
         out_str += format!("NLUTS: {}, ", self.nluts).as_ref();
         out_str += format!("NELUT: {}, ", self.nelut).as_ref();
-        for look_up in &self.lutd {
-            out_str += format!("LUTD: {look_up}, ").as_ref();
-        }
         write!(f, "{out_str}")
     }
 }
@@ -408,13 +532,26 @@ impl FromStr for PixelValueType {
         }
     }
 }
+impl FieldValue for PixelValueType {}
+impl Display for PixelValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::INT => "INT",
+            Self::B => "B",
+            Self::SI => "SI",
+            Self::R => "R",
+            Self::C => "C",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for ImageRepresentation {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "MONO" => Ok(Self::MONO),
             "RGB" => Ok(Self::RGB),
-            "RGBLUT" => Ok(Self::RGBLUT),
+            "RGB/LUT" | "RGBLUT" => Ok(Self::RGBLUT),
             "MULTI" => Ok(Self::MULTI),
             "NODISPLY" => Ok(Self::NODISPLY),
             "NVECTOR" => Ok(Self::NVECTOR),
@@ -425,6 +562,23 @@ impl FromStr for ImageRepresentation {
         }
     }
 }
+impl FieldValue for ImageRepresentation {}
+impl Display for ImageRepresentation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::MONO => "MONO",
+            Self::RGB => "RGB",
+            Self::RGBLUT => "RGB/LUT",
+            Self::MULTI => "MULTI",
+            Self::NODISPLY => "NODISPLY",
+            Self::NVECTOR => "NVECTOR",
+            Self::POLAR => "POLAR",
+            Self::VPH => "VPH",
+            Self::YCbCr601 => "YCbCr601",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for PixelJustification {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -435,6 +589,16 @@ impl FromStr for PixelJustification {
         }
     }
 }
+impl FieldValue for PixelJustification {}
+impl Display for PixelJustification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::R => "R",
+            Self::L => "L",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for CoordinateRepresentation {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -446,10 +610,27 @@ impl FromStr for CoordinateRepresentation {
             "P" => Ok(Self::P),
             "G" => Ok(Self::G),
             "D" => Ok(Self::D),
+            "C" => Ok(Self::C),
             _ => Err(NitfError::EnumError("CoordinateRepresentation")),
         }
     }
 }
+impl FieldValue for CoordinateRepresentation {}
+impl Display for CoordinateRepresentation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::DEFAULT => "",
+            Self::U => "U",
+            Self::N => "N",
+            Self::S => "S",
+            Self::P => "P",
+            Self::G => "G",
+            Self::D => "D",
+            Self::C => "C",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for Compression {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -475,6 +656,31 @@ impl FromStr for Compression {
         }
     }
 }
+impl FieldValue for Compression {}
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::NC => "NC",
+            Self::NM => "NM",
+            Self::C1 => "C1",
+            Self::C3 => "C3",
+            Self::C4 => "C4",
+            Self::C5 => "C5",
+            Self::C6 => "C6",
+            Self::C7 => "C7",
+            Self::C8 => "C8",
+            Self::I1 => "I1",
+            Self::M1 => "M1",
+            Self::M3 => "M3",
+            Self::M4 => "M4",
+            Self::M5 => "M5",
+            Self::M6 => "M6",
+            Self::M7 => "M7",
+            Self::M8 => "M8",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for Mode {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -487,3 +693,15 @@ impl FromStr for Mode {
         }
     }
 }
+impl FieldValue for Mode {}
+impl Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::B => "B",
+            Self::P => "P",
+            Self::R => "R",
+            Self::S => "S",
+        };
+        write!(f, "{code}")
+    }
+}
@@ -0,0 +1,126 @@
+//! Label segment subheader definition, only present in NITF 2.0 files
+use std::fmt::Display;
+use std::io::{Read, Seek, Write};
+
+use crate::headers::graphic_hdr::BoundLocation;
+use crate::headers::NitfSegmentHeader;
+use crate::parse::ParseSession;
+use crate::types::{ExtendedSubheader, NitfField, NitfVersion, Security};
+use crate::NitfResult;
+/// Header fields for Label Segment
+#[derive(Default, Clone, Debug, Eq, PartialEq)]
+pub struct LabelHeader {
+    /// File Part Type
+    pub la: NitfField<String>,
+    /// Label Identifier
+    pub lid: NitfField<String>,
+    /// Security information
+    pub security: Security,
+    /// Encryption
+    pub encryp: NitfField<String>,
+    /// Label Font Style
+    pub lfs: NitfField<String>,
+    /// Label Cell Width
+    pub lcw: NitfField<u8>,
+    /// Label Cell Height
+    pub lch: NitfField<u8>,
+    /// Label Display Level
+    pub ldlvl: NitfField<u16>,
+    /// Label Attachment Level
+    pub lalvl: NitfField<u16>,
+    /// Label Location
+    pub lloc: NitfField<BoundLocation>,
+    /// Label Text Color, one byte each of red, green and blue
+    pub ltc: Vec<NitfField<String>>,
+    /// Label Background Color, one byte each of red, green and blue
+    pub lbc: Vec<NitfField<String>>,
+    /// Label Extended Subheader Data Length
+    pub lxshdl: NitfField<u16>,
+    /// Label Extended Subheader Overflow
+    pub lxsofl: NitfField<u16>,
+    /// Label Extended Subheader Data
+    pub lxshd: ExtendedSubheader,
+}
+
+impl Display for LabelHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let color = |color: &[NitfField<String>]| {
+            let bytes: Vec<u8> = color.iter().flat_map(|c| c.bytes.clone()).collect();
+            format!("{bytes:?}")
+        };
+        let mut out_str = String::default();
+        out_str += format!("LA: {}, ", self.la).as_ref();
+        out_str += format!("LID: {}, ", self.lid).as_ref();
+        out_str += format!("SECURITY: [{}], ", self.security).as_ref();
+        out_str += format!("ENCRYP: {}, ", self.encryp).as_ref();
+        out_str += format!("LFS: {}, ", self.lfs).as_ref();
+        out_str += format!("LCW: {}, ", self.lcw).as_ref();
+        out_str += format!("LCH: {}, ", self.lch).as_ref();
+        out_str += format!("LDLVL: {}, ", self.ldlvl).as_ref();
+        out_str += format!("LALVL: {}, ", self.lalvl).as_ref();
+        out_str += format!("LLOC: {}, ", self.lloc).as_ref();
+        out_str += format!("LTC: {}, ", color(&self.ltc)).as_ref();
+        out_str += format!("LBC: {}, ", color(&self.lbc)).as_ref();
+        out_str += format!("LXSHDL: {}, ", self.lxshdl).as_ref();
+        out_str += format!("LXSOFL: {}, ", self.lxsofl).as_ref();
+        out_str += format!("[LXSHD: {}]", self.lxshd).as_ref();
+        write!(f, "[Label Subheader: {out_str}]")
+    }
+}
+impl NitfSegmentHeader for LabelHeader {
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.la.read(reader, session, 2u8, "LA")?;
+        self.lid.read(reader, session, 10u8, "LID")?;
+        self.security.read_version(reader, session, version)?;
+        self.encryp.read(reader, session, 1u8, "ENCRYP")?;
+        self.lfs.read(reader, session, 1u8, "LFS")?;
+        self.lcw.read(reader, session, 2u8, "LCW")?;
+        self.lch.read(reader, session, 2u8, "LCH")?;
+        self.ldlvl.read(reader, session, 3u8, "LDLVL")?;
+        self.lalvl.read(reader, session, 3u8, "LALVL")?;
+        self.lloc.read(reader, session, 10u8, "LLOC")?;
+        for (colors, name) in [(&mut self.ltc, "LTC"), (&mut self.lbc, "LBC")] {
+            colors.clear();
+            for _ in 0..3 {
+                let mut color: NitfField<String> = NitfField::default();
+                color.read(reader, session, 1u8, name)?;
+                colors.push(color);
+            }
+        }
+        self.lxshdl.read(reader, session, 5u8, "LXSHDL")?;
+        let extended_length = self.lxshdl.val;
+        if extended_length != 0 {
+            self.lxsofl.read(reader, session, 3u8, "LXSOFL")?;
+            self.lxshd
+                .read(reader, self.lxshdl.extended_data_length("LXSHDL")?, "LXSHD")?;
+        }
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.la.write(writer)?;
+        self.lid.write(writer)?;
+        self.security.write(writer)?;
+        self.encryp.write(writer)?;
+        self.lfs.write(writer)?;
+        self.lcw.write(writer)?;
+        self.lch.write(writer)?;
+        self.ldlvl.write(writer)?;
+        self.lalvl.write(writer)?;
+        self.lloc.write(writer)?;
+        for color in self.ltc.iter().chain(&self.lbc) {
+            color.write(writer)?;
+        }
+        self.lxshdl.write(writer)?;
+        if self.lxshdl.val != 0 {
+            self.lxsofl.write(writer)?;
+            self.lxshd.write(writer)?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 //! Header metadata definitions
 
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 
 pub mod data_extension_hdr;
 pub mod graphic_hdr;
 pub mod image_hdr;
+pub mod label_hdr;
 pub mod nitf_file_hdr;
 pub mod reserved_extension_hdr;
 pub mod text_hdr;
@@ -12,32 +13,71 @@ pub mod text_hdr;
 pub use data_extension_hdr::DataExtensionHeader;
 pub use graphic_hdr::GraphicHeader;
 pub use image_hdr::ImageHeader;
+pub use label_hdr::LabelHeader;
 pub use nitf_file_hdr::NitfHeader;
 pub use reserved_extension_hdr::ReservedExtensionHeader;
 pub use text_hdr::TextHeader;
 
+use crate::parse::ParseSession;
+use crate::types::NitfVersion;
 use crate::NitfResult;
 
 /// Nitf segment header interface definition
 ///
-/// Provide implementation for `read()`, `from_reader` defined automatically.
+/// Provide implementation for `read_version()` and `write()`, `read()` and
+/// `from_reader` defined automatically.
 pub trait NitfSegmentHeader
 where
     Self: Sized + Default,
 {
-    /// Read the segment info from stream
+    /// Read the segment info from stream, laid out for file format `version`
     ///
     /// # Parameters
     ///
     /// reader: Stream from which to read header information
+    ///
+    /// session: Handles fields which fail to parse, and collects their diagnostics
+    ///
+    /// version: File format version given by the file header
     #[allow(unused)]
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        panic!("Didn't implement read() method")
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        panic!("Didn't implement read_version() method")
+    }
+
+    /// Read the segment info from stream, laid out for NITF 2.1
+    ///
+    /// Fields which fail to parse are logged and given a default value.
+    ///
+    /// # Parameters
+    ///
+    /// reader: Stream from which to read header information
+    fn read<R: Read + Seek>(&mut self, reader: &mut R) -> NitfResult<()> {
+        self.read_version(reader, &mut ParseSession::default(), NitfVersion::default())
+    }
+
+    /// Write the segment info to stream
+    ///
+    /// # Parameters
+    ///
+    /// writer: Stream to which header information is written
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()>;
+
+    fn from_reader<R: Read + Seek>(reader: &mut R) -> NitfResult<Self> {
+        Self::from_reader_version(reader, &mut ParseSession::default(), NitfVersion::default())
     }
 
-    fn from_reader(reader: &mut File) -> NitfResult<Self> {
+    fn from_reader_version<R: Read + Seek>(
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<Self> {
         let mut hdr = Self::default();
-        hdr.read(reader)?;
+        hdr.read_version(reader, session, version)?;
         Ok(hdr)
     }
 }
@@ -1,11 +1,16 @@
 //! File header definition
 use std::fmt::Display;
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::segments::SegmentKind;
+use crate::types::{ExtendedSubheader, NitfField, NitfVersion, Security};
 use crate::{NitfError, NitfResult};
 /// Metadata for Nitf File Header
+///
+/// NITF 2.0 files have no `fbkgc`, a 27 character `oname`, and use the
+/// `numx` slot for the label segment count.
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct NitfHeader {
     /// File Profile Name
@@ -30,7 +35,7 @@ pub struct NitfHeader {
     pub fscpys: NitfField<u32>,
     /// Encryption
     pub encryp: NitfField<String>,
-    /// File Background Color
+    /// File Background Color (not present in NITF 2.0)
     pub fbkgc: Vec<NitfField<String>>, // TODO: Fix the parsing of this
     /// Originator's Name
     pub oname: NitfField<String>,
@@ -48,8 +53,10 @@ pub struct NitfHeader {
     pub nums: NitfField<u16>,
     /// Graphic Segments
     pub graphheaders: Vec<SubHeader>,
-    /// Reserved for future use
+    /// Number of Label Segments (NUML) in NITF 2.0, reserved for future use otherwise
     pub numx: NitfField<u16>,
+    /// Label Segments (NITF 2.0)
+    pub labelheaders: Vec<SubHeader>,
     /// Number of Text Segments
     pub numt: NitfField<u16>,
     /// Text Segments
@@ -89,11 +96,9 @@ impl Display for NitfHeader {
         out_str += format!("FSCOP: {}, ", self.fscop).as_ref();
         out_str += format!("FSCPYS: {}, ", self.fscpys).as_ref();
         out_str += format!("ENCRYP: {}, ", self.encryp).as_ref();
-        out_str += format!(
-            "FBKGC: [R: {}, G: {}, B: {}], ",
-            self.fbkgc[0], self.fbkgc[1], self.fbkgc[2],
-        )
-        .as_ref();
+        if let [red, green, blue] = &self.fbkgc[..] {
+            out_str += format!("FBKGC: [R: {red}, G: {green}, B: {blue}], ").as_ref();
+        }
         out_str += format!("ONAME: {}, ", self.oname).as_ref();
         out_str += format!("OPHONE: {}, ", self.ophone).as_ref();
         out_str += format!("FL: {}, ", self.fl).as_ref();
@@ -107,6 +112,9 @@ impl Display for NitfHeader {
             out_str += format!("[GRAPHHEADERS: {}], ", seg).as_ref()
         }
         out_str += format!("NUMX: {}, ", self.numx).as_ref();
+        for seg in &self.labelheaders {
+            out_str += format!("[LABELHEADER: {}], ", seg).as_ref()
+        }
         out_str += format!("NUMT: {}, ", self.numt).as_ref();
         for seg in &self.textheaders {
             out_str += format!("[TEXTHEADER: {}], ", seg).as_ref()
@@ -130,80 +138,220 @@ impl Display for NitfHeader {
 }
 
 impl NitfSegmentHeader for NitfHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.fhdr.read(reader, 4u8, "FHDR")?;
-        // Crash if file header is not NITF
-        if self.fhdr.string != "NITF" {
+    /// The file header identifies its own version from FHDR and FVER, so
+    /// `version` is ignored
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        _version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.fhdr.read(reader, session, 4u8, "FHDR")?;
+        // Crash if file header is not NITF or NSIF
+        if !matches!(self.fhdr.string.as_str(), "NITF" | "NSIF") {
             return Err(NitfError::FileType(self.fhdr.string.clone()));
         }
-        self.fver.read(reader, 5u8, "FVER")?;
-        self.clevel.read(reader, 2u8, "CLEVEL")?;
-        self.stype.read(reader, 4u8, "STYPE")?;
-        self.ostaid.read(reader, 10u8, "OSTAID")?;
-        self.fdt.read(reader, 14u8, "FDT")?;
-        self.ftitle.read(reader, 80u8, "FTITLE")?;
-        self.security.read(reader)?;
-        self.fscop.read(reader, 5u8, "FSCOP")?;
-        self.fscpys.read(reader, 5u8, "FSCPYS")?;
-        self.encryp.read(reader, 1u8, "ENCRYP")?;
-        for _ in 0..3 {
-            let mut color: NitfField<String> = NitfField::default();
-            color.read(reader, 1u8, "READ")?;
-            self.fbkgc.push(color);
-        }
-
-        self.oname.read(reader, 24u8, "ONAME")?;
-        self.ophone.read(reader, 18u8, "OPHONE")?;
-        self.fl.read(reader, 12u8, "FL")?;
-        self.hl.read(reader, 6u8, "HL")?;
-        self.numi.read(reader, 3u8, "NUMI")?;
-        for _ in 0..self.numi.val {
+        self.fver.read(reader, session, 5u8, "FVER")?;
+        let version = NitfVersion::detect(&self.fhdr.string, &self.fver.string)?;
+        self.clevel.read(reader, session, 2u8, "CLEVEL")?;
+        self.stype.read(reader, session, 4u8, "STYPE")?;
+        self.ostaid.read(reader, session, 10u8, "OSTAID")?;
+        self.fdt.read(reader, session, 14u8, "FDT")?;
+        self.ftitle.read(reader, session, 80u8, "FTITLE")?;
+        self.security.read_version(reader, session, version)?;
+        self.fscop.read(reader, session, 5u8, "FSCOP")?;
+        self.fscpys.read(reader, session, 5u8, "FSCPYS")?;
+        self.encryp.read(reader, session, 1u8, "ENCRYP")?;
+        if version.is_nitf20() {
+            self.oname.read(reader, session, 27u8, "ONAME")?;
+        } else {
+            for _ in 0..3 {
+                let mut color: NitfField<String> = NitfField::default();
+                color.read(reader, session, 1u8, "FBKGC")?;
+                self.fbkgc.push(color);
+            }
+            self.oname.read(reader, session, 24u8, "ONAME")?;
+        }
+        self.ophone.read(reader, session, 18u8, "OPHONE")?;
+        self.fl.read(reader, session, 12u8, "FL")?;
+        self.hl.read(reader, session, 6u8, "HL")?;
+        self.numi.read(reader, session, 3u8, "NUMI")?;
+        for index in 0..self.numi.val as usize {
             let mut subheader = SubHeader::default();
-            subheader.read(reader, 6, 10)?;
+            subheader.read(reader, session, 6, 10, SegmentKind::Image, index)?;
             self.imheaders.push(subheader);
         }
 
-        self.nums.read(reader, 3u8, "NUMS")?;
-        for _ in 0..self.nums.val {
+        self.nums.read(reader, session, 3u8, "NUMS")?;
+        for index in 0..self.nums.val as usize {
             let mut subheader = SubHeader::default();
-            subheader.read(reader, 4, 6)?;
+            subheader.read(reader, session, 4, 6, SegmentKind::Graphic, index)?;
             self.graphheaders.push(subheader);
         }
 
-        self.numx.read(reader, 3u8, "NUMX")?;
-        self.numt.read(reader, 3u8, "NUMT")?;
-        for _ in 0..self.numt.val {
+        self.numx.read(reader, session, 3u8, "NUMX")?;
+        if version.is_nitf20() {
+            for index in 0..self.numx.val as usize {
+                let mut subheader = SubHeader::default();
+                subheader.read(reader, session, 4, 3, SegmentKind::Label, index)?;
+                self.labelheaders.push(subheader);
+            }
+        }
+        self.numt.read(reader, session, 3u8, "NUMT")?;
+        for index in 0..self.numt.val as usize {
             let mut subheader = SubHeader::default();
-            subheader.read(reader, 4, 5)?;
+            subheader.read(reader, session, 4, 5, SegmentKind::Text, index)?;
             self.textheaders.push(subheader);
         }
 
-        self.numdes.read(reader, 3u8, "NUMDES")?;
-        for _ in 0..self.numdes.val {
+        self.numdes.read(reader, session, 3u8, "NUMDES")?;
+        for index in 0..self.numdes.val as usize {
             let mut subheader = SubHeader::default();
-            subheader.read(reader, 4, 9)?;
+            subheader.read(reader, session, 4, 9, SegmentKind::DataExtension, index)?;
             self.dextheaders.push(subheader);
         }
 
-        self.numres.read(reader, 3u8, "NUMRES")?;
-        for _ in 0..self.numres.val {
+        self.numres.read(reader, session, 3u8, "NUMRES")?;
+        for index in 0..self.numres.val as usize {
             let mut subheader = SubHeader::default();
-            subheader.read(reader, 4, 7)?;
+            subheader.read(reader, session, 4, 7, SegmentKind::ReservedExtension, index)?;
             self.resheaders.push(subheader);
         }
 
-        self.udhdl.read(reader, 5u8, "UDHDL")?;
+        self.udhdl.read(reader, session, 5u8, "UDHDL")?;
         if self.udhdl.val != 0 {
-            self.udhofl.read(reader, 3u8, "UDHOFL")?;
+            self.udhofl.read(reader, session, 3u8, "UDHOFL")?;
             self.udhd
-                .read(reader, (self.udhdl.val - 3) as usize, "UDHD")?;
+                .read(reader, self.udhdl.extended_data_length("UDHDL")?, "UDHD")?;
         }
 
-        self.xhdl.read(reader, 5u8, "XHDL")?;
+        self.xhdl.read(reader, session, 5u8, "XHDL")?;
         if self.xhdl.val != 0 {
-            self.xhdlofl.read(reader, 3u8, "XHDLOFL")?;
-            self.xhd.read(reader, (self.xhdl.val - 3) as usize, "XHD")?;
+            self.xhdlofl.read(reader, session, 3u8, "XHDLOFL")?;
+            self.xhd
+                .read(reader, self.xhdl.extended_data_length("XHDL")?, "XHD")?;
+        }
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.fhdr.write(writer)?;
+        self.fver.write(writer)?;
+        self.clevel.write(writer)?;
+        self.stype.write(writer)?;
+        self.ostaid.write(writer)?;
+        self.fdt.write(writer)?;
+        self.ftitle.write(writer)?;
+        self.security.write(writer)?;
+        self.fscop.write(writer)?;
+        self.fscpys.write(writer)?;
+        self.encryp.write(writer)?;
+        for color in &self.fbkgc {
+            color.write(writer)?;
+        }
+        self.oname.write(writer)?;
+        self.ophone.write(writer)?;
+        self.fl.write(writer)?;
+        self.hl.write(writer)?;
+        self.numi.write(writer)?;
+        for subheader in &self.imheaders {
+            subheader.write(writer)?;
+        }
+        self.nums.write(writer)?;
+        for subheader in &self.graphheaders {
+            subheader.write(writer)?;
+        }
+        self.numx.write(writer)?;
+        for subheader in &self.labelheaders {
+            subheader.write(writer)?;
+        }
+        self.numt.write(writer)?;
+        for subheader in &self.textheaders {
+            subheader.write(writer)?;
+        }
+        self.numdes.write(writer)?;
+        for subheader in &self.dextheaders {
+            subheader.write(writer)?;
+        }
+        self.numres.write(writer)?;
+        for subheader in &self.resheaders {
+            subheader.write(writer)?;
+        }
+        self.udhdl.write(writer)?;
+        if self.udhdl.val != 0 {
+            self.udhofl.write(writer)?;
+            self.udhd.write(writer)?;
+        }
+        self.xhdl.write(writer)?;
+        if self.xhdl.val != 0 {
+            self.xhdlofl.write(writer)?;
+            self.xhd.write(writer)?;
+        }
+        Ok(())
+    }
+}
+impl NitfHeader {
+    /// File format version, as detected when the header was read
+    pub fn version(&self) -> NitfVersion {
+        self.security.version
+    }
+
+    /// Replace the segment counts and length tables with the given sizes
+    ///
+    /// Each slice holds the (subheader size, data size) of every segment of that type.
+    /// The header length (HL) and file length (FL) are recomputed to match.
+    /// Label segments are only allowed in NITF 2.0 files.
+    pub fn set_segment_sizes(
+        &mut self,
+        images: &[(u32, u64)],
+        graphics: &[(u32, u64)],
+        labels: &[(u32, u64)],
+        texts: &[(u32, u64)],
+        data_extensions: &[(u32, u64)],
+        reserved_extensions: &[(u32, u64)],
+    ) -> NitfResult<()> {
+        self.numi.set_num(images.len(), 3u8, "NUMI")?;
+        self.imheaders = SubHeader::table(SegmentKind::Image, images, 6, 10)?;
+        self.nums.set_num(graphics.len(), 3u8, "NUMS")?;
+        self.graphheaders = SubHeader::table(SegmentKind::Graphic, graphics, 4, 6)?;
+        if !labels.is_empty() && !self.version().is_nitf20() {
+            return Err(NitfError::Unsupported(format!(
+                "label segments in {}",
+                self.version()
+            )));
         }
+        self.numx.set_num(labels.len(), 3u8, "NUMX")?;
+        self.labelheaders = SubHeader::table(SegmentKind::Label, labels, 4, 3)?;
+        self.numt.set_num(texts.len(), 3u8, "NUMT")?;
+        self.textheaders = SubHeader::table(SegmentKind::Text, texts, 4, 5)?;
+        self.numdes.set_num(data_extensions.len(), 3u8, "NUMDES")?;
+        self.dextheaders = SubHeader::table(SegmentKind::DataExtension, data_extensions, 4, 9)?;
+        self.numres
+            .set_num(reserved_extensions.len(), 3u8, "NUMRES")?;
+        self.resheaders =
+            SubHeader::table(SegmentKind::ReservedExtension, reserved_extensions, 4, 7)?;
+
+        // HL and FL are fixed width, so the header can be sized before they are set
+        self.hl.set_num(0, 6u8, "HL")?;
+        self.fl.set_num(0, 12u8, "FL")?;
+        let mut encoded = vec![];
+        self.write(&mut encoded)?;
+        let header_length = encoded.len() as u64;
+        let file_length = [
+            images,
+            graphics,
+            labels,
+            texts,
+            data_extensions,
+            reserved_extensions,
+        ]
+        .iter()
+        .flat_map(|sizes| sizes.iter())
+        .fold(header_length, |total, (sh_size, item_size)| {
+            total + *sh_size as u64 + item_size
+        });
+        self.hl.set_num(header_length, 6u8, "HL")?;
+        self.fl.set_num(file_length, 12u8, "FL")?;
         Ok(())
     }
 }
@@ -219,12 +367,62 @@ pub struct SubHeader {
     pub item_size: NitfField<u64>,
 }
 impl SubHeader {
-    pub fn read(&mut self, reader: &mut File, sh_size: u64, item_size: u64) -> NitfResult<()> {
+    /// Read the length table entry of segment `index` (zero indexed) of type `kind`
+    pub fn read<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        sh_size: u64,
+        item_size: u64,
+        kind: SegmentKind,
+        index: usize,
+    ) -> NitfResult<()> {
+        let (sh_name, item_name) = Self::field_names(kind, index);
         self.subheader_size
-            .read(reader, sh_size, "SUBHEADER_SIZE")?;
-        self.item_size.read(reader, item_size, "ITEM_SIZE")?;
+            .read(reader, session, sh_size, &sh_name)?;
+        self.item_size
+            .read(reader, session, item_size, &item_name)?;
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.subheader_size.write(writer)?;
+        self.item_size.write(writer)?;
         Ok(())
     }
+
+    /// Names of the length table fields of segment `index` (zero indexed) of
+    /// type `kind`, such as `LISH001` and `LI001`
+    pub(crate) fn field_names(kind: SegmentKind, index: usize) -> (String, String) {
+        let (sh_name, item_name) = kind.length_fields();
+        (
+            format!("{sh_name}{:03}", index + 1),
+            format!("{item_name}{:03}", index + 1),
+        )
+    }
+
+    /// Encode the length table of segments of type `kind` from (subheader
+    /// size, data size) pairs
+    fn table(
+        kind: SegmentKind,
+        sizes: &[(u32, u64)],
+        sh_size: u64,
+        item_size: u64,
+    ) -> NitfResult<Vec<Self>> {
+        let mut table = Vec::with_capacity(sizes.len());
+        for (index, (subheader_size, data_size)) in sizes.iter().enumerate() {
+            let (sh_name, item_name) = Self::field_names(kind, index);
+            let mut subheader = Self::default();
+            subheader
+                .subheader_size
+                .set_num(subheader_size, sh_size, &sh_name)?;
+            subheader
+                .item_size
+                .set_num(data_size, item_size, &item_name)?;
+            table.push(subheader);
+        }
+        Ok(table)
+    }
 }
 impl Display for SubHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -235,3 +433,44 @@ impl Display for SubHeader {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{NitfBuilder, NitfHeaderBuilder};
+    use crate::Nitf;
+    use std::io::Cursor;
+
+    #[test]
+    fn length_table_names() -> NitfResult<()> {
+        let mut header = NitfHeaderBuilder::new()?.build();
+        let texts = [(285, 10), (285, 100_000)];
+        match header.set_segment_sizes(&[], &[], &[], &texts, &[], &[]) {
+            Err(NitfError::InvalidValue(field, value)) => {
+                assert_eq!((field.as_str(), value.as_str()), ("LT002", "100000"))
+            }
+            other => panic!("expected an invalid LT002, got {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_background_color() -> NitfResult<()> {
+        let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build()).build()?;
+        let mut bytes = vec![];
+        nitf.write_to(&mut bytes)?;
+        let nitf = Nitf::from_reader(&mut Cursor::new(&bytes))?;
+        let fbkgc = nitf.nitf_header.meta.fbkgc[1].offset() as usize;
+        bytes.truncate(fbkgc);
+        match Nitf::from_reader(&mut Cursor::new(bytes)) {
+            Err(NitfError::Truncated { location, .. }) => {
+                assert_eq!(
+                    (location.field.as_str(), location.offset),
+                    ("FBKGC", fbkgc as u64)
+                )
+            }
+            other => panic!("expected a truncated FBKGC, got {other:?}"),
+        }
+        Ok(())
+    }
+}
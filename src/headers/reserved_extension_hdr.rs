@@ -1,9 +1,10 @@
 //! Reserved Extension segment subheader definition
 use std::fmt::Display;
-use std::fs::File;
+use std::io::{Read, Seek, Write};
 
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::types::{ExtendedSubheader, NitfField, NitfVersion, Security};
 use crate::NitfResult;
 /// Metadata for Reserved Extension Segment
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
@@ -34,16 +35,33 @@ impl Display for ReservedExtensionHeader {
     }
 }
 impl NitfSegmentHeader for ReservedExtensionHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.re.read(reader, 2u8, "RE")?;
-        self.resid.read(reader, 25u8, "RESID")?;
-        self.resver.read(reader, 2u8, "RESVER")?;
-        self.security.read(reader)?;
-        self.resshl.read(reader, 4u8, "RESSHL")?;
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.re.read(reader, session, 2u8, "RE")?;
+        self.resid.read(reader, session, 25u8, "RESID")?;
+        self.resver.read(reader, session, 2u8, "RESVER")?;
+        self.security.read_version(reader, session, version)?;
+        self.resshl.read(reader, session, 4u8, "RESSHL")?;
         if self.resshl.val != 0 {
             self.resshf
                 .read(reader, self.resshl.val as usize, "RESSHF")?;
         }
         Ok(())
     }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.re.write(writer)?;
+        self.resid.write(writer)?;
+        self.resver.write(writer)?;
+        self.security.write(writer)?;
+        self.resshl.write(writer)?;
+        if self.resshl.val != 0 {
+            self.resshf.write(writer)?;
+        }
+        Ok(())
+    }
 }
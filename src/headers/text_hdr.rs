@@ -1,18 +1,18 @@
 //! Text segment definition
 use std::fmt::Display;
-use std::fs::File;
-
+use std::io::{Read, Seek, Write};
 use std::str::FromStr;
 
 use crate::headers::NitfSegmentHeader;
-use crate::types::{ExtendedSubheader, NitfField, Security};
+use crate::parse::ParseSession;
+use crate::types::{ExtendedSubheader, FieldValue, NitfField, NitfVersion, Security};
 use crate::{NitfError, NitfResult};
 /// Text Segment Metadata
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct TextHeader {
     /// File Part Type
     pub te: NitfField<String>,
-    /// Text Identifier
+    /// Text Identifier, 10 characters in NITF 2.0 which has no `txtalvl`
     pub textid: NitfField<String>,
     /// Text Attachment Level
     pub txtalvl: NitfField<u16>,
@@ -49,21 +49,49 @@ pub enum TextFormat {
 }
 
 impl NitfSegmentHeader for TextHeader {
-    fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.te.read(reader, 2u8, "TE")?;
-        self.textid.read(reader, 7u8, "TEXTID")?;
-        self.txtalvl.read(reader, 3u8, "TXTALVL")?;
-        self.txtdt.read(reader, 14u8, "TXTDT")?;
-        self.txttitl.read(reader, 80u8, "TXTTITL")?;
-        self.security.read(reader)?;
-        self.encryp.read(reader, 1u8, "ENCRYP")?;
-        self.txtfmt.read(reader, 3u8, "TXTFMT")?;
-        self.txshdl.read(reader, 5u8, "TXSHDL")?;
+    fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.te.read(reader, session, 2u8, "TE")?;
+        if version.is_nitf20() {
+            self.textid.read(reader, session, 10u8, "TEXTID")?;
+        } else {
+            self.textid.read(reader, session, 7u8, "TEXTID")?;
+            self.txtalvl.read(reader, session, 3u8, "TXTALVL")?;
+        }
+        self.txtdt.read(reader, session, 14u8, "TXTDT")?;
+        self.txttitl.read(reader, session, 80u8, "TXTTITL")?;
+        self.security.read_version(reader, session, version)?;
+        self.encryp.read(reader, session, 1u8, "ENCRYP")?;
+        self.txtfmt.read(reader, session, 3u8, "TXTFMT")?;
+        self.txshdl.read(reader, session, 5u8, "TXSHDL")?;
         let extended_length = self.txshdl.val;
         if extended_length != 0 {
-            self.txsofl.read(reader, 3u8, "TXSOFL")?;
+            self.txsofl.read(reader, session, 3u8, "TXSOFL")?;
             self.txshd
-                .read(reader, (extended_length - 3) as usize, "TXSHD")?;
+                .read(reader, self.txshdl.extended_data_length("TXSHDL")?, "TXSHD")?;
+        }
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        self.te.write(writer)?;
+        self.textid.write(writer)?;
+        if !self.security.version.is_nitf20() {
+            self.txtalvl.write(writer)?;
+        }
+        self.txtdt.write(writer)?;
+        self.txttitl.write(writer)?;
+        self.security.write(writer)?;
+        self.encryp.write(writer)?;
+        self.txtfmt.write(writer)?;
+        self.txshdl.write(writer)?;
+        if self.txshdl.val != 0 {
+            self.txsofl.write(writer)?;
+            self.txshd.write(writer)?;
         }
         Ok(())
     }
@@ -97,3 +125,15 @@ impl FromStr for TextFormat {
         }
     }
 }
+impl FieldValue for TextFormat {}
+impl Display for TextFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::MTF => "MTF",
+            Self::STA => "STA",
+            Self::UT1 => "UT1",
+            Self::U8S => "U8S",
+        };
+        write!(f, "{code}")
+    }
+}
@@ -0,0 +1,600 @@
+//! ITU-T T.4 decoding for `C1` and `M1` compressed bi-level images
+//!
+//! `COMRAT` selects one dimensional modified Huffman coding (`1D`), or two
+//! dimensional modified READ coding with a K factor of 2 (`2DS`) or 4 (`2DH`).
+//! Decoding the two dimensional variants is the same, as each line carries a tag
+//! bit saying how it is coded. Lines may start with EOL codes (with or without
+//! fill bits), and each block may end with an RTC.
+//!
+//! Blocks are coded separately. Without a mask table the blocks follow each
+//! other, each starting on a byte boundary. Like GDAL, white runs decode to 0
+//! and black runs to 1.
+use crate::headers::image_hdr::Mode;
+use crate::headers::ImageHeader;
+use crate::image::{BlockLayout, DecodedBlock, MaskTable};
+use crate::segments::SegmentData;
+use crate::{NitfError, NitfResult};
+
+/// Modified Huffman white run terminating codes, as (code length, code) for runs 0 to 63
+const WHITE_TERMINATING: [(u8, u16); 64] = [
+    (8, 0b00110101),
+    (6, 0b000111),
+    (4, 0b0111),
+    (4, 0b1000),
+    (4, 0b1011),
+    (4, 0b1100),
+    (4, 0b1110),
+    (4, 0b1111),
+    (5, 0b10011),
+    (5, 0b10100),
+    (5, 0b00111),
+    (5, 0b01000),
+    (6, 0b001000),
+    (6, 0b000011),
+    (6, 0b110100),
+    (6, 0b110101),
+    (6, 0b101010),
+    (6, 0b101011),
+    (7, 0b0100111),
+    (7, 0b0001100),
+    (7, 0b0001000),
+    (7, 0b0010111),
+    (7, 0b0000011),
+    (7, 0b0000100),
+    (7, 0b0101000),
+    (7, 0b0101011),
+    (7, 0b0010011),
+    (7, 0b0100100),
+    (7, 0b0011000),
+    (8, 0b00000010),
+    (8, 0b00000011),
+    (8, 0b00011010),
+    (8, 0b00011011),
+    (8, 0b00010010),
+    (8, 0b00010011),
+    (8, 0b00010100),
+    (8, 0b00010101),
+    (8, 0b00010110),
+    (8, 0b00010111),
+    (8, 0b00101000),
+    (8, 0b00101001),
+    (8, 0b00101010),
+    (8, 0b00101011),
+    (8, 0b00101100),
+    (8, 0b00101101),
+    (8, 0b00000100),
+    (8, 0b00000101),
+    (8, 0b00001010),
+    (8, 0b00001011),
+    (8, 0b01010010),
+    (8, 0b01010011),
+    (8, 0b01010100),
+    (8, 0b01010101),
+    (8, 0b00100100),
+    (8, 0b00100101),
+    (8, 0b01011000),
+    (8, 0b01011001),
+    (8, 0b01011010),
+    (8, 0b01011011),
+    (8, 0b01001010),
+    (8, 0b01001011),
+    (8, 0b00110010),
+    (8, 0b00110011),
+    (8, 0b00110100),
+];
+
+/// Modified Huffman white run makeup codes, for runs 64 to 1728 in steps of 64
+const WHITE_MAKEUP: [(u8, u16); 27] = [
+    (5, 0b11011),
+    (5, 0b10010),
+    (6, 0b010111),
+    (7, 0b0110111),
+    (8, 0b00110110),
+    (8, 0b00110111),
+    (8, 0b01100100),
+    (8, 0b01100101),
+    (8, 0b01101000),
+    (8, 0b01100111),
+    (9, 0b011001100),
+    (9, 0b011001101),
+    (9, 0b011010010),
+    (9, 0b011010011),
+    (9, 0b011010100),
+    (9, 0b011010101),
+    (9, 0b011010110),
+    (9, 0b011010111),
+    (9, 0b011011000),
+    (9, 0b011011001),
+    (9, 0b011011010),
+    (9, 0b011011011),
+    (9, 0b010011000),
+    (9, 0b010011001),
+    (9, 0b010011010),
+    (6, 0b011000),
+    (9, 0b010011011),
+];
+
+/// Modified Huffman black run terminating codes, for runs 0 to 63
+const BLACK_TERMINATING: [(u8, u16); 64] = [
+    (10, 0b0000110111),
+    (3, 0b010),
+    (2, 0b11),
+    (2, 0b10),
+    (3, 0b011),
+    (4, 0b0011),
+    (4, 0b0010),
+    (5, 0b00011),
+    (6, 0b000101),
+    (6, 0b000100),
+    (7, 0b0000100),
+    (7, 0b0000101),
+    (7, 0b0000111),
+    (8, 0b00000100),
+    (8, 0b00000111),
+    (9, 0b000011000),
+    (10, 0b0000010111),
+    (10, 0b0000011000),
+    (10, 0b0000001000),
+    (11, 0b00001100111),
+    (11, 0b00001101000),
+    (11, 0b00001101100),
+    (11, 0b00000110111),
+    (11, 0b00000101000),
+    (11, 0b00000010111),
+    (11, 0b00000011000),
+    (12, 0b000011001010),
+    (12, 0b000011001011),
+    (12, 0b000011001100),
+    (12, 0b000011001101),
+    (12, 0b000001101000),
+    (12, 0b000001101001),
+    (12, 0b000001101010),
+    (12, 0b000001101011),
+    (12, 0b000011010010),
+    (12, 0b000011010011),
+    (12, 0b000011010100),
+    (12, 0b000011010101),
+    (12, 0b000011010110),
+    (12, 0b000011010111),
+    (12, 0b000001101100),
+    (12, 0b000001101101),
+    (12, 0b000011011010),
+    (12, 0b000011011011),
+    (12, 0b000001010100),
+    (12, 0b000001010101),
+    (12, 0b000001010110),
+    (12, 0b000001010111),
+    (12, 0b000001100100),
+    (12, 0b000001100101),
+    (12, 0b000001010010),
+    (12, 0b000001010011),
+    (12, 0b000000100100),
+    (12, 0b000000110111),
+    (12, 0b000000111000),
+    (12, 0b000000100111),
+    (12, 0b000000101000),
+    (12, 0b000001011000),
+    (12, 0b000001011001),
+    (12, 0b000000101011),
+    (12, 0b000000101100),
+    (12, 0b000001011010),
+    (12, 0b000001100110),
+    (12, 0b000001100111),
+];
+
+/// Modified Huffman black run makeup codes, for runs 64 to 1728 in steps of 64
+const BLACK_MAKEUP: [(u8, u16); 27] = [
+    (10, 0b0000001111),
+    (12, 0b000011001000),
+    (12, 0b000011001001),
+    (12, 0b000001011011),
+    (12, 0b000000110011),
+    (12, 0b000000110100),
+    (12, 0b000000110101),
+    (13, 0b0000001101100),
+    (13, 0b0000001101101),
+    (13, 0b0000001001010),
+    (13, 0b0000001001011),
+    (13, 0b0000001001100),
+    (13, 0b0000001001101),
+    (13, 0b0000001110010),
+    (13, 0b0000001110011),
+    (13, 0b0000001110100),
+    (13, 0b0000001110101),
+    (13, 0b0000001110110),
+    (13, 0b0000001110111),
+    (13, 0b0000001010010),
+    (13, 0b0000001010011),
+    (13, 0b0000001010100),
+    (13, 0b0000001010101),
+    (13, 0b0000001011010),
+    (13, 0b0000001011011),
+    (13, 0b0000001100100),
+    (13, 0b0000001100101),
+];
+
+/// Makeup codes shared by both colors, for runs 1792 to 2560 in steps of 64
+const EXTENDED_MAKEUP: [(u8, u16); 13] = [
+    (11, 0b00000001000),
+    (11, 0b00000001100),
+    (11, 0b00000001101),
+    (12, 0b000000010010),
+    (12, 0b000000010011),
+    (12, 0b000000010100),
+    (12, 0b000000010101),
+    (12, 0b000000010110),
+    (12, 0b000000010111),
+    (12, 0b000000011100),
+    (12, 0b000000011101),
+    (12, 0b000000011110),
+    (12, 0b000000011111),
+];
+
+/// Longest code, which is also the length of the EOL code `000000000001`
+const MAX_CODE_BITS: u8 = 13;
+const EOL_BITS: u8 = 12;
+/// Number of EOL codes making up an RTC
+const RTC_EOLS: usize = 6;
+
+fn t4_error(reason: &str) -> NitfError {
+    NitfError::Fatal(format!("T.4 bi-level data, {reason}"))
+}
+
+/// Run length codes of one color, indexed by code length and code
+struct RunCodes {
+    /// `(run, is terminating)` for each code, by `[length][code]`
+    lookup: Vec<Vec<Option<(u32, bool)>>>,
+}
+impl RunCodes {
+    fn new(terminating: &[(u8, u16); 64], makeup: &[(u8, u16); 27]) -> Self {
+        let mut lookup: Vec<Vec<Option<(u32, bool)>>> = (0..=MAX_CODE_BITS)
+            .map(|length| vec![None; 1 << length])
+            .collect();
+        for (run, (length, code)) in terminating.iter().enumerate() {
+            lookup[*length as usize][*code as usize] = Some((run as u32, true));
+        }
+        let makeups = makeup.iter().chain(&EXTENDED_MAKEUP);
+        for (i, (length, code)) in makeups.enumerate() {
+            lookup[*length as usize][*code as usize] = Some((64 * (i as u32 + 1), false));
+        }
+        Self { lookup }
+    }
+
+    /// Read a complete run, any makeup codes followed by a terminating code
+    fn read_run(&self, bits: &mut BitReader) -> NitfResult<u32> {
+        let mut total = 0;
+        loop {
+            let (run, terminating) = self.read_code(bits)?;
+            total += run;
+            if terminating {
+                return Ok(total);
+            }
+        }
+    }
+
+    fn read_code(&self, bits: &mut BitReader) -> NitfResult<(u32, bool)> {
+        let mut code = 0;
+        for length in 1..=MAX_CODE_BITS as usize {
+            code = (code << 1) | bits.bit()? as usize;
+            if let Some(entry) = self.lookup[length][code] {
+                return Ok(entry);
+            }
+        }
+        Err(t4_error("invalid run length code"))
+    }
+}
+
+/// Reads bits most significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits
+    pos: usize,
+}
+impl BitReader<'_> {
+    fn bit(&mut self) -> NitfResult<u8> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| t4_error("data ends before the last row"))?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    fn peek(&self, n: u8) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..n as usize {
+            let byte = self.data.get((self.pos + i) / 8)?;
+            value = (value << 1) | ((byte >> (7 - (self.pos + i) % 8)) & 1) as u32;
+        }
+        Some(value)
+    }
+
+    /// Consume an EOL code and the fill bits before it, if one comes next
+    fn eol(&mut self) -> bool {
+        let mut zeros = 0;
+        while self.peek(1) == Some(0) {
+            zeros += 1;
+            self.pos += 1;
+        }
+        if zeros >= EOL_BITS as usize - 1 && self.peek(1) == Some(1) {
+            self.pos += 1;
+            true
+        } else {
+            self.pos -= zeros;
+            false
+        }
+    }
+}
+
+/// Two dimensional coding modes (T.4 Table 4)
+enum Mode2d {
+    Pass,
+    Horizontal,
+    Vertical(i64),
+}
+
+fn read_mode(bits: &mut BitReader) -> NitfResult<Mode2d> {
+    // Codes: 1 V0, 011 VR1, 010 VL1, 001 H, 0001 P, 000011 VR2, 000010 VL2,
+    // 0000011 VR3, 0000010 VL3
+    let mut zeros = 0;
+    while bits.bit()? == 0 {
+        zeros += 1;
+        if zeros > 6 {
+            return Err(t4_error("unsupported two dimensional extension code"));
+        }
+    }
+    Ok(match zeros {
+        0 => Mode2d::Vertical(0),
+        1 => match bits.bit()? {
+            1 => Mode2d::Vertical(1),
+            _ => Mode2d::Vertical(-1),
+        },
+        2 => Mode2d::Horizontal,
+        3 => Mode2d::Pass,
+        4 => match bits.bit()? {
+            1 => Mode2d::Vertical(2),
+            _ => Mode2d::Vertical(-2),
+        },
+        5 => match bits.bit()? {
+            1 => Mode2d::Vertical(3),
+            _ => Mode2d::Vertical(-3),
+        },
+        _ => return Err(t4_error("unsupported two dimensional extension code")),
+    })
+}
+
+/// Decoder for the bi-level blocks of an image segment
+pub(crate) struct BilevelBlocks {
+    white: RunCodes,
+    black: RunCodes,
+    two_dimensional: bool,
+    /// Start and end of the coded data of each block (and band for `IMODE`
+    /// S), `None` if the block was omitted
+    ranges: Vec<Option<(u64, u64)>>,
+}
+impl BilevelBlocks {
+    pub(crate) fn new(
+        header: &ImageHeader,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        mask: Option<&MaskTable>,
+    ) -> NitfResult<Self> {
+        if layout.nbpp != 1 {
+            return Err(NitfError::InvalidValue(
+                "NBPP".to_string(),
+                format!("{} (bi-level compression)", layout.nbpp),
+            ));
+        }
+        if layout.nbands > 1 && layout.imode != Mode::S {
+            return Err(NitfError::Unsupported(format!(
+                "bi-level compression of {} bands with IMODE {}",
+                layout.nbands, layout.imode
+            )));
+        }
+        let two_dimensional = match header.comrat.string.as_str() {
+            "1D" => false,
+            "2DS" | "2DH" => true,
+            other => {
+                return Err(NitfError::InvalidValue(
+                    "COMRAT".to_string(),
+                    format!("{other} (bi-level compression)"),
+                ))
+            }
+        };
+        let mut blocks = Self {
+            white: RunCodes::new(&WHITE_TERMINATING, &WHITE_MAKEUP),
+            black: RunCodes::new(&BLACK_TERMINATING, &BLACK_MAKEUP),
+            two_dimensional,
+            ranges: vec![],
+        };
+        let n_records = MaskTable::n_records(layout);
+        let offsets: Vec<Option<u64>> = match mask.filter(|mask| !mask.bmr.is_empty()) {
+            Some(mask) => {
+                let n_blocks = layout.n_blocks() as usize;
+                (0..n_records)
+                    .map(|i| mask.block_offset(layout, (i % n_blocks) as u64, i / n_blocks))
+                    .collect()
+            }
+            None => {
+                // Blocks follow each other, so all but the last must be decoded
+                // to find them. This is done once, as the decoder is cached.
+                let start = mask.map_or(0, |mask| mask.imdatoff as u64);
+                let mut offsets = vec![Some(start)];
+                if n_records > 1 {
+                    let bytes =
+                        data.read_range(start, data.len().saturating_sub(start) as usize)?;
+                    let mut pos = 0;
+                    for _ in 1..n_records {
+                        let mut bits = BitReader {
+                            data: &bytes,
+                            pos: pos * 8,
+                        };
+                        blocks.decode_block(&mut bits, layout)?;
+                        pos = bits.pos.div_ceil(8);
+                        offsets.push(Some(start + pos as u64));
+                    }
+                }
+                offsets
+            }
+        };
+        // Each block ends where the next one in the data starts
+        let mut starts: Vec<u64> = offsets.iter().flatten().copied().collect();
+        starts.sort_unstable();
+        blocks.ranges = offsets
+            .into_iter()
+            .map(|offset| {
+                offset.map(|start| {
+                    let next = starts.partition_point(|other| *other <= start);
+                    (start, starts.get(next).copied().unwrap_or(data.len()))
+                })
+            })
+            .collect();
+        Ok(blocks)
+    }
+
+    /// Decode `band` of `block`, or `None` if it was omitted
+    pub(crate) fn decode(
+        &self,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        block: u64,
+        band: usize,
+    ) -> NitfResult<Option<DecodedBlock>> {
+        let index = match layout.imode {
+            Mode::S => band * layout.n_blocks() as usize + block as usize,
+            _ => block as usize,
+        };
+        let Some((start, end)) = self.ranges.get(index).copied().flatten() else {
+            return Ok(None);
+        };
+        let bytes = data.read_range(start, end.saturating_sub(start) as usize)?;
+        let mut bits = BitReader {
+            data: &bytes,
+            pos: 0,
+        };
+        let plane = self.decode_block(&mut bits, layout)?;
+        Ok(Some(DecodedBlock {
+            nbits: 1,
+            planes: vec![plane],
+        }))
+    }
+
+    /// Decode the rows of a block, leaving `bits` after its final EOLs
+    fn decode_block(&self, bits: &mut BitReader, layout: &BlockLayout) -> NitfResult<Vec<u32>> {
+        let width = layout.nppbh as usize;
+        let mut plane = Vec::with_capacity(layout.block_pixels() as usize);
+        // Changing elements of the reference line and the line being decoded
+        let mut reference: Vec<usize> = vec![];
+        let mut changes: Vec<usize> = vec![];
+        for _ in 0..layout.nppbv {
+            bits.eol();
+            let one_dimensional = !self.two_dimensional || bits.bit()? == 1;
+            changes.clear();
+            match one_dimensional {
+                true => self.decode_1d(bits, width, &mut changes)?,
+                false => self.decode_2d(bits, width, &reference, &mut changes)?,
+            }
+            let mut color = 0;
+            let mut start = 0;
+            for &change in changes.iter().chain([&width]) {
+                plane.extend(std::iter::repeat_n(color, change - start));
+                start = change;
+                color ^= 1;
+            }
+            std::mem::swap(&mut reference, &mut changes);
+        }
+        // Return to control (RTC) is six EOLs, with a tag bit after each for 2D
+        // coding. Fewer EOLs belong to the first row of the next block.
+        let end = bits.pos;
+        let mut eols = 0;
+        while eols < RTC_EOLS && bits.eol() {
+            if self.two_dimensional && bits.peek(1) == Some(1) {
+                bits.pos += 1;
+            }
+            eols += 1;
+        }
+        if eols < RTC_EOLS {
+            bits.pos = end;
+        }
+        Ok(plane)
+    }
+
+    fn codes(&self, color: u8) -> &RunCodes {
+        match color {
+            0 => &self.white,
+            _ => &self.black,
+        }
+    }
+
+    fn decode_1d(
+        &self,
+        bits: &mut BitReader,
+        width: usize,
+        changes: &mut Vec<usize>,
+    ) -> NitfResult<()> {
+        let (mut pos, mut color) = (0, 0);
+        while pos < width {
+            pos += self.codes(color).read_run(bits)? as usize;
+            if pos < width {
+                changes.push(pos);
+            }
+            color ^= 1;
+        }
+        if pos > width {
+            return Err(t4_error("runs longer than the row"));
+        }
+        Ok(())
+    }
+
+    fn decode_2d(
+        &self,
+        bits: &mut BitReader,
+        width: usize,
+        reference: &[usize],
+        changes: &mut Vec<usize>,
+    ) -> NitfResult<()> {
+        // a0 starts on an imaginary white pixel before the row
+        let mut a0: i64 = -1;
+        let mut color = 0u8;
+        while a0 < width as i64 {
+            // b1 is the first change on the reference line to the right of a0
+            // and to the opposite color, which happens at even indexes for white
+            let mut i = reference.partition_point(|b| (*b as i64) <= a0);
+            if i % 2 != color as usize {
+                i += 1;
+            }
+            let b1 = reference.get(i).map_or(width, |b| *b) as i64;
+            let b2 = reference.get(i + 1).map_or(width, |b| *b) as i64;
+            match read_mode(bits)? {
+                Mode2d::Pass => a0 = b2,
+                Mode2d::Horizontal => {
+                    let start = a0.max(0);
+                    let a1 = start + self.codes(color).read_run(bits)? as i64;
+                    let a2 = a1 + self.codes(color ^ 1).read_run(bits)? as i64;
+                    if a2 > width as i64 {
+                        return Err(t4_error("runs longer than the row"));
+                    }
+                    for change in [a1, a2] {
+                        if change < width as i64 {
+                            changes.push(change as usize);
+                        }
+                    }
+                    a0 = a2;
+                }
+                Mode2d::Vertical(offset) => {
+                    let a1 = b1 + offset;
+                    if a1 < a0.max(0) || a1 > width as i64 {
+                        return Err(t4_error("vertical mode change outside of the row"));
+                    }
+                    if a1 < width as i64 {
+                        changes.push(a1 as usize);
+                    }
+                    a0 = a1;
+                    color ^= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
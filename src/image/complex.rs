@@ -0,0 +1,259 @@
+//! Complex pixels of SAR images, from `C` pixels or paired bands
+use num_complex::Complex;
+use std::f64::consts::TAU;
+
+use crate::headers::image_hdr::PixelValueType;
+use crate::headers::ImageHeader;
+use crate::image::PixelBuffer;
+use crate::segments::NitfSegment;
+use crate::{NitfError, NitfResult};
+
+/// Bands (zero indexed) holding one channel of complex pixels
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ComplexBands {
+    /// A band of complex (`C`) pixels
+    Complex(usize),
+    /// In-phase (ISUBCAT `I`) and quadrature (`Q`) bands
+    IQ(usize, usize),
+    /// Magnitude (ISUBCAT `M`) and phase (`P`) bands
+    MP(usize, usize),
+}
+
+/// Complex pixels of a single channel, in row-major order
+///
+/// Integer in-phase and quadrature bands keep their integer values, while
+/// everything else is returned as floats.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComplexBuffer {
+    F32(Vec<Complex<f32>>),
+    F64(Vec<Complex<f64>>),
+    /// In-phase and quadrature bands of up to 16 bit signed integers
+    I16(Vec<Complex<i16>>),
+    /// In-phase and quadrature bands of 17 to 32 bit signed integers
+    I32(Vec<Complex<i32>>),
+}
+
+impl ComplexBuffer {
+    /// Number of pixels in the buffer
+    pub fn len(&self) -> usize {
+        match self {
+            Self::F32(values) => values.len(),
+            Self::F64(values) => values.len(),
+            Self::I16(values) => values.len(),
+            Self::I32(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Magnitude of each pixel, as `F64` for `F64` and `I32` buffers and `F32` otherwise
+    pub fn magnitude(&self) -> PixelBuffer {
+        match self {
+            Self::F32(values) => PixelBuffer::F32(values.iter().map(|v| v.norm()).collect()),
+            Self::F64(values) => PixelBuffer::F64(values.iter().map(|v| v.norm()).collect()),
+            Self::I16(values) => PixelBuffer::F32(
+                values
+                    .iter()
+                    .map(|v| Complex::new(v.re as f32, v.im as f32).norm())
+                    .collect(),
+            ),
+            Self::I32(values) => PixelBuffer::F64(
+                values
+                    .iter()
+                    .map(|v| Complex::new(v.re as f64, v.im as f64).norm())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Phase of each pixel in radians, within [-π, π]
+    ///
+    /// The element type follows [magnitude()](Self::magnitude).
+    pub fn phase(&self) -> PixelBuffer {
+        match self {
+            Self::F32(values) => PixelBuffer::F32(values.iter().map(|v| v.arg()).collect()),
+            Self::F64(values) => PixelBuffer::F64(values.iter().map(|v| v.arg()).collect()),
+            Self::I16(values) => PixelBuffer::F32(
+                values
+                    .iter()
+                    .map(|v| (v.im as f32).atan2(v.re as f32))
+                    .collect(),
+            ),
+            Self::I32(values) => PixelBuffer::F64(
+                values
+                    .iter()
+                    .map(|v| (v.im as f64).atan2(v.re as f64))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl NitfSegment<ImageHeader> {
+    /// Channels of complex pixels in the image
+    ///
+    /// Every band of a `C` image is a channel of its own. Otherwise bands are
+    /// paired by their subcategory (ISUBCAT), each `I` band followed by a `Q`
+    /// band and each `M` band by a `P` band.
+    pub fn complex_bands(&self) -> NitfResult<Vec<ComplexBands>> {
+        if self.meta.pvtype.val == PixelValueType::C {
+            return Ok((0..self.meta.bands.len())
+                .map(ComplexBands::Complex)
+                .collect());
+        }
+        let isubcat: Vec<&str> = self
+            .meta
+            .bands
+            .iter()
+            .map(|band| band.isubcat.string.as_str())
+            .collect();
+        let mut channels = vec![];
+        let mut band = 0;
+        while band < isubcat.len() {
+            let channel = match (isubcat[band], isubcat.get(band + 1)) {
+                ("I", Some(&"Q")) => ComplexBands::IQ(band, band + 1),
+                ("M", Some(&"P")) => ComplexBands::MP(band, band + 1),
+                (other, _) => {
+                    return Err(NitfError::InvalidValue(
+                        "ISUBCAT".to_string(),
+                        format!(
+                            "'{other}' of band {}, expected an I/Q or M/P pair",
+                            band + 1
+                        ),
+                    ))
+                }
+            };
+            channels.push(channel);
+            band += 2;
+        }
+        if channels.is_empty() {
+            return Err(NitfError::InvalidValue(
+                "NBANDS".to_string(),
+                "0".to_string(),
+            ));
+        }
+        Ok(channels)
+    }
+
+    /// Decode every complex channel of the image, see [complex_bands()](Self::complex_bands)
+    /// ```no_run
+    /// use std::path::Path;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../sicd.nitf")).unwrap();
+    /// let channels = nitf.image_segments[0].read_complex().unwrap();
+    /// let magnitude = channels[0].magnitude();
+    /// ```
+    pub fn read_complex(&self) -> NitfResult<Vec<ComplexBuffer>> {
+        let layout = self.layout()?;
+        self.read_complex_window(0, 0, layout.nrows, layout.ncols)
+    }
+
+    /// Decode a `rows` x `cols` window of every complex channel, starting at
+    /// pixel (`row0`, `col0`)
+    ///
+    /// Magnitude and phase bands are converted to complex floats. Integer phase
+    /// values are fractions of a full cycle over 2^NBPP, and float phase values
+    /// are in radians.
+    pub fn read_complex_window(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+    ) -> NitfResult<Vec<ComplexBuffer>> {
+        let decoder = self.decoder()?;
+        let mut channels = vec![];
+        for bands in self.complex_bands()? {
+            let channel = match bands {
+                ComplexBands::Complex(band) => {
+                    match decoder
+                        .read_window(row0, col0, rows, cols, &[band])?
+                        .remove(0)
+                    {
+                        PixelBuffer::C32(values) => ComplexBuffer::F32(values),
+                        other => return Err(unsupported("complex", &other)),
+                    }
+                }
+                ComplexBands::IQ(i, q) => {
+                    let mut pair = decoder.read_window(row0, col0, rows, cols, &[i, q])?;
+                    let (q, i) = (pair.remove(1), pair.remove(0));
+                    iq_pixels(i, q)?
+                }
+                ComplexBands::MP(m, p) => {
+                    let mut pair = decoder.read_window(row0, col0, rows, cols, &[m, p])?;
+                    let (p, m) = (pair.remove(1), pair.remove(0));
+                    self.mp_pixels(m, p)?
+                }
+            };
+            channels.push(channel);
+        }
+        Ok(channels)
+    }
+
+    /// Complex floats from magnitude and phase bands
+    fn mp_pixels(&self, m: PixelBuffer, p: PixelBuffer) -> NitfResult<ComplexBuffer> {
+        let magnitude = to_f64(&m).ok_or_else(|| unsupported("magnitude", &m))?;
+        let phase = to_f64(&p).ok_or_else(|| unsupported("phase", &p))?;
+        let cycle = match p {
+            PixelBuffer::F32(_) | PixelBuffer::F64(_) => TAU,
+            _ => 2f64.powi(self.meta.nbpp.val as i32),
+        };
+        let values = magnitude
+            .into_iter()
+            .zip(phase)
+            .map(|(m, p)| Complex::from_polar(m, p * TAU / cycle));
+        Ok(match (m, p) {
+            (PixelBuffer::F64(_), _) | (_, PixelBuffer::F64(_)) => {
+                ComplexBuffer::F64(values.collect())
+            }
+            _ => ComplexBuffer::F32(
+                values
+                    .map(|v| Complex::new(v.re as f32, v.im as f32))
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// Complex pixels from in-phase and quadrature bands of the same type
+fn iq_pixels(i: PixelBuffer, q: PixelBuffer) -> NitfResult<ComplexBuffer> {
+    fn zip<I: Copy, O>(i: &[I], q: &[I], f: impl Fn(I) -> O) -> Vec<Complex<O>> {
+        i.iter()
+            .zip(q)
+            .map(|(&i, &q)| Complex::new(f(i), f(q)))
+            .collect()
+    }
+    Ok(match (&i, &q) {
+        (PixelBuffer::F32(i), PixelBuffer::F32(q)) => ComplexBuffer::F32(zip(i, q, |v| v)),
+        (PixelBuffer::F64(i), PixelBuffer::F64(q)) => ComplexBuffer::F64(zip(i, q, |v| v)),
+        (PixelBuffer::I8(i), PixelBuffer::I8(q)) => ComplexBuffer::I16(zip(i, q, i16::from)),
+        (PixelBuffer::I16(i), PixelBuffer::I16(q)) => ComplexBuffer::I16(zip(i, q, |v| v)),
+        (PixelBuffer::I32(i), PixelBuffer::I32(q)) => ComplexBuffer::I32(zip(i, q, |v| v)),
+        _ => return Err(unsupported("I/Q", &i)),
+    })
+}
+
+/// Values of a real buffer as `f64`, or `None` for complex buffers
+fn to_f64(buffer: &PixelBuffer) -> Option<Vec<f64>> {
+    fn convert<T: Copy + Into<f64>>(values: &[T]) -> Option<Vec<f64>> {
+        Some(values.iter().map(|&v| v.into()).collect())
+    }
+    match buffer {
+        PixelBuffer::U8(values) => convert(values),
+        PixelBuffer::I8(values) => convert(values),
+        PixelBuffer::U16(values) => convert(values),
+        PixelBuffer::I16(values) => convert(values),
+        PixelBuffer::U32(values) => convert(values),
+        PixelBuffer::I32(values) => convert(values),
+        PixelBuffer::U64(values) => Some(values.iter().map(|&v| v as f64).collect()),
+        PixelBuffer::I64(values) => Some(values.iter().map(|&v| v as f64).collect()),
+        PixelBuffer::F32(values) => convert(values),
+        PixelBuffer::F64(values) => convert(values),
+        PixelBuffer::C32(_) => None,
+    }
+}
+
+fn unsupported(bands: &str, buffer: &PixelBuffer) -> NitfError {
+    NitfError::Unsupported(format!("{bands} bands of type {:?}", buffer.pixel_type()))
+}
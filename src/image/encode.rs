@@ -0,0 +1,376 @@
+//! Encoding pixel arrays as uncompressed image segments
+use crate::builder::ImageHeaderBuilder;
+use crate::headers::image_hdr::{Compression, Mode, PixelJustification};
+use crate::headers::ImageHeader;
+use crate::image::pixel::write_bits;
+use crate::image::{BlockLayout, MaskTable, Pixel, NOT_RECORDED};
+use crate::{NitfError, NitfResult};
+
+/// Largest block dimension chosen when no block size is given
+pub const DEFAULT_BLOCK_SIZE: u32 = 1024;
+
+/// Encode in-memory bands into an image subheader and uncompressed (`NC`) data
+///
+/// PVTYPE and NBPP follow from the element type (see [PixelType::to_header()](super::PixelType::to_header)),
+/// dimensions up to [DEFAULT_BLOCK_SIZE] are written as a single block and
+/// larger ones in blocks of that size, and bands are stored with `IMODE` B.
+/// Partial blocks are padded with the fill value, or zero without one.
+///
+/// With a [fill()](Self::fill) value the image is written masked (`NM`): blocks
+/// holding nothing but fill are omitted, and the fill value becomes the pad
+/// pixel, which readers substitute for the omitted blocks.
+/// ```
+/// use nitf_rs::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+/// use nitf_rs::image::{ImageEncoder, PixelBuffer};
+///
+/// // 12 bit samples of a 4 x 6 image, in 2 x 4 blocks of which one is all fill
+/// let pixels: Vec<u16> = (0..24).map(|i| if i % 6 < 4 && i < 12 { 0 } else { i * 100 }).collect();
+/// let (header, data) = ImageEncoder::new(4, 6, &[&pixels])?
+///     .block_size(4, 2)?
+///     .abpp(12)?
+///     .fill(0)
+///     .encode(ImageHeaderBuilder::new()?.iid1("ENCODED")?)?;
+/// assert_eq!(header.nbpr.val, 2);
+///
+/// let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+///     .image(header, data)
+///     .build()?;
+/// assert_eq!(nitf.image_segments[0].read_band(0)?, PixelBuffer::U16(pixels));
+/// # Ok::<(), nitf_rs::NitfError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageEncoder<'a, T: Pixel> {
+    nrows: u32,
+    ncols: u32,
+    bands: Vec<&'a [T]>,
+    block_size: Option<(u32, u32)>,
+    imode: Mode,
+    abpp: Option<u8>,
+    fill: Option<T>,
+}
+
+impl<'a, T: Pixel> ImageEncoder<'a, T> {
+    /// Encoder for `bands` of `nrows` x `ncols` pixels each, in row-major order
+    pub fn new(nrows: u32, ncols: u32, bands: &[&'a [T]]) -> NitfResult<Self> {
+        let n_pixels = nrows as usize * ncols as usize;
+        if bands.is_empty() {
+            return Err(NitfError::InvalidValue(
+                "NBANDS".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if let Some(band) = bands.iter().position(|band| band.len() != n_pixels) {
+            return Err(NitfError::Fatal(format!(
+                "band {band} has {} pixels, expected {nrows} x {ncols}",
+                bands[band].len()
+            )));
+        }
+        Ok(Self {
+            nrows,
+            ncols,
+            bands: bands.to_vec(),
+            block_size: None,
+            imode: Mode::B,
+            abpp: None,
+            fill: None,
+        })
+    }
+
+    /// Number of Pixels Per Block Horizontal (NPPBH) and Vertical (NPPBV)
+    pub fn block_size(mut self, nppbh: u32, nppbv: u32) -> NitfResult<Self> {
+        if nppbh == 0 || nppbv == 0 {
+            return Err(NitfError::InvalidValue(
+                "NPPBH/NPPBV".to_string(),
+                format!("{nppbh}/{nppbv}"),
+            ));
+        }
+        self.block_size = Some((nppbh, nppbv));
+        Ok(self)
+    }
+
+    /// Image Mode (IMODE)
+    pub fn imode(mut self, imode: Mode) -> Self {
+        self.imode = imode;
+        self
+    }
+
+    /// Actual Bits-Per-Pixel (ABPP) of the values, which are right-justified
+    pub fn abpp(mut self, abpp: u8) -> NitfResult<Self> {
+        let (_, nbpp) = T::TYPE.to_header();
+        if abpp == 0 || abpp > nbpp {
+            return Err(NitfError::InvalidValue(
+                "ABPP".to_string(),
+                format!("{abpp} (NBPP {nbpp})"),
+            ));
+        }
+        self.abpp = Some(abpp);
+        Ok(self)
+    }
+
+    /// Fill value, used for padding and to omit blocks holding nothing else
+    pub fn fill(mut self, value: T) -> Self {
+        self.fill = Some(value);
+        self
+    }
+
+    /// Fill in the image fields of `header` and encode the image data
+    ///
+    /// Bands which were not added to `header` are added without a band
+    /// representation, or as `M` for a single band.
+    pub fn encode(&self, header: ImageHeaderBuilder) -> NitfResult<(ImageHeader, Vec<u8>)> {
+        let (pvtype, nbpp) = T::TYPE.to_header();
+        let default_size = |pixels: u32| pixels.clamp(1, DEFAULT_BLOCK_SIZE);
+        let (nppbh, nppbv) = self
+            .block_size
+            .unwrap_or((default_size(self.ncols), default_size(self.nrows)));
+        let ic = match self.fill {
+            Some(_) => Compression::NM,
+            None => Compression::NC,
+        };
+        let mut header = header
+            .size(self.nrows, self.ncols)?
+            .pvtype(pvtype)?
+            .nbpp(nbpp)?
+            .abpp(self.abpp.unwrap_or(nbpp), PixelJustification::R)?
+            .imode(self.imode.clone())?
+            .block_size(nppbh, nppbv)?
+            .ic(ic, "")?;
+        if header.n_bands() == 0 {
+            let irepband = if self.bands.len() == 1 { "M" } else { "" };
+            for _ in &self.bands {
+                header = header.band(irepband, "")?;
+            }
+        }
+        if header.n_bands() != self.bands.len() {
+            return Err(NitfError::InvalidValue(
+                "NBANDS".to_string(),
+                format!("{} (encoding {} bands)", header.n_bands(), self.bands.len()),
+            ));
+        }
+        let meta = header.build()?;
+        let layout = BlockLayout::from_header(&meta)?;
+        let data = self.encode_data(&layout)?;
+        Ok((meta, data))
+    }
+
+    /// Blocks of the image, preceded by a mask table if there is a fill value
+    fn encode_data(&self, layout: &BlockLayout) -> NitfResult<Vec<u8>> {
+        let n_blocks = layout.n_blocks() as usize;
+        let n_records = MaskTable::n_records(layout);
+        let value_mask = match layout.nbpp {
+            64 => u64::MAX,
+            nbpp => (1 << nbpp) - 1,
+        };
+        let fill_bits = self.fill.unwrap_or_default().to_bits() & value_mask;
+        let mut records = Vec::with_capacity(n_records);
+        // Whether each record holds nothing but fill, and whether it holds any
+        let mut all_fill = Vec::with_capacity(n_records);
+        let mut any_fill = Vec::with_capacity(n_records);
+        for record in 0..n_records {
+            let block = (record % n_blocks) as u64;
+            let bands = match layout.imode {
+                Mode::S => record / n_blocks..record / n_blocks + 1,
+                _ => 0..layout.nbands,
+            };
+            let record_start = layout.block_range(block, bands.start).0;
+            let record_bytes = match layout.imode {
+                Mode::S => layout.band_block_bytes(),
+                _ => layout.block_bytes(),
+            };
+            let mut bytes = vec![0u8; record_bytes as usize];
+            let (mut only_fill, mut has_fill) = (true, false);
+            let block_row = (block / layout.nbpr as u64) as u32;
+            let block_col = (block % layout.nbpr as u64) as u32;
+            for band in bands {
+                let band_start = (layout.block_range(block, band).0 - record_start) * 8;
+                for row in 0..layout.nppbv {
+                    let image_row = block_row * layout.nppbv + row;
+                    for col in 0..layout.nppbh {
+                        let image_col = block_col * layout.nppbh + col;
+                        let bits = match image_row < layout.nrows && image_col < layout.ncols {
+                            true => {
+                                let index =
+                                    image_row as usize * layout.ncols as usize + image_col as usize;
+                                let bits = self.bands[band][index].to_bits() & value_mask;
+                                only_fill &= bits == fill_bits;
+                                bits
+                            }
+                            false => fill_bits,
+                        };
+                        has_fill |= bits == fill_bits;
+                        let bit = band_start + layout.sample_bit(band, row, col);
+                        write_bits(&mut bytes, bit, layout.nbpp, bits);
+                    }
+                }
+            }
+            records.push(bytes);
+            all_fill.push(only_fill);
+            any_fill.push(has_fill);
+        }
+        let Some(fill) = self.fill else {
+            return Ok(records.concat());
+        };
+        // Masked data, omitting records which are all fill
+        let mut mask = MaskTable {
+            bmrlnth: 4,
+            tmrlnth: 4,
+            tpxcdlnth: layout.nbpp as u16,
+            tpxcd: Some(fill.to_bits() & value_mask),
+            ..Default::default()
+        };
+        let mut blocks = vec![];
+        for (i, record) in records.iter().enumerate() {
+            if all_fill[i] {
+                mask.bmr.push(NOT_RECORDED);
+                mask.tmr.push(NOT_RECORDED);
+                continue;
+            }
+            let offset = blocks.len() as u32;
+            mask.bmr.push(offset);
+            mask.tmr
+                .push(if any_fill[i] { offset } else { NOT_RECORDED });
+            blocks.extend_from_slice(record);
+        }
+        let mut data = vec![];
+        mask.write(&mut data)?;
+        mask.imdatoff = data.len() as u32;
+        data.clear();
+        mask.write(&mut data)?;
+        data.extend(blocks);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::builder::{NitfBuilder, NitfHeaderBuilder};
+    use crate::image::PixelBuffer;
+    use crate::segments::NitfSegment;
+    use crate::Nitf;
+
+    /// Encode, write and read back the image segment of `encoder`
+    fn round_trip<T: Pixel>(encoder: &ImageEncoder<T>) -> NitfResult<NitfSegment<ImageHeader>> {
+        let (header, data) = encoder.encode(ImageHeaderBuilder::new()?)?;
+        let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header, data)
+            .build()?;
+        let mut bytes = vec![];
+        nitf.write_to(&mut bytes)?;
+        let mut nitf = Nitf::from_reader(&mut Cursor::new(bytes))?;
+        Ok(nitf.image_segments.remove(0))
+    }
+
+    #[test]
+    fn image_modes() -> NitfResult<()> {
+        // 5 x 7 pixels in 3 x 4 blocks, so the last row and column of blocks are partial
+        let bands: Vec<Vec<u8>> = (0..3)
+            .map(|band| (0..35).map(|i| band * 50 + i).collect())
+            .collect();
+        let bands: Vec<&[u8]> = bands.iter().map(Vec::as_slice).collect();
+        for imode in [Mode::B, Mode::P, Mode::R, Mode::S] {
+            let encoder = ImageEncoder::new(5, 7, &bands)?
+                .block_size(3, 4)?
+                .imode(imode.clone());
+            let segment = round_trip(&encoder)?;
+            assert_eq!(segment.meta.imode.val, imode);
+            assert_eq!((segment.meta.nbpr.val, segment.meta.nbpc.val), (3, 2));
+            let expected: Vec<PixelBuffer> =
+                bands.iter().map(|b| PixelBuffer::U8(b.to_vec())).collect();
+            assert_eq!(segment.read_image()?, expected, "IMODE {imode}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn twelve_bit_samples() -> NitfResult<()> {
+        let pixels: Vec<u16> = (0..48).map(|i| (i * 331) % 4096).collect();
+        let encoder = ImageEncoder::new(6, 8, &[&pixels])?
+            .block_size(4, 4)?
+            .abpp(12)?;
+        let segment = round_trip(&encoder)?;
+        assert_eq!((segment.meta.nbpp.val, segment.meta.abpp.val), (16, 12));
+        assert_eq!(segment.meta.pjust.val, PixelJustification::R);
+        assert_eq!(segment.read_band(0)?, PixelBuffer::U16(pixels));
+        Ok(())
+    }
+
+    #[test]
+    fn signed_samples() -> NitfResult<()> {
+        let first: Vec<i16> = (0..20).map(|i| (i - 10) * 1000).collect();
+        let second: Vec<i16> = first.iter().map(|v| -v - 1).collect();
+        for imode in [Mode::S, Mode::R] {
+            let encoder = ImageEncoder::new(4, 5, &[&first, &second])?
+                .block_size(2, 3)?
+                .imode(imode);
+            let segment = round_trip(&encoder)?;
+            assert_eq!(
+                segment.read_image()?,
+                [
+                    PixelBuffer::I16(first.clone()),
+                    PixelBuffer::I16(second.clone())
+                ]
+            );
+        }
+        let values: Vec<f32> = (0..6).map(|i| i as f32 * -1.5).collect();
+        let segment = round_trip(&ImageEncoder::new(2, 3, &[&values])?)?;
+        assert_eq!(segment.read_band(0)?, PixelBuffer::F32(values));
+        Ok(())
+    }
+
+    #[test]
+    fn fill_omits_blocks() -> NitfResult<()> {
+        // The top left block of the first band is all fill
+        let first: Vec<u16> = (0..48)
+            .map(|i| if i / 8 < 4 && i % 8 < 4 { 7 } else { i + 100 })
+            .collect();
+        let second: Vec<u16> = (0..48).map(|i| i + 1000).collect();
+        let encoder = ImageEncoder::new(6, 8, &[&first, &second])?
+            .block_size(4, 4)?
+            .imode(Mode::S)
+            .fill(7);
+        let segment = round_trip(&encoder)?;
+        assert_eq!(segment.meta.ic.val, Compression::NM);
+        assert_eq!(segment.pad_pixel()?, Some(7));
+        assert!(!segment.block_present(0, 0, 0)?);
+        assert!(segment.block_present(0, 0, 1)?);
+        assert!(segment.block_present(1, 1, 0)?);
+        // Pad pixels of the partial bottom blocks are recorded and dropped on reading
+        let mask = segment.mask_table()?.unwrap();
+        assert_eq!(mask.tmr.iter().filter(|r| **r != NOT_RECORDED).count(), 4);
+        assert_eq!(
+            segment.read_image()?,
+            [PixelBuffer::U16(first), PixelBuffer::U16(second)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wide_pad_pixel() -> NitfResult<()> {
+        // 64 bit pad pixel code, with the right block all fill
+        let fill = -2.5f64;
+        let values: Vec<f64> = (0..8)
+            .map(|i| if i % 4 < 2 { i as f64 } else { fill })
+            .collect();
+        let encoder = ImageEncoder::new(2, 4, &[&values])?
+            .block_size(2, 2)?
+            .fill(fill);
+        let segment = round_trip(&encoder)?;
+        assert_eq!(segment.pad_pixel()?, Some(fill.to_bits()));
+        assert!(!segment.block_present(0, 1, 0)?);
+        assert_eq!(segment.read_band(0)?, PixelBuffer::F64(values));
+        // Codes longer than 64 bits keep their leading zero bytes
+        let mut mask = segment.mask_table()?.unwrap();
+        let blocked = segment.data.read_range(mask.imdatoff as u64, 16)?.to_vec();
+        mask.tpxcdlnth = 72;
+        mask.imdatoff += 1;
+        let mut data = vec![];
+        mask.write(&mut data)?;
+        assert_eq!(data[10..19], [[0].as_slice(), &fill.to_be_bytes()].concat());
+        data.extend(blocked);
+        assert_eq!(MaskTable::read(&data.into(), &segment.layout()?)?, mask);
+        Ok(())
+    }
+}
@@ -0,0 +1,1193 @@
+//! JPEG decoding for `C3`, `M3`, `I1`, `C5` and `M5` compressed images
+//!
+//! Implements the sequential DCT process of ITU-T T.81 with Huffman coding, for
+//! 8 and 12 bit samples, as profiled for NITF by MIL-STD-188-198A, and the
+//! lossless process (process 14) for 2 to 16 bit samples. Each block of
+//! the image is a separate JPEG stream, holding all bands as components (or a
+//! single band for `IMODE` S).
+//!
+//! Streams may omit their quantization and Huffman tables, in which case tables
+//! from the first stream of the image are used, followed by the default tables
+//! selected by the quality level of the NITF application marker or `COMRAT`
+//! (`00.1` to `00.5`).
+use crate::headers::image_hdr::{ImageRepresentation, Mode};
+use crate::headers::ImageHeader;
+use crate::image::{BlockLayout, DecodedBlock, MaskTable};
+use crate::segments::SegmentData;
+use crate::{NitfError, NitfResult};
+
+/// Natural (row-major) index of each zigzag position
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Default quantization tables of MIL-STD-188-198A, in zigzag order, for quality levels 1 to 5
+const DEFAULT_QUANTIZATION: [[u8; 64]; 5] = [
+    [
+        8, 72, 72, 72, 72, 72, 72, 72, 72, 72, 78, 74, 76, 74, 78, 89, 81, 84, 84, 81, 89, 106, 93,
+        94, 99, 94, 93, 106, 129, 111, 108, 116, 116, 108, 111, 129, 135, 128, 136, 145, 136, 128,
+        135, 155, 160, 177, 177, 160, 155, 193, 213, 228, 213, 193, 255, 255, 255, 255, 255, 255,
+        255, 255, 255, 255,
+    ],
+    [
+        8, 36, 36, 36, 36, 36, 36, 36, 36, 36, 39, 37, 38, 37, 39, 45, 41, 42, 42, 41, 45, 53, 47,
+        47, 50, 47, 47, 53, 65, 56, 54, 59, 59, 54, 56, 65, 68, 64, 69, 73, 69, 64, 68, 78, 81, 89,
+        89, 81, 78, 98, 108, 115, 108, 98, 130, 144, 144, 130, 178, 190, 178, 243, 243, 255,
+    ],
+    [
+        8, 10, 10, 10, 10, 10, 10, 10, 10, 10, 11, 10, 11, 10, 11, 13, 11, 12, 12, 11, 13, 15, 13,
+        13, 14, 13, 13, 15, 18, 16, 15, 16, 16, 15, 16, 18, 19, 18, 19, 21, 19, 18, 19, 22, 23, 25,
+        25, 23, 22, 27, 30, 32, 30, 27, 36, 40, 40, 36, 50, 53, 50, 68, 68, 91,
+    ],
+    [
+        8, 7, 7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 7, 8, 9, 8, 8, 8, 8, 9, 11, 9, 9, 10, 9, 9, 11, 13,
+        11, 11, 12, 12, 11, 11, 13, 14, 13, 14, 15, 14, 13, 14, 16, 16, 18, 18, 16, 16, 20, 22, 23,
+        22, 20, 26, 29, 29, 26, 36, 38, 36, 49, 49, 65,
+    ],
+    [
+        4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5, 5, 5, 6, 5, 5, 6, 5, 5, 6, 7, 6,
+        6, 6, 6, 6, 6, 7, 8, 7, 8, 8, 8, 7, 8, 9, 9, 10, 10, 9, 9, 11, 12, 13, 12, 11, 14, 16, 16,
+        14, 20, 21, 20, 27, 27, 36,
+    ],
+];
+
+/// Code counts and values of the default DC Huffman table (T.81 Table K.3)
+const DEFAULT_DC_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DEFAULT_DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+/// Code counts and values of the default AC Huffman table (T.81 Table K.5)
+const DEFAULT_AC_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 125];
+const DEFAULT_AC_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+const DHT: u8 = 0xC4;
+const DRI: u8 = 0xDD;
+const APP6: u8 = 0xE6;
+const APP14: u8 = 0xEE;
+/// Start of frame of the lossless process (T.81 process 14)
+const LOSSLESS_SOF: u8 = 0xC3;
+
+fn jpeg_error(reason: &str) -> NitfError {
+    NitfError::Fatal(format!("JPEG stream, {reason}"))
+}
+
+/// Canonical Huffman table, decoded with the procedure of T.81 F.2.2.3
+#[derive(Debug, Clone)]
+struct Huffman {
+    /// Largest code of each length, -1 if there are none
+    maxcode: [i32; 17],
+    /// Index into `values` of the first code of each length, minus that code
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+impl Huffman {
+    fn new(bits: &[u8], values: &[u8]) -> NitfResult<Self> {
+        let mut maxcode = [-1; 17];
+        let mut offset = [0; 17];
+        let (mut code, mut k) = (0i32, 0i32);
+        for length in 1..=16 {
+            let count = bits[length - 1] as i32;
+            offset[length] = k - code;
+            code += count;
+            k += count;
+            if count > 0 {
+                maxcode[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        if k as usize > values.len() {
+            return Err(jpeg_error("Huffman table is truncated"));
+        }
+        Ok(Self {
+            maxcode,
+            offset,
+            values: values[..k as usize].to_vec(),
+        })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> NitfResult<u8> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | bits.bit() as i32;
+            if code <= self.maxcode[length] {
+                let index = (self.offset[length] + code) as usize;
+                return Ok(self.values[index]);
+            }
+        }
+        Err(jpeg_error("invalid Huffman code"))
+    }
+}
+
+/// Quantization and Huffman tables, which may carry over between streams
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tables {
+    /// Quantization tables in zigzag order
+    quantization: [Option<[u16; 64]>; 4],
+    dc: [Option<Huffman>; 4],
+    ac: [Option<Huffman>; 4],
+    /// Quality level of the NITF application marker, 0 for custom tables
+    quality: Option<u8>,
+}
+impl Tables {
+    /// Default tables for a MIL-STD-188-198A quality level from 1 to 5
+    pub(crate) fn defaults(quality: u8) -> NitfResult<Self> {
+        let mut tables = Self::default();
+        if let Some(table) = DEFAULT_QUANTIZATION.get((quality as usize).wrapping_sub(1)) {
+            let table = table.map(|q| q as u16);
+            tables.quantization = [Some(table); 4];
+        }
+        let dc = Huffman::new(&DEFAULT_DC_BITS, &DEFAULT_DC_VALUES)?;
+        let ac = Huffman::new(&DEFAULT_AC_BITS, &DEFAULT_AC_VALUES)?;
+        tables.dc = [0, 1, 2, 3].map(|_| Some(dc.clone()));
+        tables.ac = [0, 1, 2, 3].map(|_| Some(ac.clone()));
+        Ok(tables)
+    }
+
+    /// Read the tables defined before the first scan of a stream
+    pub(crate) fn read(data: &[u8]) -> NitfResult<Self> {
+        let mut tables = Self::default();
+        let mut markers = Markers::new(data)?;
+        while let Some((marker, segment)) = markers.next_segment()? {
+            match marker {
+                SOS | EOI => break,
+                _ => tables.define(marker, segment)?,
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Use tables from `fallback` wherever none are defined
+    fn or(mut self, fallback: &Self) -> Self {
+        for (table, default) in self.quantization.iter_mut().zip(&fallback.quantization) {
+            *table = table.or(*default);
+        }
+        for (tables, defaults) in [(&mut self.dc, &fallback.dc), (&mut self.ac, &fallback.ac)] {
+            for (table, default) in tables.iter_mut().zip(defaults) {
+                if table.is_none() {
+                    *table = default.clone();
+                }
+            }
+        }
+        self
+    }
+
+    /// Apply a DQT, DHT or NITF application marker segment
+    fn define(&mut self, marker: u8, segment: &[u8]) -> NitfResult<()> {
+        match marker {
+            DQT => {
+                let mut rest = segment;
+                while !rest.is_empty() {
+                    let (precision, id) = ((rest[0] >> 4) as usize, (rest[0] & 0x0F) as usize);
+                    let size = 64 * (precision + 1);
+                    if id > 3 || rest.len() < 1 + size {
+                        return Err(jpeg_error("invalid quantization table"));
+                    }
+                    let mut table = [0u16; 64];
+                    for (k, q) in table.iter_mut().enumerate() {
+                        *q = match precision {
+                            0 => rest[1 + k] as u16,
+                            _ => u16::from_be_bytes([rest[1 + 2 * k], rest[2 + 2 * k]]),
+                        };
+                    }
+                    self.quantization[id] = Some(table);
+                    rest = &rest[1 + size..];
+                }
+            }
+            DHT => {
+                let mut rest = segment;
+                while !rest.is_empty() {
+                    if rest.len() < 17 {
+                        return Err(jpeg_error("invalid Huffman table"));
+                    }
+                    let (class, id) = (rest[0] >> 4, (rest[0] & 0x0F) as usize);
+                    let count: usize = rest[1..17].iter().map(|n| *n as usize).sum();
+                    if id > 3 || class > 1 || rest.len() < 17 + count {
+                        return Err(jpeg_error("invalid Huffman table"));
+                    }
+                    let table = Some(Huffman::new(&rest[1..17], &rest[17..17 + count])?);
+                    match class {
+                        0 => self.dc[id] = table,
+                        _ => self.ac[id] = table,
+                    }
+                    rest = &rest[17 + count..];
+                }
+            }
+            APP6 if segment.len() > 16 && segment.starts_with(b"NITF\0") => {
+                self.quality = Some(segment[16]);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the marker segments of a stream, starting after SOI
+struct Markers<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Markers<'a> {
+    fn new(data: &'a [u8]) -> NitfResult<Self> {
+        match data {
+            [0xFF, SOI, ..] => Ok(Self { data, pos: 2 }),
+            _ => Err(jpeg_error("missing start of image marker")),
+        }
+    }
+
+    /// Next marker and its segment payload (empty for EOI)
+    fn next_segment(&mut self) -> NitfResult<Option<(u8, &'a [u8])>> {
+        // Markers may be preceded by any number of fill bytes
+        while self.data.get(self.pos) == Some(&0xFF) && self.data.get(self.pos + 1) == Some(&0xFF) {
+            self.pos += 1;
+        }
+        let marker = match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, marker]) => *marker,
+            Some(_) => return Err(jpeg_error("expected a marker")),
+            None => return Ok(None),
+        };
+        self.pos += 2;
+        if marker == EOI {
+            return Ok(Some((marker, &[])));
+        }
+        let length = match self.data.get(self.pos..self.pos + 2) {
+            Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+            None => return Err(jpeg_error("truncated marker segment")),
+        };
+        let segment = self
+            .data
+            .get(self.pos + 2..self.pos + length)
+            .filter(|_| length >= 2)
+            .ok_or_else(|| jpeg_error("truncated marker segment"))?;
+        self.pos += length;
+        Ok(Some((marker, segment)))
+    }
+
+    /// Skip over entropy-coded data, up to the next marker other than RSTn
+    fn skip_entropy_coded(&mut self) {
+        let data = self.data;
+        while self.pos + 1 < data.len() {
+            if data[self.pos] == 0xFF && !matches!(data[self.pos + 1], 0x00 | 0xD0..=0xD7 | 0xFF) {
+                return;
+            }
+            self.pos += 1;
+        }
+        self.pos = data.len();
+    }
+}
+
+/// Length of the stream starting at the SOI marker at the start of `data`, through EOI
+pub(crate) fn stream_length(data: &[u8]) -> NitfResult<usize> {
+    let mut markers = Markers::new(data)?;
+    while let Some((marker, _)) = markers.next_segment()? {
+        match marker {
+            EOI => return Ok(markers.pos),
+            SOS => markers.skip_entropy_coded(),
+            _ => {}
+        }
+    }
+    Err(jpeg_error("missing end of image marker"))
+}
+
+/// Reads bits from entropy-coded data, removing stuffed zero bytes
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    n_bits: u32,
+}
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos,
+            acc: 0,
+            n_bits: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.n_bits <= 24 {
+            // Past the end of the data or at a marker, feed zeros
+            let mut byte = 0;
+            match self.data.get(self.pos..self.pos + 2) {
+                Some([0xFF, 0x00]) => {
+                    byte = 0xFF;
+                    self.pos += 2;
+                }
+                Some([0xFF, _]) => {}
+                _ => {
+                    if let Some(b) = self
+                        .data
+                        .get(self.pos)
+                        .filter(|_| self.pos + 1 < self.data.len())
+                    {
+                        byte = *b;
+                        self.pos += 1;
+                    }
+                }
+            }
+            self.acc |= (byte as u32) << (24 - self.n_bits);
+            self.n_bits += 8;
+        }
+    }
+
+    fn bit(&mut self) -> u32 {
+        self.bits(1)
+    }
+
+    fn bits(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        if self.n_bits < n {
+            self.fill();
+        }
+        let value = self.acc >> (32 - n);
+        self.acc <<= n;
+        self.n_bits -= n;
+        value
+    }
+
+    /// Read an `n` bit magnitude category value (T.81 F.2.2.1 EXTEND)
+    fn receive_extend(&mut self, n: u32) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        let value = self.bits(n) as i32;
+        if value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+
+    /// Discard buffered bits and consume the RSTn marker which should follow
+    fn restart(&mut self) -> NitfResult<()> {
+        self.acc = 0;
+        self.n_bits = 0;
+        while self.data.get(self.pos) == Some(&0xFF) && self.data.get(self.pos + 1) == Some(&0xFF) {
+            self.pos += 1;
+        }
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(jpeg_error("missing restart marker")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    tq: usize,
+    /// Samples, with width and height padded to whole MCUs
+    plane: Vec<u16>,
+    stride: usize,
+}
+
+/// Decoded stream, one plane of `width` x `height` samples per component
+#[derive(Debug, Clone)]
+pub(crate) struct JpegImage {
+    pub width: usize,
+    pub height: usize,
+    pub precision: u8,
+    pub planes: Vec<Vec<u16>>,
+    /// Whether the components are Y, Cb and Cr rather than the stored bands
+    pub ycbcr: bool,
+}
+
+/// Inverse DCT basis, `cos((2x + 1) u pi / 16)` scaled by `C(u) / 2`
+fn idct_basis() -> [[f32; 8]; 8] {
+    let mut basis = [[0f32; 8]; 8];
+    for (x, row) in basis.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 0.5 / 2f32.sqrt() } else { 0.5 };
+            *value = scale * (((2 * x + 1) * u) as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    basis
+}
+
+/// Decode a complete stream
+///
+/// Tables which are not defined in the stream are taken from `fallback`.
+pub(crate) fn decode(data: &[u8], fallback: &Tables) -> NitfResult<JpegImage> {
+    let mut tables = Tables::default();
+    let mut markers = Markers::new(data)?;
+    let mut frame: Option<(usize, usize, u8)> = None;
+    let mut lossless = false;
+    let mut components: Vec<Component> = vec![];
+    let mut restart_interval = 0;
+    let mut adobe_transform = None;
+    let mut app6_color = None;
+    let basis = idct_basis();
+    while let Some((marker, segment)) = markers.next_segment()? {
+        match marker {
+            EOI => break,
+            DQT | DHT => tables.define(marker, segment)?,
+            APP6 => {
+                tables.define(marker, segment)?;
+                if segment.len() > 17 && segment.starts_with(b"NITF\0") {
+                    app6_color = Some(segment[17]);
+                }
+            }
+            APP14 if segment.len() >= 12 && segment.starts_with(b"Adobe") => {
+                adobe_transform = Some(segment[11]);
+            }
+            DRI => {
+                let bytes = segment.get(..2).ok_or_else(|| jpeg_error("invalid DRI"))?;
+                restart_interval = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+            }
+            0xC0 | 0xC1 | LOSSLESS_SOF => {
+                if segment.len() < 6 {
+                    return Err(jpeg_error("invalid frame header"));
+                }
+                let precision = segment[0];
+                let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                let n = segment[5] as usize;
+                lossless = marker == LOSSLESS_SOF;
+                let supported = match lossless {
+                    true => (2..=16).contains(&precision),
+                    false => matches!(precision, 8 | 12),
+                };
+                if !supported || width == 0 || height == 0 {
+                    return Err(jpeg_error(&format!(
+                        "unsupported frame of {width} x {height} with {precision} bit samples"
+                    )));
+                }
+                if n == 0 || segment.len() < 6 + 3 * n {
+                    return Err(jpeg_error("invalid frame header"));
+                }
+                components = segment[6..6 + 3 * n]
+                    .chunks_exact(3)
+                    .map(|c| Component {
+                        id: c[0],
+                        h: (c[1] >> 4).max(1) as usize,
+                        v: (c[1] & 0x0F).max(1) as usize,
+                        tq: (c[2] & 0x03) as usize,
+                        plane: vec![],
+                        stride: 0,
+                    })
+                    .collect();
+                let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+                let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+                // Lossless MCUs are a single sample of each component, not 8 x 8 blocks
+                let unit = match lossless {
+                    true if h_max > 1 || v_max > 1 => {
+                        return Err(NitfError::Unsupported(
+                            "subsampled lossless JPEG".to_string(),
+                        ));
+                    }
+                    true => 1,
+                    false => 8,
+                };
+                let (mcus_x, mcus_y) =
+                    (width.div_ceil(unit * h_max), height.div_ceil(unit * v_max));
+                for component in &mut components {
+                    component.stride = mcus_x * component.h * unit;
+                    component.plane = vec![0; component.stride * mcus_y * component.v * unit];
+                }
+                frame = Some((width, height, precision));
+            }
+            0xC2 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(NitfError::Unsupported(format!(
+                    "JPEG process with SOF marker {marker:02X}"
+                )));
+            }
+            SOS => {
+                let (width, height, precision) =
+                    frame.ok_or_else(|| jpeg_error("scan before frame header"))?;
+                let resolved = tables.clone().or(fallback);
+                let pos = markers.pos;
+                let end = match lossless {
+                    true => decode_lossless_scan(
+                        data,
+                        pos,
+                        segment,
+                        &mut components,
+                        &resolved,
+                        (width, height, precision),
+                        restart_interval,
+                    )?,
+                    false => decode_scan(
+                        data,
+                        pos,
+                        segment,
+                        &mut components,
+                        &resolved,
+                        (width, height, precision),
+                        restart_interval,
+                        &basis,
+                    )?,
+                };
+                markers.pos = end;
+                markers.skip_entropy_coded();
+            }
+            _ => {}
+        }
+    }
+    let (width, height, precision) = frame.ok_or_else(|| jpeg_error("missing frame header"))?;
+    let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+    let planes = components
+        .iter()
+        .map(|c| upsample(c, (h_max, v_max), width, height))
+        .collect();
+    // Lossless streams keep the components as stored
+    let ycbcr = components.len() == 3
+        && !lossless
+        && match (app6_color, adobe_transform) {
+            (Some(color), _) => color == 2,
+            (None, Some(transform)) => transform != 0,
+            (None, None) => components.iter().map(|c| c.id).ne(*b"RGB"),
+        };
+    Ok(JpegImage {
+        width,
+        height,
+        precision,
+        planes,
+        ycbcr,
+    })
+}
+
+/// Scale the samples of a component to the full `width` x `height` of the image
+///
+/// Components subsampled by two horizontally, and optionally vertically, are
+/// interpolated like libjpeg does ("fancy upsampling"). Other factors are
+/// upsampled by replication.
+fn upsample(
+    c: &Component,
+    (h_max, v_max): (usize, usize),
+    width: usize,
+    height: usize,
+) -> Vec<u16> {
+    let at = |x: usize, y: usize| c.plane[y * c.stride + x] as u32;
+    let mut plane = Vec::with_capacity(width * height);
+    let (x_factor, y_factor) = (h_max / c.h, v_max / c.v);
+    let exact = h_max % c.h == 0 && v_max % c.v == 0;
+    if !exact || x_factor != 2 || y_factor > 2 {
+        for y in 0..height {
+            let row = y * c.v / v_max;
+            plane.extend((0..width).map(|x| at(x * c.h / h_max, row) as u16));
+        }
+        return plane;
+    }
+    // Weights of 3/4 for the nearest and 1/4 for the next nearest sample,
+    // with rounding biases alternating between even and odd outputs
+    let (in_width, in_height) = (width.div_ceil(2), height.div_ceil(y_factor));
+    let (shift, biases) = match y_factor {
+        1 => (2, [1, 2]),
+        _ => (4, [8, 7]),
+    };
+    let mut sums = vec![0; in_width];
+    for y in 0..height {
+        let row = y / y_factor;
+        for (x, sum) in sums.iter_mut().enumerate() {
+            *sum = match (y_factor, y % 2) {
+                (1, _) => at(x, row),
+                (_, 0) => 3 * at(x, row) + at(x, row.saturating_sub(1)),
+                _ => 3 * at(x, row) + at(x, (row + 1).min(in_height - 1)),
+            };
+        }
+        for x in 0..width {
+            let nearest = x / 2;
+            let next = match x % 2 {
+                0 => nearest.saturating_sub(1),
+                _ => (nearest + 1).min(in_width - 1),
+            };
+            let value = 3 * sums[nearest] + sums[next] + biases[x % 2];
+            plane.push((value >> shift) as u16);
+        }
+    }
+    plane
+}
+
+/// Decode the entropy-coded data of one scan starting at `pos`, returning the
+/// position at which it ends
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    data: &[u8],
+    pos: usize,
+    header: &[u8],
+    components: &mut [Component],
+    tables: &Tables,
+    (width, height, precision): (usize, usize, u8),
+    restart_interval: usize,
+    basis: &[[f32; 8]; 8],
+) -> NitfResult<usize> {
+    let n = *header
+        .first()
+        .ok_or_else(|| jpeg_error("invalid scan header"))? as usize;
+    if n == 0 || header.len() < 1 + 2 * n + 3 {
+        return Err(jpeg_error("invalid scan header"));
+    }
+    // (component index, DC table, AC table, quantization table)
+    let mut scan = Vec::with_capacity(n);
+    for selector in header[1..1 + 2 * n].chunks_exact(2) {
+        let index = components
+            .iter()
+            .position(|c| c.id == selector[0])
+            .ok_or_else(|| jpeg_error("scan of unknown component"))?;
+        let component = &components[index];
+        let missing = || jpeg_error("scan uses an undefined table");
+        let dc = tables.dc[(selector[1] >> 4) as usize & 3]
+            .as_ref()
+            .ok_or_else(missing)?;
+        let ac = tables.ac[(selector[1] & 0x0F) as usize & 3]
+            .as_ref()
+            .ok_or_else(missing)?;
+        let q = tables.quantization[component.tq].ok_or_else(missing)?;
+        scan.push((index, dc, ac, q));
+    }
+    let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+    // A single component scan is not interleaved, and covers just the component's blocks
+    let (mcus_x, mcus_y) = match scan.as_slice() {
+        [(index, ..)] => {
+            let c = &components[*index];
+            (
+                (width * c.h).div_ceil(h_max).div_ceil(8),
+                (height * c.v).div_ceil(v_max).div_ceil(8),
+            )
+        }
+        _ => (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max)),
+    };
+    let interleaved = scan.len() > 1;
+    let max_value = (1i32 << precision) - 1;
+    let shift = 1i32 << (precision - 1);
+    let mut bits = BitReader::new(data, pos);
+    let mut predictions = vec![0i32; scan.len()];
+    let mut coefficients = [0i32; 64];
+    let mut samples = [0f32; 64];
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            bits.restart()?;
+            predictions.fill(0);
+        }
+        let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+        for (i_scan, (index, dc, ac, q)) in scan.iter().enumerate() {
+            let component = &mut components[*index];
+            let (blocks_h, blocks_v) = match interleaved {
+                true => (component.h, component.v),
+                false => (1, 1),
+            };
+            for block_y in 0..blocks_v {
+                for block_x in 0..blocks_h {
+                    // Entropy decoding (T.81 F.2.2)
+                    coefficients.fill(0);
+                    let category = dc.decode(&mut bits)? as u32;
+                    predictions[i_scan] += bits.receive_extend(category);
+                    coefficients[0] = predictions[i_scan] * q[0] as i32;
+                    let mut k = 1;
+                    while k < 64 {
+                        let rs = ac.decode(&mut bits)?;
+                        let (run, size) = ((rs >> 4) as usize, (rs & 0x0F) as u32);
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        if k > 63 {
+                            return Err(jpeg_error("coefficient index out of range"));
+                        }
+                        coefficients[ZIGZAG[k]] = bits.receive_extend(size) * q[k] as i32;
+                        k += 1;
+                    }
+                    // Separable inverse DCT, columns then rows
+                    for x in 0..8 {
+                        for y in 0..8 {
+                            samples[y * 8 + x] = (0..8)
+                                .map(|v| basis[y][v] * coefficients[v * 8 + x] as f32)
+                                .sum();
+                        }
+                    }
+                    let x0 = (mcu_x * blocks_h + block_x) * 8;
+                    let y0 = (mcu_y * blocks_v + block_y) * 8;
+                    for y in 0..8 {
+                        let column = &samples[y * 8..y * 8 + 8];
+                        let row = (y0 + y) * component.stride + x0;
+                        for (out, basis) in component.plane[row..row + 8].iter_mut().zip(basis) {
+                            let value: f32 = basis.iter().zip(column).map(|(b, s)| b * s).sum();
+                            *out = (value.round() as i32 + shift).clamp(0, max_value) as u16;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(bits.pos)
+}
+
+/// Decode a lossless scan (T.81 Annex H) into the component planes
+fn decode_lossless_scan(
+    data: &[u8],
+    pos: usize,
+    header: &[u8],
+    components: &mut [Component],
+    tables: &Tables,
+    (width, height, precision): (usize, usize, u8),
+    restart_interval: usize,
+) -> NitfResult<usize> {
+    let n = *header
+        .first()
+        .ok_or_else(|| jpeg_error("invalid scan header"))? as usize;
+    if n == 0 || header.len() < 1 + 2 * n + 3 {
+        return Err(jpeg_error("invalid scan header"));
+    }
+    // Ss selects the predictor and the low bits of Ah/Al the point transform
+    let predictor = header[1 + 2 * n];
+    let point_transform = (header[3 + 2 * n] & 0x0F) as u32;
+    if !(1..=7).contains(&predictor) || point_transform >= precision as u32 {
+        return Err(jpeg_error("invalid lossless scan parameters"));
+    }
+    // (component index, DC table)
+    let mut scan = Vec::with_capacity(n);
+    for selector in header[1..1 + 2 * n].chunks_exact(2) {
+        let index = components
+            .iter()
+            .position(|c| c.id == selector[0])
+            .ok_or_else(|| jpeg_error("scan of unknown component"))?;
+        let table = tables.dc[(selector[1] >> 4) as usize & 3]
+            .as_ref()
+            .ok_or_else(|| jpeg_error("scan uses an undefined table"))?;
+        scan.push((index, table));
+    }
+    let bits_mask = (1i32 << (precision as u32 - point_transform)) - 1;
+    let initial = 1i32 << (precision as u32 - point_transform - 1);
+    let mut bits = BitReader::new(data, pos);
+    // Row at which the current restart interval started
+    let mut interval_row = 0;
+    let mut interval_col = 0;
+    for sample in 0..width * height {
+        if restart_interval > 0 && sample > 0 && sample % restart_interval == 0 {
+            bits.restart()?;
+            (interval_row, interval_col) = (sample / width, sample % width);
+        }
+        let (x, y) = (sample % width, sample / width);
+        for (index, table) in &scan {
+            let category = table.decode(&mut bits)? as u32;
+            let difference = match category {
+                16 => 32768,
+                _ => bits.receive_extend(category),
+            };
+            let component = &mut components[*index];
+            let stride = component.stride;
+            let at =
+                |x: usize, y: usize| (component.plane[y * stride + x] >> point_transform) as i32;
+            // The first row of a restart interval is predicted from the left
+            let prediction = if y == interval_row && x == interval_col {
+                initial
+            } else if y == interval_row {
+                at(x - 1, y)
+            } else if x == 0 {
+                at(x, y - 1)
+            } else {
+                let (a, b, c) = (at(x - 1, y), at(x, y - 1), at(x - 1, y - 1));
+                match predictor {
+                    1 => a,
+                    2 => b,
+                    3 => c,
+                    4 => a + b - c,
+                    5 => a + ((b - c) >> 1),
+                    6 => b + ((a - c) >> 1),
+                    _ => (a + b) >> 1,
+                }
+            };
+            let value = (prediction + difference) & 0xFFFF & bits_mask;
+            component.plane[y * stride + x] = (value << point_transform) as u16;
+        }
+    }
+    Ok(bits.pos)
+}
+
+/// Convert Y, Cb, Cr planes to R, G, B in place (JFIF conversion)
+fn ycbcr_to_rgb(image: &mut JpegImage) {
+    let max_value = ((1u32 << image.precision) - 1) as f32;
+    let center = (1u32 << (image.precision - 1)) as f32;
+    let [y, cb, cr] = &mut image.planes[..] else {
+        return;
+    };
+    for ((y, cb), cr) in y.iter_mut().zip(cb.iter_mut()).zip(cr.iter_mut()) {
+        let (luma, blue, red) = (*y as f32, *cb as f32 - center, *cr as f32 - center);
+        let convert = |value: f32| value.round().clamp(0.0, max_value) as u16;
+        *y = convert(luma + 1.402 * red);
+        *cb = convert(luma - 0.344136 * blue - 0.714136 * red);
+        *cr = convert(luma + 1.772 * blue);
+    }
+}
+
+/// Locations and shared tables of the JPEG streams of an image segment
+#[derive(Debug, Clone)]
+pub(crate) struct JpegBlocks {
+    /// Byte range of the stream of each block (and band for `IMODE` S),
+    /// `None` if the block was omitted
+    streams: Vec<Option<(u64, usize)>>,
+    tables: Box<Tables>,
+    /// Convert YCbCr streams to RGB
+    to_rgb: bool,
+}
+impl JpegBlocks {
+    pub(crate) fn new(
+        header: &ImageHeader,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        mask: Option<&MaskTable>,
+    ) -> NitfResult<Self> {
+        let n_streams = MaskTable::n_records(layout);
+        let streams: Vec<Option<(u64, usize)>> = match mask.filter(|mask| !mask.bmr.is_empty()) {
+            Some(mask) => {
+                let n_blocks = layout.n_blocks() as usize;
+                let offsets: Vec<Option<u64>> = (0..n_streams)
+                    .map(|i| mask.block_offset(layout, (i % n_blocks) as u64, i / n_blocks))
+                    .collect();
+                // Each stream ends at the latest where the next one starts
+                let mut starts: Vec<u64> = offsets.iter().flatten().copied().collect();
+                starts.sort_unstable();
+                offsets
+                    .iter()
+                    .map(|offset| {
+                        offset.map(|start| {
+                            let end = starts
+                                .iter()
+                                .find(|s| **s > start)
+                                .map_or(data.len(), |s| *s);
+                            (start, end.saturating_sub(start) as usize)
+                        })
+                    })
+                    .collect()
+            }
+            None => {
+                let start = mask.map_or(0, |mask| mask.imdatoff as u64);
+                let bytes = data.read_range(start, data.len().saturating_sub(start) as usize)?;
+                scan_streams(&bytes, n_streams)?
+                    .into_iter()
+                    .map(|(offset, len)| Some((start + offset as u64, len)))
+                    .collect()
+            }
+        };
+        // Tables of the first stream are shared with streams which omit theirs
+        let mut quality = comrat_quality(header.comrat.string.as_str());
+        let mut first_tables = Tables::default();
+        if let Some((offset, len)) = streams.iter().flatten().next() {
+            first_tables = Tables::read(&data.read_range(*offset, *len)?)?;
+            quality = first_tables.quality.filter(|q| *q > 0).or(quality);
+        }
+        let tables = Box::new(first_tables.or(&Tables::defaults(quality.unwrap_or(0))?));
+        let to_rgb = header.irep.val != ImageRepresentation::YCbCr601;
+        Ok(Self {
+            streams,
+            tables,
+            to_rgb,
+        })
+    }
+
+    /// Decode the stream holding `band` of `block`, or `None` if it was omitted
+    ///
+    /// Except for `IMODE` S the stream holds all bands of the block.
+    pub(crate) fn decode(
+        &self,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        block: u64,
+        band: usize,
+    ) -> NitfResult<Option<DecodedBlock>> {
+        let index = match layout.imode {
+            Mode::S => band * layout.n_blocks() as usize + block as usize,
+            _ => block as usize,
+        };
+        let Some((offset, len)) = self.streams.get(index).copied().flatten() else {
+            return Ok(None);
+        };
+        let mut image = decode(&data.read_range(offset, len)?, &self.tables)?;
+        if image.width != layout.nppbh as usize || image.height != layout.nppbv as usize {
+            return Err(jpeg_error(&format!(
+                "block of {} x {} pixels, expected {} x {}",
+                image.width, image.height, layout.nppbh, layout.nppbv
+            )));
+        }
+        if image.ycbcr && self.to_rgb {
+            ycbcr_to_rgb(&mut image);
+        }
+        Ok(Some(DecodedBlock {
+            nbits: image.precision as u32,
+            planes: image
+                .planes
+                .into_iter()
+                .map(|plane| plane.into_iter().map(u32::from).collect())
+                .collect(),
+        }))
+    }
+}
+
+/// Quality level given by a COMRAT of `00.1` to `00.5`
+fn comrat_quality(comrat: &str) -> Option<u8> {
+    match comrat {
+        "00.1" => Some(1),
+        "00.2" => Some(2),
+        "00.3" => Some(3),
+        "00.4" => Some(4),
+        "00.5" => Some(5),
+        _ => None,
+    }
+}
+
+/// Find the offsets and lengths of the first `n_streams` consecutive streams
+///
+/// Some writers put a few bytes before the first stream, so the start of the
+/// data is searched for an SOI marker.
+fn scan_streams(data: &[u8], n_streams: usize) -> NitfResult<Vec<(usize, usize)>> {
+    let mut streams = Vec::with_capacity(n_streams);
+    let mut pos = data
+        .windows(3)
+        .take(100)
+        .position(|bytes| bytes == [0xFF, SOI, 0xFF])
+        .ok_or_else(|| jpeg_error("no start of image marker in the image data"))?;
+    while streams.len() < n_streams {
+        let len = stream_length(&data[pos..])?;
+        streams.push((pos, len));
+        if streams.len() == n_streams {
+            break;
+        }
+        pos += len;
+        // Skip any padding between streams
+        pos += data[pos..]
+            .windows(2)
+            .position(|bytes| bytes == [0xFF, SOI])
+            .ok_or_else(|| {
+                jpeg_error(&format!(
+                    "found {} of {n_streams} block streams",
+                    streams.len()
+                ))
+            })?;
+    }
+    Ok(streams)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+    use crate::headers::image_hdr::{Compression, PixelJustification, PixelValueType};
+    use crate::image::{PixelBuffer, NOT_RECORDED};
+    use crate::segments::NitfSegment;
+
+    use super::*;
+
+    /// Largest difference from the source pixels of the quality 100 fixtures
+    const TOLERANCE: i32 = 2;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/testdata/jpeg/{name}", env!("CARGO_MANIFEST_DIR"));
+        std::fs::read(path).unwrap()
+    }
+
+    /// 8 bit `C3` image segment of 16 x 16 blocks
+    fn image(
+        nrows: u32,
+        ncols: u32,
+        irep: ImageRepresentation,
+        irepbands: &[&str],
+        data: Vec<u8>,
+    ) -> NitfResult<NitfSegment<ImageHeader>> {
+        let mut header = ImageHeaderBuilder::new()?
+            .size(nrows, ncols)?
+            .pvtype(PixelValueType::INT)?
+            .nbpp(8)?
+            .abpp(8, PixelJustification::R)?
+            .irep(irep)?
+            .imode(Mode::P)?
+            .block_size(16, 16)?
+            .ic(Compression::C3, "")?;
+        for irepband in irepbands {
+            header = header.band(irepband, "")?;
+        }
+        let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header.build()?, data)
+            .build()?;
+        Ok(nitf.image_segments.remove(0))
+    }
+
+    /// Single band `C5` or `M5` image segment of 8 x 8 blocks
+    fn lossless(
+        ncols: u32,
+        abpp: u8,
+        ic: Compression,
+        data: Vec<u8>,
+    ) -> NitfResult<NitfSegment<ImageHeader>> {
+        let header = ImageHeaderBuilder::new()?
+            .size(8, ncols)?
+            .pvtype(PixelValueType::INT)?
+            .nbpp(abpp.next_multiple_of(8))?
+            .abpp(abpp, PixelJustification::R)?
+            .irep(ImageRepresentation::MONO)?
+            .imode(Mode::B)?
+            .block_size(8, 8)?
+            .ic(ic, "")?
+            .band("M", "")?;
+        let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header.build()?, data)
+            .build()?;
+        Ok(nitf.image_segments.remove(0))
+    }
+
+    /// Source pixels of the lossless fixtures, with `pt` low bits cleared
+    fn lossless_pixels(ncols: u32, bits: u32, pt: u32) -> Vec<u32> {
+        (0..8 * ncols)
+            .map(|i| {
+                let (row, col) = (i / ncols, i % ncols);
+                let value = (col * 1237 + row * 2111 + col * row * 71) % (1 << bits);
+                value >> pt << pt
+            })
+            .collect()
+    }
+
+    fn u16_pixels(decoded: PixelBuffer) -> Vec<u32> {
+        let PixelBuffer::U16(pixels) = decoded else {
+            panic!("decoded {:?} pixels", decoded.pixel_type());
+        };
+        pixels.into_iter().map(u32::from).collect()
+    }
+
+    fn assert_close(decoded: &PixelBuffer, ncols: u32, expected: impl Fn(u32, u32) -> u32) {
+        let PixelBuffer::U8(pixels) = decoded else {
+            panic!("decoded {:?} pixels", decoded.pixel_type());
+        };
+        for (i, pixel) in pixels.iter().enumerate() {
+            let (row, col) = (i as u32 / ncols, i as u32 % ncols);
+            let difference = *pixel as i32 - expected(row, col) as i32;
+            assert!(
+                difference.abs() <= TOLERANCE,
+                "pixel ({row}, {col}) is {pixel}, expected {}",
+                expected(row, col)
+            );
+        }
+    }
+
+    #[test]
+    fn baseline_monochrome() -> NitfResult<()> {
+        let segment = image(
+            16,
+            16,
+            ImageRepresentation::MONO,
+            &["M"],
+            fixture("baseline_gray.jpg"),
+        )?;
+        assert_close(&segment.read_band(0)?, 16, |row, col| col * 8 + row * 4);
+        Ok(())
+    }
+
+    #[test]
+    fn baseline_rgb() -> NitfResult<()> {
+        let data = fixture("baseline_rgb.jpg");
+        let segment = image(16, 16, ImageRepresentation::RGB, &["R", "G", "B"], data)?;
+        let bands = segment.read_image()?;
+        assert_close(&bands[0], 16, |_, col| col * 12);
+        assert_close(&bands[1], 16, |row, _| row * 12);
+        assert_close(&bands[2], 16, |_, _| 128);
+        Ok(())
+    }
+
+    #[test]
+    fn restart_intervals_across_blocks() -> NitfResult<()> {
+        // Two streams with a restart marker after every MCU, found by scanning the data
+        let data = [fixture("restart_block0.jpg"), fixture("restart_block1.jpg")].concat();
+        let segment = image(16, 32, ImageRepresentation::MONO, &["M"], data)?;
+        let gradient = |row: u32, col: u32| col * 6 + row * 4;
+        // Windows within the second block reuse the stream offsets of the first read
+        let decoder = segment.decoder()?;
+        assert_close(&decoder.read_band(0)?, 32, gradient);
+        for (row0, col0) in [(2, 20), (9, 24)] {
+            let window = decoder.read_window(row0, col0, 4, 4, &[0])?;
+            assert_close(&window[0], 4, |row, col| gradient(row0 + row, col0 + col));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn missing_stream() {
+        let data = fixture("baseline_gray.jpg");
+        let segment = image(16, 32, ImageRepresentation::MONO, &["M"], data).unwrap();
+        assert!(segment.read_band(0).is_err());
+    }
+
+    #[test]
+    fn lossless_predictors() -> NitfResult<()> {
+        for predictor in 1..=7 {
+            let data = fixture(&format!("lossless_pred{predictor}.jpg"));
+            let segment = lossless(8, 12, Compression::C5, data)?;
+            let decoded = u16_pixels(segment.read_band(0)?);
+            assert_eq!(decoded, lossless_pixels(8, 12, 0), "predictor {predictor}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lossless_point_transform() -> NitfResult<()> {
+        // Predictor 7 on samples shifted right by 2 bits
+        let data = fixture("lossless_point_transform.jpg");
+        let segment = lossless(8, 12, Compression::C5, data)?;
+        assert_eq!(u16_pixels(segment.read_band(0)?), lossless_pixels(8, 12, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn lossless_restart_interval() -> NitfResult<()> {
+        // Restart markers every 5 samples, so intervals start within rows
+        let segment = lossless(8, 8, Compression::C5, fixture("lossless_restart.jpg"))?;
+        let PixelBuffer::U8(decoded) = segment.read_band(0)? else {
+            panic!("expected 8 bit pixels");
+        };
+        let expected: Vec<u8> = lossless_pixels(8, 8, 0)
+            .into_iter()
+            .map(|v| v as u8)
+            .collect();
+        assert_eq!(decoded, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn lossless_masked() -> NitfResult<()> {
+        // Mask table recording the first block and omitting the second
+        let table = MaskTable {
+            imdatoff: 18,
+            bmrlnth: 4,
+            bmr: vec![0, NOT_RECORDED],
+            ..Default::default()
+        };
+        let mut data = vec![];
+        table.write(&mut data)?;
+        data.extend(fixture("lossless_pred1.jpg"));
+        let segment = lossless(16, 12, Compression::M5, data)?;
+        let decoded = u16_pixels(segment.read_band(0)?);
+        let block = lossless_pixels(8, 12, 0);
+        for (row, pixels) in decoded.chunks_exact(16).enumerate() {
+            assert_eq!(pixels[..8], block[row * 8..row * 8 + 8]);
+            assert_eq!(pixels[8..], [0; 8]);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+//! JPEG 2000 decoding for `C8` and `M8` compressed images
+//!
+//! The image data holds a single codestream for the whole image, with one
+//! component per band. Blocks and windows are decoded by restricting the decode
+//! area of the codestream, and reduced resolutions by discarding wavelet
+//! decomposition levels, so neither needs the full frame to be decompressed.
+//!
+//! Decoding is done with the `jpeg2k` crate, using its Rust port of OpenJPEG.
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use jpeg2k::{DecodeArea, DecodeParameters, Image};
+
+use crate::headers::image_hdr::Mode;
+use crate::image::{BlockLayout, DecodedBlock, MaskTable};
+use crate::segments::SegmentData;
+use crate::{NitfError, NitfResult};
+
+/// Start of codestream marker, followed by the image and tile size marker
+const SOC_SIZ: [u8; 4] = [0xFF, 0x4F, 0xFF, 0x51];
+/// Coding style default marker
+const COD: u8 = 0x52;
+/// Start of tile-part marker, which ends the main header
+const SOT: u8 = 0x90;
+
+fn j2k_error(reason: impl std::fmt::Display) -> NitfError {
+    NitfError::Fatal(format!("JPEG 2000 codestream, {reason}"))
+}
+
+/// Codestream of an image segment, with what its main header declares
+#[derive(Debug, Clone)]
+pub(crate) struct Codestream {
+    /// Offset of the codestream within the segment data
+    offset: u64,
+    /// Number of wavelet decomposition levels
+    levels: u8,
+    /// Blocks which are omitted according to the mask table
+    omitted: Vec<bool>,
+    /// Codestream fetched from lazily read data, kept between decodes
+    fetched: OnceLock<Vec<u8>>,
+}
+impl Codestream {
+    pub(crate) fn new(
+        data: &SegmentData,
+        layout: &BlockLayout,
+        mask: Option<&MaskTable>,
+    ) -> NitfResult<Self> {
+        let offset = mask.map_or(0, |mask| mask.imdatoff as u64);
+        let omitted = match mask {
+            Some(mask) => (0..layout.n_blocks())
+                .map(|block| !mask.block_present(layout, block, 0))
+                .collect(),
+            None => vec![],
+        };
+        Ok(Self {
+            offset,
+            levels: decomposition_levels(data, offset)?,
+            omitted,
+            fetched: OnceLock::new(),
+        })
+    }
+
+    /// Bytes of the codestream, borrowed from in-memory data or fetched once
+    fn codestream<'a>(&'a self, data: &'a SegmentData) -> NitfResult<&'a [u8]> {
+        if let Some(bytes) = data.as_slice() {
+            return Ok(bytes.get(self.offset as usize..).unwrap_or_default());
+        }
+        if let Some(bytes) = self.fetched.get() {
+            return Ok(bytes);
+        }
+        let size = data.len().saturating_sub(self.offset);
+        let bytes = data.read_range(self.offset, size as usize)?.into_owned();
+        Ok(self.fetched.get_or_init(|| bytes))
+    }
+
+    /// Number of reduced resolution levels available
+    pub(crate) fn levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Decode the pixels `col0..col1` of rows `row0..row1` at resolution `level`
+    ///
+    /// The result covers the reduced resolution pixels `ceil(col0 / 2^level)` to
+    /// `ceil(col1 / 2^level)`, and likewise for rows, with one plane per band.
+    pub(crate) fn decode_area(
+        &self,
+        data: &SegmentData,
+        (row0, col0, row1, col1): (u32, u32, u32, u32),
+        level: u8,
+    ) -> NitfResult<(u32, DecodedBlock)> {
+        if level > self.levels {
+            return Err(NitfError::Fatal(format!(
+                "resolution level {level} of codestream with {} levels",
+                self.levels
+            )));
+        }
+        let codestream = self.codestream(data)?;
+        let parameters = DecodeParameters::new()
+            .reduce(level as u32)
+            .decode_area(Some(DecodeArea::new(col0, row0, col1, row1)));
+        let image = Image::from_bytes_with(codestream, parameters).map_err(j2k_error)?;
+        let components = image.components();
+        let Some(first) = components.first() else {
+            return Err(j2k_error("no components"));
+        };
+        let width = first.width();
+        let mut planes = Vec::with_capacity(components.len());
+        for component in components {
+            if component.width() != width || component.height() != first.height() {
+                return Err(j2k_error("subsampled components are not supported"));
+            }
+            // Signed samples are kept as two's complement bits
+            planes.push(component.data().iter().map(|v| *v as u32).collect());
+        }
+        let block = DecodedBlock {
+            nbits: first.precision(),
+            planes,
+        };
+        Ok((width, block))
+    }
+
+    /// Parts of the result of [decode_area()](Self::decode_area) covered by
+    /// omitted blocks, as ranges of rows and columns
+    pub(crate) fn omitted_areas(
+        &self,
+        layout: &BlockLayout,
+        (row0, col0, row1, col1): (u32, u32, u32, u32),
+        level: u8,
+    ) -> Vec<(Range<usize>, Range<usize>)> {
+        let reduce = |value: u32| value.div_ceil(1 << level) as usize;
+        let clip = |start: u32, end: u32, first: u32, last: u32| {
+            let (start, end) = (reduce(start.max(first)), reduce(end.min(last)));
+            start.saturating_sub(reduce(first))..end.saturating_sub(reduce(first))
+        };
+        let mut areas = vec![];
+        for (block, _) in self
+            .omitted
+            .iter()
+            .enumerate()
+            .filter(|(_, omitted)| **omitted)
+        {
+            let block_row = block as u32 / layout.nbpr;
+            let block_col = block as u32 % layout.nbpr;
+            let (top, left) = (block_row * layout.nppbv, block_col * layout.nppbh);
+            let rows = clip(top, top + layout.nppbv, row0, row1);
+            let cols = clip(left, left + layout.nppbh, col0, col1);
+            if !rows.is_empty() && !cols.is_empty() {
+                areas.push((rows, cols));
+            }
+        }
+        areas
+    }
+
+    /// Decode `band` of `block` at full resolution, or `None` if it was omitted
+    ///
+    /// Except for `IMODE` S the result holds all bands of the block.
+    pub(crate) fn decode(
+        &self,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        block: u64,
+        band: usize,
+    ) -> NitfResult<Option<DecodedBlock>> {
+        if self.omitted.get(block as usize) == Some(&true) {
+            return Ok(None);
+        }
+        let (block_row, block_col) = (
+            (block / layout.nbpr as u64) as u32,
+            (block % layout.nbpr as u64) as u32,
+        );
+        let (row0, col0) = (block_row * layout.nppbv, block_col * layout.nppbh);
+        let row1 = (row0 + layout.nppbv).min(layout.nrows);
+        let col1 = (col0 + layout.nppbh).min(layout.ncols);
+        let (width, decoded) = self.decode_area(data, (row0, col0, row1, col1), 0)?;
+        // Place the area in a full block, leaving zeros beyond the image
+        let nppbh = layout.nppbh as usize;
+        let mut planes: Vec<Vec<u32>> = decoded
+            .planes
+            .into_iter()
+            .map(|area| {
+                let mut plane = vec![0; layout.block_pixels() as usize];
+                for (i_row, row) in area.chunks_exact(width.max(1) as usize).enumerate() {
+                    plane[i_row * nppbh..i_row * nppbh + row.len()].copy_from_slice(row);
+                }
+                plane
+            })
+            .collect();
+        if layout.imode == Mode::S {
+            if band >= planes.len() {
+                return Err(j2k_error(format!(
+                    "{} components for band {band}",
+                    planes.len()
+                )));
+            }
+            planes = vec![planes.swap_remove(band)];
+        }
+        Ok(Some(DecodedBlock {
+            nbits: decoded.nbits,
+            planes,
+        }))
+    }
+}
+
+/// Number of decomposition levels of the COD marker in the main header
+///
+/// The markers are read one at a time, so only the main header up to COD is
+/// fetched from the data.
+fn decomposition_levels(data: &SegmentData, offset: u64) -> NitfResult<u8> {
+    let size = data.len().saturating_sub(offset);
+    let read = |pos: u64, len: usize| data.read_range(offset + pos, len);
+    if size < 4 || *read(0, 4)? != SOC_SIZ {
+        return Err(j2k_error("missing SOC and SIZ markers"));
+    }
+    let mut pos = 2;
+    while pos + 4 <= size {
+        let [0xFF, marker, l0, l1] = *read(pos, 4)? else {
+            break;
+        };
+        match marker {
+            // Scod, progression order, layers and multiple component transform
+            // precede the number of levels
+            COD if pos + 10 <= size => return Ok(read(pos + 9, 1)?[0]),
+            COD => return Err(j2k_error("truncated COD marker")),
+            SOT => break,
+            _ => pos += 2 + u16::from_be_bytes([l0, l1]) as u64,
+        }
+    }
+    Err(j2k_error("missing COD marker"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Main header with an empty SIZ marker, then COD with 5 levels and SOT
+    const HEADER: [u8; 24] = [
+        0xFF, 0x4F, 0xFF, 0x51, 0x00, 0x02, 0xFF, 0x52, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x05, 0x04, 0x04, 0x00, 0x01, 0xFF, 0x90, 0x00, 0x0A,
+    ];
+
+    #[test]
+    fn main_header_levels() -> NitfResult<()> {
+        assert_eq!(decomposition_levels(&HEADER.to_vec().into(), 0)?, 5);
+        // The codestream follows a mask table
+        let masked = [vec![0; 7], HEADER.to_vec()].concat();
+        assert_eq!(decomposition_levels(&masked.into(), 7)?, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_main_header() {
+        for (data, offset) in [
+            (HEADER[..12].to_vec(), 0),
+            (HEADER[..6].to_vec(), 0),
+            ([&HEADER[..6], &HEADER[20..]].concat(), 0),
+            (HEADER[1..].to_vec(), 0),
+            (HEADER.to_vec(), 30),
+        ] {
+            assert!(decomposition_levels(&data.into(), offset).is_err());
+        }
+    }
+}
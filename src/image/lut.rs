@@ -0,0 +1,120 @@
+//! Display of images through band look-up tables
+use crate::headers::image_hdr::{Band, ImageRepresentation};
+use crate::headers::ImageHeader;
+use crate::image::PixelBuffer;
+use crate::segments::NitfSegment;
+use crate::{NitfError, NitfResult};
+
+/// Display values of an image band mapped through its look-up tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayPixels {
+    /// Grey values, from a single table
+    Grey(Vec<u8>),
+    /// 16 bit grey values, from a pair of tables holding the high then low bytes
+    Grey16(Vec<u16>),
+    /// Red, green and blue values, from three tables
+    Rgb(Vec<[u8; 3]>),
+}
+
+impl Band {
+    /// Map `pixels` of the band through its look-up tables (LUTD)
+    ///
+    /// Pixel values are indices into the tables, and values beyond the last
+    /// entry map to the last entry.
+    pub fn apply_luts(&self, pixels: &PixelBuffer) -> NitfResult<DisplayPixels> {
+        let entry = |lut: &[u8], index: usize| lut.get(index).or(lut.last()).copied();
+        match &self.lutd[..] {
+            [] => Err(NitfError::InvalidValue(
+                "NLUTS".to_string(),
+                "0".to_string(),
+            )),
+            [grey] => Ok(DisplayPixels::Grey(map_indices(pixels, |index| {
+                entry(grey, index).unwrap_or_default()
+            })?)),
+            [high, low] => Ok(DisplayPixels::Grey16(map_indices(pixels, |index| {
+                let high = entry(high, index).unwrap_or_default();
+                let low = entry(low, index).unwrap_or_default();
+                u16::from_be_bytes([high, low])
+            })?)),
+            [red, green, blue] => Ok(DisplayPixels::Rgb(map_indices(pixels, |index| {
+                [red, green, blue].map(|lut| entry(lut, index).unwrap_or_default())
+            })?)),
+            luts => Err(NitfError::Unsupported(format!(
+                "display through {} look-up tables",
+                luts.len()
+            ))),
+        }
+    }
+}
+
+impl NitfSegment<ImageHeader> {
+    /// Decode an `RGB/LUT` or `MONO` image and map it through its look-up tables
+    ///
+    /// `RGB/LUT` images have three tables, giving [DisplayPixels::Rgb], and
+    /// `MONO` images one or two, giving [DisplayPixels::Grey] or
+    /// [DisplayPixels::Grey16].
+    /// ```no_run
+    /// use std::path::Path;
+    /// use nitf_rs::image::DisplayPixels;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+    /// if let DisplayPixels::Rgb(pixels) = nitf.image_segments[0].read_display().unwrap() {
+    ///     println!("First pixel: {:?}", pixels[0]);
+    /// }
+    /// ```
+    pub fn read_display(&self) -> NitfResult<DisplayPixels> {
+        let layout = self.layout()?;
+        self.read_display_window(0, 0, layout.nrows, layout.ncols)
+    }
+
+    /// Decode a `rows` x `cols` window, starting at pixel (`row0`, `col0`), and
+    /// map it through the look-up tables of the image
+    ///
+    /// See [read_display()](Self::read_display).
+    pub fn read_display_window(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+    ) -> NitfResult<DisplayPixels> {
+        let irep = &self.meta.irep.val;
+        if !matches!(
+            irep,
+            ImageRepresentation::RGBLUT | ImageRepresentation::MONO
+        ) {
+            return Err(NitfError::Unsupported(format!("display of IREP {irep}")));
+        }
+        let [band] = &self.meta.bands[..] else {
+            return Err(NitfError::InvalidValue(
+                "NBANDS".to_string(),
+                format!("{} for IREP {irep}", self.meta.bands.len()),
+            ));
+        };
+        let nluts = band.lutd.len();
+        let valid = match irep {
+            ImageRepresentation::RGBLUT => nluts == 3,
+            _ => nluts == 1 || nluts == 2,
+        };
+        if !valid {
+            return Err(NitfError::InvalidValue(
+                "NLUTS".to_string(),
+                format!("{nluts} for IREP {irep}"),
+            ));
+        }
+        let pixels = self.read_window(row0, col0, rows, cols, &[0])?.remove(0);
+        band.apply_luts(&pixels)
+    }
+}
+
+/// Map each pixel of an unsigned integer buffer, as a table index, through `f`
+fn map_indices<O>(pixels: &PixelBuffer, f: impl Fn(usize) -> O) -> NitfResult<Vec<O>> {
+    match pixels {
+        PixelBuffer::U8(values) => Ok(values.iter().map(|&v| f(v as usize)).collect()),
+        PixelBuffer::U16(values) => Ok(values.iter().map(|&v| f(v as usize)).collect()),
+        PixelBuffer::U32(values) => Ok(values.iter().map(|&v| f(v as usize)).collect()),
+        other => Err(NitfError::Unsupported(format!(
+            "look-up table indices of type {:?}",
+            other.pixel_type()
+        ))),
+    }
+}
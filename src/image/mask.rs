@@ -0,0 +1,181 @@
+//! Image data mask table
+//!
+//! Images with compression `NM` or `M*` start their data with a table
+//! recording where each block is stored, which blocks were omitted, and which
+//! blocks contain pad pixels.
+use std::io::Write;
+
+use crate::headers::image_hdr::Mode;
+use crate::image::BlockLayout;
+use crate::segments::SegmentData;
+use crate::{NitfError, NitfResult};
+
+/// Offset value marking a block which is not recorded
+pub const NOT_RECORDED: u32 = 0xFFFFFFFF;
+
+/// Image data mask table which prefixes masked image data
+#[derive(Default, Debug, Clone, Eq, PartialEq)]
+pub struct MaskTable {
+    /// Blocked Image Data Offset, from the start of the table
+    pub imdatoff: u32,
+    /// Block Mask Record Length
+    pub bmrlnth: u16,
+    /// Pad Pixel Mask Record Length
+    pub tmrlnth: u16,
+    /// Pad Output Pixel Code Length, in bits
+    pub tpxcdlnth: u16,
+    /// Pad Output Pixel Code
+    pub tpxcd: Option<u64>,
+    /// Block offsets from the start of the blocked image data, one per block
+    /// (per block and band for `IMODE` S). [NOT_RECORDED] marks omitted blocks.
+    pub bmr: Vec<u32>,
+    /// Offsets of blocks containing pad pixels, [NOT_RECORDED] for blocks without
+    pub tmr: Vec<u32>,
+}
+
+impl MaskTable {
+    /// Parse the table at the start of `data`
+    pub fn read(data: &SegmentData, layout: &BlockLayout) -> NitfResult<Self> {
+        let fixed = data.read_range(0, 10)?;
+        let mut table = Self {
+            imdatoff: u32::from_be_bytes([fixed[0], fixed[1], fixed[2], fixed[3]]),
+            bmrlnth: u16::from_be_bytes([fixed[4], fixed[5]]),
+            tmrlnth: u16::from_be_bytes([fixed[6], fixed[7]]),
+            tpxcdlnth: u16::from_be_bytes([fixed[8], fixed[9]]),
+            ..Default::default()
+        };
+        let mut offset = 10;
+        let tpxcd_bytes = table.tpxcd_bytes();
+        if tpxcd_bytes > 0 {
+            let code = data.read_range(offset, tpxcd_bytes)?;
+            // Codes are at most 64 bits, any bytes before those are zero
+            table.tpxcd = Some(code.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64));
+            offset += tpxcd_bytes as u64;
+        }
+        let n_records = Self::n_records(layout);
+        for (length, records) in [
+            (table.bmrlnth, &mut table.bmr),
+            (table.tmrlnth, &mut table.tmr),
+        ] {
+            match length {
+                0 => {}
+                4 => {
+                    let bytes = data.read_range(offset, n_records * 4)?;
+                    *records = bytes
+                        .chunks_exact(4)
+                        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect();
+                    offset += bytes.len() as u64;
+                }
+                _ => {
+                    return Err(NitfError::InvalidValue(
+                        "BMRLNTH/TMRLNTH".to_string(),
+                        length.to_string(),
+                    ))
+                }
+            }
+        }
+        let imdatoff = table.imdatoff as u64;
+        if imdatoff < offset || imdatoff > data.len() {
+            return Err(NitfError::InvalidValue(
+                "IMDATOFF".to_string(),
+                format!(
+                    "{imdatoff} (table of {offset} bytes, data of {})",
+                    data.len()
+                ),
+            ));
+        }
+        // Recorded blocks must start within the blocked image data
+        let blocked = data.len() - imdatoff;
+        for (name, records) in [("BMR", &table.bmr), ("TMR", &table.tmr)] {
+            let outside = |record: &&u32| **record != NOT_RECORDED && **record as u64 >= blocked;
+            if let Some(record) = records.iter().find(outside) {
+                return Err(NitfError::InvalidValue(
+                    name.to_string(),
+                    format!("{record} (blocked image data of {blocked} bytes)"),
+                ));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Write the table, in the layout it is read
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        writer.write_all(&self.imdatoff.to_be_bytes())?;
+        writer.write_all(&self.bmrlnth.to_be_bytes())?;
+        writer.write_all(&self.tmrlnth.to_be_bytes())?;
+        writer.write_all(&self.tpxcdlnth.to_be_bytes())?;
+        if let Some(code) = self.tpxcd {
+            let n_bytes = self.tpxcd_bytes();
+            let code = code.to_be_bytes();
+            let n_code = n_bytes.min(code.len());
+            writer.write_all(&vec![0; n_bytes - n_code])?;
+            writer.write_all(&code[code.len() - n_code..])?;
+        }
+        for record in self.bmr.iter().chain(&self.tmr) {
+            writer.write_all(&record.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Number of block records, which is per band for `IMODE` S
+    pub fn n_records(layout: &BlockLayout) -> usize {
+        let bands = match layout.imode {
+            Mode::S => layout.nbands,
+            _ => 1,
+        };
+        layout.n_blocks() as usize * bands
+    }
+
+    /// Size of the pad pixel code in bytes
+    fn tpxcd_bytes(&self) -> usize {
+        (self.tpxcdlnth as usize).div_ceil(8)
+    }
+
+    fn record_index(layout: &BlockLayout, block: u64, band: usize) -> usize {
+        match layout.imode {
+            Mode::S => band * layout.n_blocks() as usize + block as usize,
+            _ => block as usize,
+        }
+    }
+
+    /// Offset of `band` of block number `block` from the start of the segment
+    /// data, or `None` if the block is not recorded
+    ///
+    /// For interleaved modes the offset is the start of the block, regardless
+    /// of `band`.
+    pub fn block_offset(&self, layout: &BlockLayout, block: u64, band: usize) -> Option<u64> {
+        let imdatoff = self.imdatoff as u64;
+        if self.bmr.is_empty() {
+            return Some(imdatoff + layout.block_range(block, band).0);
+        }
+        match self.bmr.get(Self::record_index(layout, block, band)) {
+            None | Some(&NOT_RECORDED) => None,
+            Some(&offset) => {
+                let band_offset = match (layout.band_sequential(), &layout.imode) {
+                    (true, Mode::B) => band as u64 * layout.band_block_bytes(),
+                    _ => 0,
+                };
+                Some(imdatoff + offset as u64 + band_offset)
+            }
+        }
+    }
+
+    /// Whether `band` of block number `block` is recorded in the data
+    pub fn block_present(&self, layout: &BlockLayout, block: u64, band: usize) -> bool {
+        self.block_offset(layout, block, band).is_some()
+    }
+
+    /// Whether `band` of block number `block` contains pad pixels
+    ///
+    /// Without a pad pixel mask every block may contain pad pixels.
+    pub fn block_has_pad(&self, layout: &BlockLayout, block: u64, band: usize) -> bool {
+        if self.tmr.is_empty() {
+            return true;
+        }
+        !matches!(
+            self.tmr.get(Self::record_index(layout, block, band)),
+            None | Some(&NOT_RECORDED)
+        )
+    }
+}
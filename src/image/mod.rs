@@ -0,0 +1,1136 @@
+//! Pixel access for image segments
+//!
+//! Image data is decoded into one [PixelBuffer] per band, holding the pixels in
+//! row-major order. The element type of the buffer follows from the PVTYPE and
+//! NBPP of the subheader (see [PixelType]), e.g. 12 bit integers are returned as
+//! `u16`. Pad pixels in partial blocks beyond NROWS and NCOLS are dropped.
+//!
+//! For masked images (`NM` and `M*` compression) the [MaskTable] at the start of
+//! the data is used to locate blocks, and blocks which were omitted from the data
+//! are filled with the pad pixel value.
+//!
+//! Bi-level images compressed with ITU-T T.4 (`C1` and `M1`) are decoded to one
+//! `u8` per pixel, with black pixels set to 1, or as packed bits with
+//! [read_bilevel()](NitfSegment::read_bilevel).
+//!
+//! Vector quantized images (`C4` and `M4`), such as CIB, are decoded to `u8`
+//! through the lookup tables of their VQ header or `RPFIMG` TRE.
+//!
+//! JPEG compressed images (`C3`, `M3` and `I1`) and lossless JPEG (`C5` and `M5`)
+//! are decoded when the `jpeg` feature is enabled, with samples of more than 8
+//! bits returned as `u16`. Each block is decoded
+//! in full, so windowed reads of these images only save the blocks outside the
+//! window.
+//!
+//! JPEG 2000 compressed images (`C8` and `M8`) are decoded when the `jpeg2000`
+//! feature is enabled. Windows are decoded from just the part of the codestream
+//! they cover, and [read_window_at_level()](NitfSegment::read_window_at_level)
+//! decodes reduced resolution quick-looks without decompressing the full image.
+//!
+//! Images split across segments are read as one with [Nitf::mosaic()](crate::Nitf::mosaic),
+//! which places each segment by its attachment level (IALVL) and location (ILOC).
+//!
+//! Complex SAR pixels, from `C` images or from bands paired by their ISUBCAT
+//! (`I` and `Q`, or `M` and `P`), are read with [read_complex()](NitfSegment::read_complex).
+//!
+//! `RGB/LUT` and `MONO` images with band look-up tables are mapped to display
+//! values with [read_display()](NitfSegment::read_display).
+//! ```no_run
+//! use std::path::Path;
+//! use nitf_rs::image::PixelBuffer;
+//! let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+//! let bands = nitf.image_segments[0].read_image().unwrap();
+//! if let PixelBuffer::U8(pixels) = &bands[0] {
+//!     println!("First pixel: {}", pixels[0]);
+//! }
+//! ```
+mod bilevel;
+mod complex;
+mod encode;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+#[cfg(feature = "jpeg2000")]
+mod jpeg2000;
+mod lut;
+mod mask;
+mod mosaic;
+mod pixel;
+mod vq;
+
+pub use complex::{ComplexBands, ComplexBuffer};
+pub use encode::{ImageEncoder, DEFAULT_BLOCK_SIZE};
+pub use lut::DisplayPixels;
+pub use mask::{MaskTable, NOT_RECORDED};
+pub use mosaic::Mosaic;
+pub(crate) use pixel::with_pixel_type;
+pub use pixel::{Pixel, PixelBuffer, PixelType};
+
+use pixel::SampleFormat;
+
+use crate::headers::image_hdr::{Compression, Mode, PixelValueType};
+use crate::headers::ImageHeader;
+use crate::segments::{NitfSegment, SegmentData};
+use crate::{NitfError, NitfResult};
+
+/// Block layout of an image segment, derived from its subheader
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BlockLayout {
+    /// Number of significant rows
+    pub nrows: u32,
+    /// Number of significant columns
+    pub ncols: u32,
+    /// Number of bands
+    pub nbands: usize,
+    /// Number of blocks per row
+    pub nbpr: u32,
+    /// Number of blocks per column
+    pub nbpc: u32,
+    /// Number of pixels per block horizontally
+    pub nppbh: u32,
+    /// Number of pixels per block vertically
+    pub nppbv: u32,
+    /// Number of bits per pixel per band
+    pub nbpp: u32,
+    /// Image mode
+    pub imode: Mode,
+}
+
+impl BlockLayout {
+    /// Derive the layout from an image subheader
+    ///
+    /// NPPBH and NPPBV values of zero (used for images with a dimension larger
+    /// than 8192 in a single block) are replaced by the image size.
+    pub fn from_header(header: &ImageHeader) -> NitfResult<Self> {
+        let nbands = match header.nbands.val {
+            0 => header.xbands.val as usize,
+            n => n as usize,
+        };
+        let nrows = header.nrows.val;
+        let ncols = header.ncols.val;
+        let nbpr = header.nbpr.val as u32;
+        let nbpc = header.nbpc.val as u32;
+        let nppbh = match header.nppbh.val {
+            0 => ncols.div_ceil(nbpr.max(1)),
+            n => n as u32,
+        };
+        let nppbv = match header.nppbv.val {
+            0 => nrows.div_ceil(nbpc.max(1)),
+            n => n as u32,
+        };
+        let layout = Self {
+            nrows,
+            ncols,
+            nbands,
+            nbpr,
+            nbpc,
+            nppbh,
+            nppbv,
+            nbpp: header.nbpp.val as u32,
+            imode: header.imode.val.clone(),
+        };
+        let invalid =
+            |field: &str, value: String| Err(NitfError::InvalidValue(field.into(), value));
+        if layout.nbands == 0 {
+            return invalid("NBANDS", header.nbands.string.clone());
+        }
+        if layout.nbpp == 0 {
+            return invalid("NBPP", header.nbpp.string.clone());
+        }
+        if (nbpr as u64) * (nppbh as u64) < ncols as u64 {
+            return invalid("NBPR", format!("{nbpr} (NPPBH {nppbh}, NCOLS {ncols})"));
+        }
+        if (nbpc as u64) * (nppbv as u64) < nrows as u64 {
+            return invalid("NBPC", format!("{nbpc} (NPPBV {nppbv}, NROWS {nrows})"));
+        }
+        Ok(layout)
+    }
+
+    /// Total number of blocks in a band
+    pub fn n_blocks(&self) -> u64 {
+        self.nbpr as u64 * self.nbpc as u64
+    }
+
+    /// Number of pixels in a block, including padding
+    pub fn block_pixels(&self) -> u64 {
+        self.nppbh as u64 * self.nppbv as u64
+    }
+
+    /// Bands are stored in separate runs of bytes rather than interleaved
+    pub fn band_sequential(&self) -> bool {
+        self.nbands == 1 || matches!(self.imode, Mode::B | Mode::S)
+    }
+
+    /// Size in bytes of a single band of a block, for band sequential modes
+    pub fn band_block_bytes(&self) -> u64 {
+        (self.block_pixels() * self.nbpp as u64).div_ceil(8)
+    }
+
+    /// Size in bytes of a block with all of its bands
+    pub fn block_bytes(&self) -> u64 {
+        match self.band_sequential() {
+            true => self.band_block_bytes() * self.nbands as u64,
+            false => (self.block_pixels() * self.nbands as u64 * self.nbpp as u64).div_ceil(8),
+        }
+    }
+
+    /// Byte offset and size of the data holding `band` of block number `block`
+    ///
+    /// For interleaved modes this is the whole block, regardless of `band`.
+    pub fn block_range(&self, block: u64, band: usize) -> (u64, u64) {
+        let band = band as u64;
+        match (self.band_sequential(), &self.imode) {
+            (true, Mode::S) => (
+                (band * self.n_blocks() + block) * self.band_block_bytes(),
+                self.band_block_bytes(),
+            ),
+            (true, _) => (
+                block * self.block_bytes() + band * self.band_block_bytes(),
+                self.band_block_bytes(),
+            ),
+            (false, _) => (block * self.block_bytes(), self.block_bytes()),
+        }
+    }
+
+    /// Number of bits holding one row of a block
+    ///
+    /// This covers all bands for interleaved modes and a single band otherwise.
+    pub fn row_bits(&self) -> u64 {
+        let bands = match self.band_sequential() {
+            true => 1,
+            false => self.nbands as u64,
+        };
+        self.nppbh as u64 * bands * self.nbpp as u64
+    }
+
+    /// Bit offset of a sample within the data returned by [block_range()](Self::block_range)
+    pub fn sample_bit(&self, band: usize, row: u32, col: u32) -> u64 {
+        let (band, row, col) = (band as u64, row as u64, col as u64);
+        let (nppbh, nbands) = (self.nppbh as u64, self.nbands as u64);
+        let index = match (self.band_sequential(), &self.imode) {
+            (true, _) => row * nppbh + col,
+            (false, Mode::R) => (row * nbands + band) * nppbh + col,
+            (false, _) => (row * nppbh + col) * nbands + band,
+        };
+        index * self.nbpp as u64
+    }
+}
+
+impl NitfSegment<ImageHeader> {
+    /// Block layout of the image
+    pub fn layout(&self) -> NitfResult<BlockLayout> {
+        BlockLayout::from_header(&self.meta)
+    }
+
+    /// Native type the pixels are decoded into
+    pub fn pixel_type(&self) -> NitfResult<PixelType> {
+        PixelType::from_header(&self.meta.pvtype.val, self.meta.nbpp.val)
+    }
+
+    /// Image data mask table, for images with `NM` or `M*` compression
+    pub fn mask_table(&self) -> NitfResult<Option<MaskTable>> {
+        match self.meta.has_mask() {
+            true => Ok(Some(MaskTable::read(&self.data, &self.layout()?)?)),
+            false => Ok(None),
+        }
+    }
+
+    /// Decoder of the image pixels, which keeps its state between reads
+    ///
+    /// Each read method of the segment builds a decoder of its own, which for
+    /// compressed images can take a pass over all of the data to locate the
+    /// blocks. Reuse one decoder for many reads of the same image instead.
+    /// ```no_run
+    /// use std::path::Path;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+    /// let decoder = nitf.image_segments[0].decoder().unwrap();
+    /// for row0 in (0..1024).step_by(256) {
+    ///     let chip = decoder.read_window(row0, 0, 256, 256, &[0]).unwrap();
+    /// }
+    /// ```
+    pub fn decoder(&self) -> NitfResult<ImageDecoder<'_>> {
+        ImageDecoder::new(self)
+    }
+
+    /// Whether `band` of a block is recorded in the data
+    ///
+    /// Only masked images can omit blocks.
+    pub fn block_present(&self, block_row: u32, block_col: u32, band: usize) -> NitfResult<bool> {
+        self.decoder()?.block_present(block_row, block_col, band)
+    }
+
+    /// Value used for pad pixels, if the image records one
+    ///
+    /// Omitted blocks are filled with this value, or zero if there is none.
+    pub fn pad_pixel(&self) -> NitfResult<Option<u64>> {
+        Ok(self.mask_table()?.and_then(|mask| mask.tpxcd))
+    }
+
+    /// Decode all bands of the image
+    pub fn read_image(&self) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?.read_image()
+    }
+
+    /// Decode a single band (zero indexed) of the image
+    pub fn read_band(&self, band: usize) -> NitfResult<PixelBuffer> {
+        self.decoder()?.read_band(band)
+    }
+
+    /// Decode the given bands (zero indexed) of the image
+    pub fn read_bands(&self, bands: &[usize]) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?.read_bands(bands)
+    }
+
+    /// Decode a `rows` x `cols` window of `bands`, starting at pixel (`row0`, `col0`)
+    ///
+    /// Only the blocks intersecting the window are read, and for uncompressed
+    /// data only the rows of those blocks which fall within the window.
+    /// ```no_run
+    /// use std::path::Path;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+    /// // 256 x 256 chip of the first band
+    /// let chip = nitf.image_segments[0].read_window(1000, 2000, 256, 256, &[0]).unwrap();
+    /// ```
+    pub fn read_window(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?.read_window(row0, col0, rows, cols, bands)
+    }
+
+    /// Decode a window of `bands`, keeping every `row_stride`th row and `col_stride`th column
+    ///
+    /// The result holds `rows.div_ceil(row_stride)` x `cols.div_ceil(col_stride)`
+    /// pixels per band. Blocks which contain none of the kept pixels are skipped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_window_strided(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+        row_stride: u32,
+        col_stride: u32,
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?
+            .read_window_strided(row0, col0, rows, cols, bands, row_stride, col_stride)
+    }
+
+    /// Decode a window of `bands` at a reduced resolution `level`, where each
+    /// level halves the number of rows and columns
+    ///
+    /// The window is given in full resolution pixels, and level 0 is the same as
+    /// [read_window()](Self::read_window). JPEG 2000 images are decoded from the
+    /// lower resolutions of the codestream, which hold the pixels from
+    /// `row0 / 2^level` to `(row0 + rows) / 2^level` (rounded up) and likewise
+    /// for columns. Other images keep every `2^level`th row and column of the
+    /// window, as [read_window_strided()](Self::read_window_strided).
+    /// ```no_run
+    /// use std::path::Path;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+    /// let image = &nitf.image_segments[0];
+    /// let layout = image.layout().unwrap();
+    /// // Quick-look of the whole image at 1/8 scale
+    /// let bands: Vec<usize> = (0..layout.nbands).collect();
+    /// let overview = image.read_window_at_level(0, 0, layout.nrows, layout.ncols, &bands, 3);
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_window_at_level(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+        level: u8,
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?
+            .read_window_at_level(row0, col0, rows, cols, bands, level)
+    }
+
+    /// Number of reduced resolution levels stored in the image data
+    ///
+    /// This is the number of wavelet decomposition levels of JPEG 2000 images,
+    /// and `None` for other images, which have no stored lower resolutions.
+    pub fn resolution_levels(&self) -> NitfResult<Option<u8>> {
+        Ok(self.decoder()?.resolution_levels())
+    }
+
+    /// Decode a band of a bi-level (PVTYPE B) image as packed bits
+    ///
+    /// Each row starts on a byte boundary and holds one bit per pixel, with the
+    /// first pixel in the most significant bit. For `C1` and `M1` images set
+    /// bits are black. [read_band()](Self::read_band) returns the same pixels
+    /// expanded to one byte each.
+    pub fn read_bilevel(&self, band: usize) -> NitfResult<Vec<u8>> {
+        self.decoder()?.read_bilevel(band)
+    }
+
+    /// Decode the given bands of a single block, including any pad pixels
+    pub fn read_block(
+        &self,
+        block_row: u32,
+        block_col: u32,
+        bands: &[usize],
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        self.decoder()?.read_block(block_row, block_col, bands)
+    }
+
+    fn build_source(&self) -> NitfResult<Source> {
+        let meta = &self.meta;
+        let format = SampleFormat::new(
+            &meta.pvtype.val,
+            meta.nbpp.val,
+            meta.abpp.val,
+            &meta.pjust.val,
+        );
+        let mask = self.mask_table()?;
+        let pad = mask
+            .as_ref()
+            .and_then(|mask| mask.tpxcd.map(|code| (code, mask.tpxcdlnth as u32)));
+        let layout = self.layout()?;
+        let codec = self.codec(&layout, mask.as_ref())?;
+        Ok(Source {
+            layout,
+            mask,
+            format,
+            pad,
+            codec,
+        })
+    }
+
+    /// Decoder for the compression of the image, `None` if uncompressed
+    fn codec(&self, layout: &BlockLayout, mask: Option<&MaskTable>) -> NitfResult<Option<Codec>> {
+        match &self.meta.ic.val {
+            Compression::NC | Compression::NM => Ok(None),
+            Compression::C1 | Compression::M1 => Ok(Some(Codec::Bilevel(
+                bilevel::BilevelBlocks::new(&self.meta, &self.data, layout, mask)?,
+            ))),
+            Compression::C4 | Compression::M4 => {
+                Ok(Some(Codec::Vq(vq::VqBlocks::new(self, layout, mask)?)))
+            }
+            #[cfg(feature = "jpeg")]
+            Compression::C3
+            | Compression::M3
+            | Compression::I1
+            | Compression::C5
+            | Compression::M5 => Ok(Some(Codec::Jpeg(jpeg::JpegBlocks::new(
+                &self.meta, &self.data, layout, mask,
+            )?))),
+            #[cfg(not(feature = "jpeg"))]
+            ic @ (Compression::C3
+            | Compression::M3
+            | Compression::I1
+            | Compression::C5
+            | Compression::M5) => Err(NitfError::Unsupported(format!(
+                "image compression {ic} without the `jpeg` feature"
+            ))),
+            #[cfg(feature = "jpeg2000")]
+            Compression::C8 | Compression::M8 => Ok(Some(Codec::Jpeg2000(
+                jpeg2000::Codestream::new(&self.data, layout, mask)?,
+            ))),
+            #[cfg(not(feature = "jpeg2000"))]
+            ic @ (Compression::C8 | Compression::M8) => Err(NitfError::Unsupported(format!(
+                "image compression {ic} without the `jpeg2000` feature"
+            ))),
+            ic => Err(NitfError::Unsupported(format!("image compression {ic}"))),
+        }
+    }
+}
+
+/// Decoder of the pixels of an image segment, from [NitfSegment::decoder()]
+///
+/// The block layout, mask table and the location of compressed blocks are
+/// worked out once and reused by every read. The decoder borrows the segment,
+/// so its subheader and data can not change while the decoder is in use.
+pub struct ImageDecoder<'a> {
+    segment: &'a NitfSegment<ImageHeader>,
+    source: Source,
+}
+
+impl<'a> ImageDecoder<'a> {
+    /// Build the decoder state of `segment`
+    pub fn new(segment: &'a NitfSegment<ImageHeader>) -> NitfResult<Self> {
+        Ok(Self {
+            segment,
+            source: segment.build_source()?,
+        })
+    }
+
+    /// Segment being decoded
+    pub fn segment(&self) -> &'a NitfSegment<ImageHeader> {
+        self.segment
+    }
+
+    /// Block layout of the image
+    pub fn layout(&self) -> &BlockLayout {
+        &self.source.layout
+    }
+
+    /// See [NitfSegment::block_present()]
+    pub fn block_present(&self, block_row: u32, block_col: u32, band: usize) -> NitfResult<bool> {
+        let source = &self.source;
+        let block = source.block_number(block_row, block_col)?;
+        Ok(match &source.mask {
+            Some(mask) => mask.block_present(&source.layout, block, band),
+            None => true,
+        })
+    }
+
+    /// See [NitfSegment::read_image()]
+    pub fn read_image(&self) -> NitfResult<Vec<PixelBuffer>> {
+        let bands: Vec<usize> = (0..self.source.layout.nbands).collect();
+        self.read_bands(&bands)
+    }
+
+    /// See [NitfSegment::read_band()]
+    pub fn read_band(&self, band: usize) -> NitfResult<PixelBuffer> {
+        Ok(self.read_bands(&[band])?.remove(0))
+    }
+
+    /// See [NitfSegment::read_bands()]
+    pub fn read_bands(&self, bands: &[usize]) -> NitfResult<Vec<PixelBuffer>> {
+        let layout = &self.source.layout;
+        self.read_window(0, 0, layout.nrows, layout.ncols, bands)
+    }
+
+    /// See [NitfSegment::read_window()]
+    pub fn read_window(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        self.read_window_strided(row0, col0, rows, cols, bands, 1, 1)
+    }
+
+    /// See [NitfSegment::read_window_strided()]
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_window_strided(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+        row_stride: u32,
+        col_stride: u32,
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        let window = Window {
+            rows: Axis::new(row0, rows, row_stride),
+            cols: Axis::new(col0, cols, col_stride),
+        };
+        with_pixel_type!(self.segment.pixel_type()?, T => {
+            let pixels = self.decode::<T>(&window, bands)?;
+            Ok(pixels.into_iter().map(T::into_buffer).collect())
+        })
+    }
+
+    /// See [NitfSegment::read_window_at_level()]
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_window_at_level(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+        level: u8,
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        #[cfg(feature = "jpeg2000")]
+        if let (Some(Codec::Jpeg2000(codestream)), true) =
+            (&self.source.codec, level > 0 && rows > 0 && cols > 0)
+        {
+            return self.read_reduced(codestream, (row0, col0, rows, cols), bands, level);
+        }
+        let stride = 1u32.checked_shl(level as u32).unwrap_or(u32::MAX);
+        self.read_window_strided(row0, col0, rows, cols, bands, stride, stride)
+    }
+
+    /// See [NitfSegment::resolution_levels()]
+    pub fn resolution_levels(&self) -> Option<u8> {
+        match &self.source.codec {
+            #[cfg(feature = "jpeg2000")]
+            Some(Codec::Jpeg2000(codestream)) => Some(codestream.levels()),
+            _ => None,
+        }
+    }
+
+    /// See [NitfSegment::read_bilevel()]
+    pub fn read_bilevel(&self, band: usize) -> NitfResult<Vec<u8>> {
+        let meta = &self.segment.meta;
+        if meta.pvtype.val != PixelValueType::B {
+            return Err(NitfError::InvalidValue(
+                "PVTYPE".to_string(),
+                format!("{} (expected B for bi-level pixels)", meta.pvtype.val),
+            ));
+        }
+        let layout = &self.source.layout;
+        let PixelBuffer::U8(pixels) = self.read_band(band)? else {
+            return Err(NitfError::Fatal(
+                "bi-level pixels decoded as bytes".to_string(),
+            ));
+        };
+        let mut packed =
+            Vec::with_capacity(layout.nrows as usize * layout.ncols.div_ceil(8) as usize);
+        for row in pixels.chunks_exact(layout.ncols.max(1) as usize) {
+            for byte in row.chunks(8) {
+                let bits = byte.iter().fold(0u8, |acc, pixel| (acc << 1) | (pixel & 1));
+                packed.push(bits << (8 - byte.len()));
+            }
+        }
+        Ok(packed)
+    }
+
+    /// See [NitfSegment::read_block()]
+    pub fn read_block(
+        &self,
+        block_row: u32,
+        block_col: u32,
+        bands: &[usize],
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        let layout = &self.source.layout;
+        check_bands(layout, bands)?;
+        self.source.block_number(block_row, block_col)?;
+        let rows: Vec<u32> = (0..layout.nppbv).collect();
+        let cols: Vec<u32> = (0..layout.nppbh).collect();
+        with_pixel_type!(self.segment.pixel_type()?, T => {
+            let pixels = self.block_samples::<T>(block_row, block_col, bands, &rows, &cols)?;
+            Ok(pixels.into_iter().map(T::into_buffer).collect())
+        })
+    }
+
+    /// Decode a window at a reduced resolution from a JPEG 2000 codestream
+    #[cfg(feature = "jpeg2000")]
+    fn read_reduced(
+        &self,
+        codestream: &jpeg2000::Codestream,
+        (row0, col0, rows, cols): (u32, u32, u32, u32),
+        bands: &[usize],
+        level: u8,
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        let source = &self.source;
+        let layout = &source.layout;
+        check_bands(layout, bands)?;
+        let window = Window {
+            rows: Axis::new(row0, rows, 1),
+            cols: Axis::new(col0, cols, 1),
+        };
+        window.check(layout)?;
+        let area = (row0, col0, row0 + rows, col0 + cols);
+        let (width, decoded) = codestream.decode_area(&self.segment.data, area, level)?;
+        let omitted = codestream.omitted_areas(layout, area, level);
+        with_pixel_type!(self.segment.pixel_type()?, T => {
+            let mut out = Vec::with_capacity(bands.len());
+            for &band in bands {
+                let plane = decoded.planes.get(band).ok_or_else(|| {
+                    NitfError::Fatal(format!("codestream has no component for band {band}"))
+                })?;
+                let mut samples: Vec<T> = plane
+                    .iter()
+                    .map(|v| <T as Pixel>::from_bits(*v as u64, decoded.nbits))
+                    .collect();
+                for (rows, cols) in &omitted {
+                    for row in rows.clone() {
+                        let start = row * width as usize;
+                        samples[start + cols.start..start + cols.end].fill(source.pad_value());
+                    }
+                }
+                out.push(T::into_buffer(samples));
+            }
+            Ok(out)
+        })
+    }
+
+    /// Decode `window` of `bands`
+    fn decode<T: Pixel>(&self, window: &Window, bands: &[usize]) -> NitfResult<Vec<Vec<T>>> {
+        let layout = &self.source.layout;
+        check_bands(layout, bands)?;
+        window.check(layout)?;
+        let (out_rows, out_cols) = (window.rows.n_out(), window.cols.n_out());
+        let mut out = vec![vec![T::default(); out_rows * out_cols]; bands.len()];
+        if out_rows == 0 || out_cols == 0 {
+            return Ok(out);
+        }
+        for block_row in window.rows.blocks(layout.nppbv) {
+            let (out_row0, rows) = window.rows.in_block(block_row, layout.nppbv);
+            if rows.is_empty() {
+                continue;
+            }
+            for block_col in window.cols.blocks(layout.nppbh) {
+                let (out_col0, cols) = window.cols.in_block(block_col, layout.nppbh);
+                if cols.is_empty() {
+                    continue;
+                }
+                let blocks = self.block_samples::<T>(block_row, block_col, bands, &rows, &cols)?;
+                for (band_out, block) in out.iter_mut().zip(blocks) {
+                    for (i_row, src) in block.chunks_exact(cols.len()).enumerate() {
+                        let dst = (out_row0 + i_row) * out_cols + out_col0;
+                        band_out[dst..dst + cols.len()].copy_from_slice(src);
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Decode the samples of `bands` at the given block-relative `rows` and `cols`
+    ///
+    /// Samples are returned in row-major order, one vector per band.
+    fn block_samples<T: Pixel>(
+        &self,
+        block_row: u32,
+        block_col: u32,
+        bands: &[usize],
+        rows: &[u32],
+        cols: &[u32],
+    ) -> NitfResult<Vec<Vec<T>>> {
+        let source = &self.source;
+        let (layout, format) = (&source.layout, &source.format);
+        let block = source.block_number(block_row, block_col)?;
+        if let Some(codec) = &source.codec {
+            return self.decoded_samples(codec, block, bands, rows, cols);
+        }
+        // Only fetch the rows of the block which are needed
+        let (first_row, last_row) = (rows[0] as u64, rows[rows.len() - 1] as u64);
+        let first_byte = first_row * layout.row_bits() / 8;
+        let end_byte = ((last_row + 1) * layout.row_bits()).div_ceil(8);
+        let skip_bits = first_byte * 8;
+        let mut interleaved = None;
+        let mut out = Vec::with_capacity(bands.len());
+        for &band in bands {
+            let Some(offset) = source.block_offset(block, band) else {
+                out.push(vec![source.pad_value(); rows.len() * cols.len()]);
+                continue;
+            };
+            let data = match (layout.band_sequential(), &interleaved) {
+                (false, Some(data)) => data,
+                _ => interleaved.insert(
+                    self.segment
+                        .data
+                        .read_range(offset + first_byte, (end_byte - first_byte) as usize)?,
+                ),
+            };
+            let mut samples = Vec::with_capacity(rows.len() * cols.len());
+            for &row in rows {
+                for &col in cols {
+                    let bit = layout.sample_bit(band, row, col) - skip_bits;
+                    samples.push(format.value::<T>(data, bit));
+                }
+            }
+            out.push(samples);
+        }
+        Ok(out)
+    }
+
+    /// Samples of `bands` at the given block-relative `rows` and `cols` of a
+    /// compressed block, which is decoded in full
+    fn decoded_samples<T: Pixel>(
+        &self,
+        codec: &Codec,
+        block: u64,
+        bands: &[usize],
+        rows: &[u32],
+        cols: &[u32],
+    ) -> NitfResult<Vec<Vec<T>>> {
+        let source = &self.source;
+        let layout = &source.layout;
+        // Except for IMODE S, all bands are decoded together
+        let shared = match layout.imode {
+            Mode::S => None,
+            _ => Some(codec.decode(&self.segment.data, layout, block, 0)?),
+        };
+        let mut out = Vec::with_capacity(bands.len());
+        for &band in bands {
+            let own;
+            let (decoded, plane) = match &shared {
+                Some(decoded) => (decoded, band),
+                None => {
+                    own = codec.decode(&self.segment.data, layout, block, band)?;
+                    (&own, 0)
+                }
+            };
+            let Some(decoded) = decoded else {
+                out.push(vec![source.pad_value(); rows.len() * cols.len()]);
+                continue;
+            };
+            let plane = decoded.planes.get(plane).ok_or_else(|| {
+                NitfError::Fatal(format!(
+                    "decoded block with {} bands, reading band {band}",
+                    decoded.planes.len()
+                ))
+            })?;
+            let mut samples = Vec::with_capacity(rows.len() * cols.len());
+            for &row in rows {
+                let offset = row as usize * layout.nppbh as usize;
+                for &col in cols {
+                    let sample = plane[offset + col as usize];
+                    samples.push(T::from_bits(sample as u64, decoded.nbits));
+                }
+            }
+            out.push(samples);
+        }
+        Ok(out)
+    }
+}
+
+fn check_bands(layout: &BlockLayout, bands: &[usize]) -> NitfResult<()> {
+    match bands.iter().find(|band| **band >= layout.nbands) {
+        Some(band) => Err(NitfError::Fatal(format!(
+            "band {band} of image with {} bands",
+            layout.nbands
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Subheader-derived state shared by the block reads of a request
+struct Source {
+    layout: BlockLayout,
+    mask: Option<MaskTable>,
+    format: SampleFormat,
+    /// Pad pixel code and its length in bits
+    pad: Option<(u64, u32)>,
+    codec: Option<Codec>,
+}
+
+/// Decoder state of a compressed image
+enum Codec {
+    Bilevel(bilevel::BilevelBlocks),
+    Vq(vq::VqBlocks),
+    #[cfg(feature = "jpeg")]
+    Jpeg(jpeg::JpegBlocks),
+    #[cfg(feature = "jpeg2000")]
+    Jpeg2000(jpeg2000::Codestream),
+}
+impl Codec {
+    /// Decode the data holding `band` of `block`, `None` if the block was omitted
+    ///
+    /// Except for `IMODE` S the result holds all bands of the block.
+    fn decode(
+        &self,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        block: u64,
+        band: usize,
+    ) -> NitfResult<Option<DecodedBlock>> {
+        match *self {
+            Self::Bilevel(ref blocks) => blocks.decode(data, layout, block, band),
+            Self::Vq(ref blocks) => blocks.decode(data, layout, block),
+            #[cfg(feature = "jpeg")]
+            Self::Jpeg(ref blocks) => blocks.decode(data, layout, block, band),
+            #[cfg(feature = "jpeg2000")]
+            Self::Jpeg2000(ref codestream) => codestream.decode(data, layout, block, band),
+        }
+    }
+}
+
+/// Samples of a decoded block, one plane of NPPBV x NPPBH samples per band
+struct DecodedBlock {
+    /// Number of significant bits per sample
+    nbits: u32,
+    planes: Vec<Vec<u32>>,
+}
+impl Source {
+    /// Row-major block number, after checking the block is within the image
+    fn block_number(&self, block_row: u32, block_col: u32) -> NitfResult<u64> {
+        let layout = &self.layout;
+        if block_row >= layout.nbpc || block_col >= layout.nbpr {
+            return Err(NitfError::Fatal(format!(
+                "block ({block_row}, {block_col}) outside of {} x {} blocks",
+                layout.nbpc, layout.nbpr
+            )));
+        }
+        Ok(block_row as u64 * layout.nbpr as u64 + block_col as u64)
+    }
+
+    /// Offset of `band` of `block` within the segment data, `None` if omitted
+    fn block_offset(&self, block: u64, band: usize) -> Option<u64> {
+        match &self.mask {
+            Some(mask) => mask.block_offset(&self.layout, block, band),
+            None => Some(self.layout.block_range(block, band).0),
+        }
+    }
+
+    fn pad_value<T: Pixel>(&self) -> T {
+        match self.pad {
+            Some((code, nbits)) => T::from_bits(code, nbits),
+            None => T::default(),
+        }
+    }
+}
+
+/// Pixel selection along one dimension of the image
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    start: u32,
+    len: u32,
+    stride: u32,
+}
+impl Axis {
+    fn new(start: u32, len: u32, stride: u32) -> Self {
+        Self { start, len, stride }
+    }
+
+    /// Number of selected pixels
+    fn n_out(&self) -> usize {
+        self.len.div_ceil(self.stride.max(1)) as usize
+    }
+
+    /// Blocks of size `block_len` overlapping the selection
+    fn blocks(&self, block_len: u32) -> std::ops::Range<u32> {
+        let end = self.start as u64 + self.len as u64;
+        self.start / block_len..end.div_ceil(block_len as u64) as u32
+    }
+
+    /// Output index of the first selected pixel in `block`, and the
+    /// block-relative positions of all selected pixels in it
+    fn in_block(&self, block: u32, block_len: u32) -> (usize, Vec<u32>) {
+        let block_start = block as u64 * block_len as u64;
+        let block_end = block_start + block_len as u64;
+        let (start, stride) = (self.start as u64, self.stride as u64);
+        let end = block_end.min(start + self.len as u64);
+        let first = (block_start.max(start) - start).div_ceil(stride);
+        let positions = (first..)
+            .map(|i| start + i * stride)
+            .take_while(|pos| *pos < end)
+            .map(|pos| (pos - block_start) as u32)
+            .collect();
+        (first as usize, positions)
+    }
+}
+
+/// Pixel selection of a windowed read
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    rows: Axis,
+    cols: Axis,
+}
+impl Window {
+    fn check(&self, layout: &BlockLayout) -> NitfResult<()> {
+        let (rows, cols) = (self.rows, self.cols);
+        if rows.stride == 0 || cols.stride == 0 {
+            return Err(NitfError::Fatal("window stride of zero".to_string()));
+        }
+        let row_end = rows.start as u64 + rows.len as u64;
+        let col_end = cols.start as u64 + cols.len as u64;
+        if row_end > layout.nrows as u64 || col_end > layout.ncols as u64 {
+            return Err(NitfError::Fatal(format!(
+                "window rows {}..{row_end}, columns {}..{col_end} outside of {} x {} image",
+                rows.start, cols.start, layout.nrows, layout.ncols
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+    use crate::headers::image_hdr::PixelJustification;
+
+    type ImageSegment = NitfSegment<ImageHeader>;
+
+    /// Image segment of `bands` with the given layout and hand-packed `data`
+    fn image(
+        nrows: u32,
+        ncols: u32,
+        bands: usize,
+        header: ImageHeaderBuilder,
+        data: Vec<u8>,
+    ) -> NitfResult<ImageSegment> {
+        let mut header = header.size(nrows, ncols)?;
+        for _ in 0..bands {
+            header = header.band("", "")?;
+        }
+        let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header.build()?, data)
+            .build()?;
+        Ok(nitf.image_segments.remove(0))
+    }
+
+    fn u8_header(imode: Mode) -> NitfResult<ImageHeaderBuilder> {
+        ImageHeaderBuilder::new()?
+            .pvtype(PixelValueType::INT)?
+            .nbpp(8)?
+            .abpp(8, PixelJustification::R)?
+            .imode(imode)
+    }
+
+    #[test]
+    fn band_interleaved_by_block() -> NitfResult<()> {
+        let data = vec![1, 2, 3, 4, 11, 12, 13, 14];
+        let segment = image(2, 2, 2, u8_header(Mode::B)?, data)?;
+        assert_eq!(
+            segment.read_image()?,
+            [
+                PixelBuffer::U8(vec![1, 2, 3, 4]),
+                PixelBuffer::U8(vec![11, 12, 13, 14])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn band_interleaved_by_pixel() -> NitfResult<()> {
+        let data = vec![1, 11, 2, 12, 3, 13, 4, 14];
+        let segment = image(2, 2, 2, u8_header(Mode::P)?, data)?;
+        assert_eq!(
+            segment.read_image()?,
+            [
+                PixelBuffer::U8(vec![1, 2, 3, 4]),
+                PixelBuffer::U8(vec![11, 12, 13, 14])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn band_interleaved_by_row() -> NitfResult<()> {
+        let data = vec![1, 2, 11, 12, 3, 4, 13, 14];
+        let segment = image(2, 2, 2, u8_header(Mode::R)?, data)?;
+        assert_eq!(
+            segment.read_image()?,
+            [
+                PixelBuffer::U8(vec![1, 2, 3, 4]),
+                PixelBuffer::U8(vec![11, 12, 13, 14])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn band_sequential() -> NitfResult<()> {
+        // One column blocks, with all blocks of the first band before the second
+        let data = vec![1, 3, 2, 4, 11, 13, 12, 14];
+        let segment = image(2, 2, 2, u8_header(Mode::S)?.block_size(1, 2)?, data)?;
+        assert_eq!(
+            segment.read_image()?,
+            [
+                PixelBuffer::U8(vec![1, 2, 3, 4]),
+                PixelBuffer::U8(vec![11, 12, 13, 14])
+            ]
+        );
+        assert_eq!(
+            segment.read_window(0, 1, 2, 1, &[1])?,
+            [PixelBuffer::U8(vec![12, 14])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn twelve_bit_samples() -> NitfResult<()> {
+        let header = ImageHeaderBuilder::new()?
+            .pvtype(PixelValueType::INT)?
+            .nbpp(12)?
+            .abpp(12, PixelJustification::R)?;
+        // 0xABC, 0x123, 0xFFF and 0x001 packed without padding
+        let data = vec![0xAB, 0xC1, 0x23, 0xFF, 0xF0, 0x01];
+        let segment = image(2, 2, 1, header, data)?;
+        assert_eq!(
+            segment.read_band(0)?,
+            PixelBuffer::U16(vec![0xABC, 0x123, 0xFFF, 0x001])
+        );
+        assert_eq!(
+            segment.read_window(1, 0, 1, 2, &[0])?,
+            [PixelBuffer::U16(vec![0xFFF, 0x001])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn actual_bits_per_pixel() -> NitfResult<()> {
+        let header = |pjust| {
+            ImageHeaderBuilder::new()?
+                .pvtype(PixelValueType::INT)?
+                .nbpp(16)?
+                .abpp(12, pjust)
+        };
+        // Left-justified samples are shifted down, unused bits are ignored
+        let left = image(
+            1,
+            2,
+            1,
+            header(PixelJustification::L)?,
+            vec![0xAB, 0xCF, 0x12, 0x3F],
+        )?;
+        assert_eq!(left.read_band(0)?, PixelBuffer::U16(vec![0xABC, 0x123]));
+        let right = image(
+            1,
+            2,
+            1,
+            header(PixelJustification::R)?,
+            vec![0xFA, 0xBC, 0xF1, 0x23],
+        )?;
+        assert_eq!(right.read_band(0)?, PixelBuffer::U16(vec![0xABC, 0x123]));
+        Ok(())
+    }
+
+    #[test]
+    fn signed_samples() -> NitfResult<()> {
+        let header = ImageHeaderBuilder::new()?
+            .pvtype(PixelValueType::SI)?
+            .nbpp(16)?
+            .abpp(12, PixelJustification::R)?;
+        // Sign extended from bit 11
+        let segment = image(1, 3, 1, header, vec![0x0F, 0xFF, 0x08, 0x00, 0x07, 0xFF])?;
+        assert_eq!(
+            segment.read_band(0)?,
+            PixelBuffer::I16(vec![-1, -2048, 2047])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_follow_changes() -> NitfResult<()> {
+        let mut segment = image(
+            2,
+            2,
+            2,
+            u8_header(Mode::B)?,
+            vec![1, 2, 3, 4, 11, 12, 13, 14],
+        )?;
+        let decoder = segment.decoder()?;
+        assert_eq!(decoder.read_band(1)?, PixelBuffer::U8(vec![11, 12, 13, 14]));
+        assert_eq!(
+            decoder.read_window(1, 0, 1, 2, &[0])?,
+            [PixelBuffer::U8(vec![3, 4])]
+        );
+        // Data of the same size, which may reuse the allocation of the old data
+        segment.data = vec![5, 6, 7, 8, 15, 16, 17, 18].into();
+        segment.data = vec![5, 6, 7, 8, 15, 16, 17, 18].into();
+        assert_eq!(segment.read_band(1)?, PixelBuffer::U8(vec![15, 16, 17, 18]));
+        segment.meta.imode.val = Mode::P;
+        assert_eq!(segment.read_band(1)?, PixelBuffer::U8(vec![6, 8, 16, 18]));
+        Ok(())
+    }
+
+    #[test]
+    fn block_padding_is_dropped() -> NitfResult<()> {
+        // 3 x 3 image in 2 x 2 blocks, with pad pixels (0xFF) past the last row and column
+        let data = vec![
+            1, 2, 4, 5, // block (0, 0)
+            3, 0xFF, 6, 0xFF, // block (0, 1)
+            7, 8, 0xFF, 0xFF, // block (1, 0)
+            9, 0xFF, 0xFF, 0xFF, // block (1, 1)
+        ];
+        let segment = image(3, 3, 1, u8_header(Mode::B)?.block_size(2, 2)?, data)?;
+        assert_eq!(segment.read_band(0)?, PixelBuffer::U8((1..=9).collect()));
+        assert_eq!(
+            segment.read_block(1, 1, &[0])?,
+            [PixelBuffer::U8(vec![9, 0xFF, 0xFF, 0xFF])]
+        );
+        assert_eq!(
+            segment.read_window_strided(0, 0, 3, 3, &[0], 2, 2)?,
+            [PixelBuffer::U8(vec![1, 3, 7, 9])]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Mosaics of image segments placed through their attachment levels
+use std::collections::HashMap;
+
+use crate::headers::graphic_hdr::BoundLocation;
+use crate::headers::ImageHeader;
+use crate::image::{with_pixel_type, Pixel, PixelBuffer, PixelType};
+use crate::segments::NitfSegment;
+use crate::{Nitf, NitfError, NitfResult};
+
+/// Attachment level and location of a segment displayed at some level
+type Placement<'a> = (u16, &'a BoundLocation);
+
+impl Nitf {
+    /// Row and column of the first pixel of each image segment in the common
+    /// coordinate system
+    ///
+    /// Each ILOC is relative to the segment displayed at the IALVL of the image,
+    /// which may be another image, a graphic or a label, and attachment level 0
+    /// is the origin of the common coordinate system. Magnification (IMAG) is
+    /// not taken into account.
+    pub fn image_positions(&self) -> NitfResult<Vec<(i64, i64)>> {
+        let placements = self.placements()?;
+        self.image_segments
+            .iter()
+            .map(|segment| resolve(&placements, segment.meta.idlvl.val))
+            .collect()
+    }
+
+    /// Row and column of the first pixel of each graphic segment in the common
+    /// coordinate system, see [image_positions()](Self::image_positions)
+    pub(crate) fn graphic_positions(&self) -> NitfResult<Vec<(i64, i64)>> {
+        let placements = self.placements()?;
+        self.graphic_segments
+            .iter()
+            .map(|segment| resolve(&placements, segment.meta.sdlvl.val))
+            .collect()
+    }
+
+    /// Virtual mosaic of the image segments (zero indexed) at their positions
+    /// in the common coordinate system, see [image_positions()](Self::image_positions)
+    ///
+    /// All segments must have the same number of bands and pixel type.
+    /// ```no_run
+    /// use std::path::Path;
+    /// let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+    /// let segments: Vec<usize> = (0..nitf.image_segments.len()).collect();
+    /// let mosaic = nitf.mosaic(&segments).unwrap();
+    /// // 512 x 512 chip of the first band, across segment boundaries
+    /// let chip = mosaic.read_window(1000, 2000, 512, 512, &[0]).unwrap();
+    /// ```
+    pub fn mosaic(&self, segments: &[usize]) -> NitfResult<Mosaic<'_>> {
+        let positions = self.image_positions()?;
+        let Some(&first) = segments.first() else {
+            return Err(NitfError::Fatal("mosaic of no image segments".to_string()));
+        };
+        let image = |index: usize| {
+            self.image_segments.get(index).ok_or_else(|| {
+                NitfError::Fatal(format!(
+                    "image segment {index} of {}",
+                    self.image_segments.len()
+                ))
+            })
+        };
+        let (nbands, pixel_type) = (image(first)?.meta.bands.len(), image(first)?.pixel_type()?);
+        let mut placed = vec![];
+        for &index in segments {
+            let segment = image(index)?;
+            if segment.meta.bands.len() != nbands || segment.pixel_type()? != pixel_type {
+                return Err(NitfError::InvalidValue(
+                    "NBANDS".to_string(),
+                    format!(
+                        "{} bands of {:?} in image segment {index}, expected {nbands} of {pixel_type:?}",
+                        segment.meta.bands.len(),
+                        segment.pixel_type()?
+                    ),
+                ));
+            }
+            placed.push((segment, positions[index]));
+        }
+        let row0 = placed.iter().map(|(_, (row, _))| *row).min().unwrap_or(0);
+        let col0 = placed.iter().map(|(_, (_, col))| *col).min().unwrap_or(0);
+        let row_end = placed
+            .iter()
+            .map(|(segment, (row, _))| row + segment.meta.nrows.val as i64)
+            .max()
+            .unwrap_or(0);
+        let col_end = placed
+            .iter()
+            .map(|(segment, (_, col))| col + segment.meta.ncols.val as i64)
+            .max()
+            .unwrap_or(0);
+        let (Ok(nrows), Ok(ncols)) = (u32::try_from(row_end - row0), u32::try_from(col_end - col0))
+        else {
+            return Err(NitfError::Fatal(format!(
+                "mosaic of {} x {} pixels",
+                row_end - row0,
+                col_end - col0
+            )));
+        };
+        // Later tiles are drawn over earlier ones, in order of display level
+        placed.sort_by_key(|(segment, _)| segment.meta.idlvl.val);
+        let tiles = placed
+            .into_iter()
+            .map(|(segment, (row, col))| Tile {
+                segment,
+                row: (row - row0) as u32,
+                col: (col - col0) as u32,
+            })
+            .collect();
+        Ok(Mosaic {
+            tiles,
+            origin: (row0, col0),
+            nrows,
+            ncols,
+            nbands,
+            pixel_type,
+        })
+    }
+
+    /// Placement of every displayable segment, by display level
+    fn placements(&self) -> NitfResult<HashMap<u16, Placement<'_>>> {
+        let images = self.image_segments.iter().map(|segment| {
+            let meta = &segment.meta;
+            ("IDLVL", meta.idlvl.val, meta.ialvl.val, &meta.iloc.val)
+        });
+        let graphics = self.graphic_segments.iter().map(|segment| {
+            let meta = &segment.meta;
+            ("SDLVL", meta.sdlvl.val, meta.salvl.val, &meta.sloc.val)
+        });
+        let labels = self.label_segments.iter().map(|segment| {
+            let meta = &segment.meta;
+            ("LDLVL", meta.ldlvl.val, meta.lalvl.val, &meta.lloc.val)
+        });
+        let mut placements = HashMap::new();
+        for (field, level, attachment, location) in images.chain(graphics).chain(labels) {
+            if placements.insert(level, (attachment, location)).is_some() {
+                return Err(NitfError::InvalidValue(
+                    field.to_string(),
+                    format!("{level}, used by more than one segment"),
+                ));
+            }
+        }
+        Ok(placements)
+    }
+}
+
+/// Position of the segment displayed at `level`, following its attachments
+fn resolve(placements: &HashMap<u16, Placement>, level: u16) -> NitfResult<(i64, i64)> {
+    let (mut row, mut col) = (0, 0);
+    let mut current = level;
+    // Every step moves to another segment, so more steps than segments is a cycle
+    for _ in 0..=placements.len() {
+        let Some((attachment, location)) = placements.get(&current) else {
+            return Err(NitfError::InvalidValue(
+                "IALVL".to_string(),
+                format!("{current}, which no segment is displayed at"),
+            ));
+        };
+        row += location.row as i64;
+        col += location.col as i64;
+        if *attachment == 0 {
+            return Ok((row, col));
+        }
+        current = *attachment;
+    }
+    Err(NitfError::InvalidValue(
+        "IALVL".to_string(),
+        format!("attachment cycle from display level {level}"),
+    ))
+}
+
+/// Image segments read as a single image, placed in the common coordinate system
+#[derive(Debug, Clone)]
+pub struct Mosaic<'a> {
+    tiles: Vec<Tile<'a>>,
+    origin: (i64, i64),
+    nrows: u32,
+    ncols: u32,
+    nbands: usize,
+    pixel_type: PixelType,
+}
+
+/// Image segment and its position in the mosaic
+#[derive(Debug, Clone)]
+struct Tile<'a> {
+    segment: &'a NitfSegment<ImageHeader>,
+    row: u32,
+    col: u32,
+}
+
+impl Mosaic<'_> {
+    /// Row and column in the common coordinate system of the first mosaic pixel
+    pub fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    /// Number of rows covering all segments
+    pub fn nrows(&self) -> u32 {
+        self.nrows
+    }
+
+    /// Number of columns covering all segments
+    pub fn ncols(&self) -> u32 {
+        self.ncols
+    }
+
+    /// Number of bands of every segment
+    pub fn nbands(&self) -> usize {
+        self.nbands
+    }
+
+    /// Native type of the pixels of every segment
+    pub fn pixel_type(&self) -> PixelType {
+        self.pixel_type
+    }
+
+    /// Decode a `rows` x `cols` window of `bands`, starting at mosaic pixel
+    /// (`row0`, `col0`)
+    ///
+    /// Only the segments intersecting the window are read. Where segments
+    /// overlap, the one with the highest display level (IDLVL) is shown, and
+    /// pixels outside of every segment are zero.
+    pub fn read_window(
+        &self,
+        row0: u32,
+        col0: u32,
+        rows: u32,
+        cols: u32,
+        bands: &[usize],
+    ) -> NitfResult<Vec<PixelBuffer>> {
+        let row_end = row0 as u64 + rows as u64;
+        let col_end = col0 as u64 + cols as u64;
+        if row_end > self.nrows as u64 || col_end > self.ncols as u64 {
+            return Err(NitfError::Fatal(format!(
+                "window rows {row0}..{row_end}, columns {col0}..{col_end} outside of {} x {} mosaic",
+                self.nrows, self.ncols
+            )));
+        }
+        with_pixel_type!(self.pixel_type, T => {
+            let mut out = vec![vec![T::default(); rows as usize * cols as usize]; bands.len()];
+            for tile in &self.tiles {
+                let meta = &tile.segment.meta;
+                let (top, left) = (row0.max(tile.row), col0.max(tile.col));
+                let bottom = (row_end as u32).min(tile.row + meta.nrows.val);
+                let right = (col_end as u32).min(tile.col + meta.ncols.val);
+                if top >= bottom || left >= right {
+                    continue;
+                }
+                let (height, width) = (bottom - top, right - left);
+                let chip = tile.segment.read_window(
+                    top - tile.row,
+                    left - tile.col,
+                    height,
+                    width,
+                    bands,
+                )?;
+                for (band_out, band_chip) in out.iter_mut().zip(&chip) {
+                    let src = band_chip.as_slice::<T>().unwrap_or_default();
+                    for (i_row, src_row) in src.chunks_exact(width as usize).enumerate() {
+                        let dst = (top - row0) as usize + i_row;
+                        let dst = dst * cols as usize + (left - col0) as usize;
+                        band_out[dst..dst + width as usize].copy_from_slice(src_row);
+                    }
+                }
+            }
+            Ok(out.into_iter().map(T::into_buffer).collect())
+        })
+    }
+}
@@ -0,0 +1,352 @@
+//! Native pixel types and buffers
+use num_complex::Complex;
+use std::fmt::Debug;
+
+use crate::headers::image_hdr::{PixelJustification, PixelValueType};
+use crate::{NitfError, NitfResult};
+
+/// Native element type used to hold the pixels of an image
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PixelType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    /// Real and imaginary 32 bit floats
+    C32,
+}
+
+/// Pixels of a single band, in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub enum PixelBuffer {
+    U8(Vec<u8>),
+    I8(Vec<i8>),
+    U16(Vec<u16>),
+    I16(Vec<i16>),
+    U32(Vec<u32>),
+    I32(Vec<i32>),
+    U64(Vec<u64>),
+    I64(Vec<i64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    C32(Vec<Complex<f32>>),
+}
+
+/// Element type which image pixels can be decoded into
+pub trait Pixel: Copy + Default + Debug + Send + Sync + 'static {
+    /// [PixelType] corresponding to the implementing type
+    const TYPE: PixelType;
+    /// Build a value from the lowest `nbits` of `bits`
+    ///
+    /// Signed integers are sign-extended from bit `nbits - 1`.
+    fn from_bits(bits: u64, nbits: u32) -> Self;
+    /// Bits of the value, the inverse of [from_bits()](Self::from_bits)
+    ///
+    /// Signed integers are returned in two's complement, to be truncated to
+    /// the number of bits they are stored in.
+    fn to_bits(self) -> u64;
+    /// Wrap `values` in a [PixelBuffer]
+    fn into_buffer(values: Vec<Self>) -> PixelBuffer;
+    /// Borrow the values of `buffer`, if it holds this type
+    fn from_buffer(buffer: &PixelBuffer) -> Option<&[Self]>;
+}
+
+/// Dispatch on a [PixelType], binding the matching native type to `$T`
+macro_rules! with_pixel_type {
+    ($pixel_type:expr, $T:ident => $body:expr) => {
+        match $pixel_type {
+            PixelType::U8 => {
+                type $T = u8;
+                $body
+            }
+            PixelType::I8 => {
+                type $T = i8;
+                $body
+            }
+            PixelType::U16 => {
+                type $T = u16;
+                $body
+            }
+            PixelType::I16 => {
+                type $T = i16;
+                $body
+            }
+            PixelType::U32 => {
+                type $T = u32;
+                $body
+            }
+            PixelType::I32 => {
+                type $T = i32;
+                $body
+            }
+            PixelType::U64 => {
+                type $T = u64;
+                $body
+            }
+            PixelType::I64 => {
+                type $T = i64;
+                $body
+            }
+            PixelType::F32 => {
+                type $T = f32;
+                $body
+            }
+            PixelType::F64 => {
+                type $T = f64;
+                $body
+            }
+            PixelType::C32 => {
+                type $T = num_complex::Complex<f32>;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_pixel_type;
+
+macro_rules! impl_pixel {
+    ($T:ty, $variant:ident, $from_bits:expr, $to_bits:expr) => {
+        impl Pixel for $T {
+            const TYPE: PixelType = PixelType::$variant;
+            fn from_bits(bits: u64, nbits: u32) -> Self {
+                $from_bits(bits, nbits)
+            }
+            fn to_bits(self) -> u64 {
+                $to_bits(self)
+            }
+            fn into_buffer(values: Vec<Self>) -> PixelBuffer {
+                PixelBuffer::$variant(values)
+            }
+            fn from_buffer(buffer: &PixelBuffer) -> Option<&[Self]> {
+                match buffer {
+                    PixelBuffer::$variant(values) => Some(values),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+/// Sign-extend the lowest `nbits` of `bits`
+fn sign_extend(bits: u64, nbits: u32) -> i64 {
+    let shift = 64 - nbits.clamp(1, 64);
+    ((bits << shift) as i64) >> shift
+}
+
+impl_pixel!(u8, U8, |bits, _| bits as u8, |v| v as u64);
+impl_pixel!(u16, U16, |bits, _| bits as u16, |v| v as u64);
+impl_pixel!(u32, U32, |bits, _| bits as u32, |v| v as u64);
+impl_pixel!(u64, U64, |bits, _| bits, |v| v);
+impl_pixel!(i8, I8, |bits, n| sign_extend(bits, n) as i8, |v| v as u64);
+impl_pixel!(i16, I16, |bits, n| sign_extend(bits, n) as i16, |v| v
+    as u64);
+impl_pixel!(i32, I32, |bits, n| sign_extend(bits, n) as i32, |v| v
+    as u64);
+impl_pixel!(i64, I64, sign_extend, |v| v as u64);
+impl_pixel!(
+    f32,
+    F32,
+    |bits, _| f32::from_bits(bits as u32),
+    |v: f32| v.to_bits() as u64
+);
+impl_pixel!(f64, F64, |bits, _| f64::from_bits(bits), |v: f64| v
+    .to_bits());
+impl_pixel!(
+    Complex<f32>,
+    C32,
+    |bits: u64, _| Complex::new(
+        f32::from_bits((bits >> 32) as u32),
+        f32::from_bits(bits as u32)
+    ),
+    |v: Complex<f32>| ((v.re.to_bits() as u64) << 32) | v.im.to_bits() as u64
+);
+
+impl PixelType {
+    /// Native type able to hold pixels of the given PVTYPE and NBPP
+    pub fn from_header(pvtype: &PixelValueType, nbpp: u8) -> NitfResult<Self> {
+        let invalid = || {
+            Err(NitfError::InvalidValue(
+                "NBPP".to_string(),
+                format!("{nbpp} (PVTYPE {pvtype})"),
+            ))
+        };
+        match pvtype {
+            PixelValueType::INT => match nbpp {
+                1..=8 => Ok(Self::U8),
+                9..=16 => Ok(Self::U16),
+                17..=32 => Ok(Self::U32),
+                33..=64 => Ok(Self::U64),
+                _ => invalid(),
+            },
+            PixelValueType::SI => match nbpp {
+                1..=8 => Ok(Self::I8),
+                9..=16 => Ok(Self::I16),
+                17..=32 => Ok(Self::I32),
+                33..=64 => Ok(Self::I64),
+                _ => invalid(),
+            },
+            PixelValueType::R => match nbpp {
+                32 => Ok(Self::F32),
+                64 => Ok(Self::F64),
+                _ => invalid(),
+            },
+            PixelValueType::C => match nbpp {
+                64 => Ok(Self::C32),
+                _ => invalid(),
+            },
+            PixelValueType::B => match nbpp {
+                1 => Ok(Self::U8),
+                _ => invalid(),
+            },
+        }
+    }
+
+    /// PVTYPE and NBPP which store pixels of this type, the inverse of
+    /// [from_header()](Self::from_header) for full width values
+    pub fn to_header(&self) -> (PixelValueType, u8) {
+        let pvtype = match self {
+            Self::U8 | Self::U16 | Self::U32 | Self::U64 => PixelValueType::INT,
+            Self::I8 | Self::I16 | Self::I32 | Self::I64 => PixelValueType::SI,
+            Self::F32 | Self::F64 => PixelValueType::R,
+            Self::C32 => PixelValueType::C,
+        };
+        (pvtype, self.size() as u8 * 8)
+    }
+
+    /// Size of one element in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 | Self::C32 => 8,
+        }
+    }
+}
+
+impl PixelBuffer {
+    /// Buffer of `len` default values of `pixel_type`
+    pub fn zeros(pixel_type: PixelType, len: usize) -> Self {
+        with_pixel_type!(pixel_type, T => T::into_buffer(vec![T::default(); len]))
+    }
+
+    /// Element type of the buffer
+    pub fn pixel_type(&self) -> PixelType {
+        match self {
+            Self::U8(_) => PixelType::U8,
+            Self::I8(_) => PixelType::I8,
+            Self::U16(_) => PixelType::U16,
+            Self::I16(_) => PixelType::I16,
+            Self::U32(_) => PixelType::U32,
+            Self::I32(_) => PixelType::I32,
+            Self::U64(_) => PixelType::U64,
+            Self::I64(_) => PixelType::I64,
+            Self::F32(_) => PixelType::F32,
+            Self::F64(_) => PixelType::F64,
+            Self::C32(_) => PixelType::C32,
+        }
+    }
+
+    /// Number of pixels in the buffer
+    pub fn len(&self) -> usize {
+        with_pixel_type!(self.pixel_type(), T => self.as_slice::<T>().unwrap_or_default().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Borrow the pixels as `T`, if the buffer holds that type
+    pub fn as_slice<T: Pixel>(&self) -> Option<&[T]> {
+        T::from_buffer(self)
+    }
+}
+
+/// Describes how a single sample is stored in the image data
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SampleFormat {
+    /// Bits used to store each sample
+    pub nbpp: u32,
+    /// Significant bits of each sample
+    pub abpp: u32,
+    /// Significant bits are left-justified within `nbpp`
+    pub left_justified: bool,
+    /// Justification applies, i.e. the samples are integers
+    pub integer: bool,
+}
+impl SampleFormat {
+    pub fn new(pvtype: &PixelValueType, nbpp: u8, abpp: u8, pjust: &PixelJustification) -> Self {
+        let nbpp = nbpp as u32;
+        // ABPP of zero or larger than NBPP would be invalid, fall back to NBPP
+        let abpp = match abpp as u32 {
+            0 => nbpp,
+            abpp => abpp.min(nbpp),
+        };
+        Self {
+            nbpp,
+            abpp,
+            left_justified: *pjust == PixelJustification::L,
+            integer: matches!(
+                pvtype,
+                PixelValueType::INT | PixelValueType::SI | PixelValueType::B
+            ),
+        }
+    }
+
+    /// Decode the sample stored at `bit_offset` of `data`
+    pub fn value<T: Pixel>(&self, data: &[u8], bit_offset: u64) -> T {
+        let mut bits = read_bits(data, bit_offset, self.nbpp);
+        if !self.integer {
+            return T::from_bits(bits, self.nbpp);
+        }
+        if self.abpp < self.nbpp {
+            if self.left_justified {
+                bits >>= self.nbpp - self.abpp;
+            } else {
+                bits &= (1 << self.abpp) - 1;
+            }
+        }
+        T::from_bits(bits, self.abpp)
+    }
+}
+
+/// Read `nbits` (at most 64) big-endian bits of `data` starting at `bit_offset`
+pub(crate) fn read_bits(data: &[u8], bit_offset: u64, nbits: u32) -> u64 {
+    let start = (bit_offset / 8) as usize;
+    if bit_offset.is_multiple_of(8) && nbits.is_multiple_of(8) {
+        let n_bytes = (nbits / 8) as usize;
+        return data[start..start + n_bytes]
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | *byte as u64);
+    }
+    let skip = (bit_offset % 8) as u32;
+    let end = (bit_offset + nbits as u64).div_ceil(8) as usize;
+    // At most 72 bits are needed to hold the covering bytes
+    let covering = data[start..end]
+        .iter()
+        .fold(0u128, |acc, byte| (acc << 8) | *byte as u128);
+    let trailing = (end - start) as u32 * 8 - skip - nbits;
+    ((covering >> trailing) & ((1u128 << nbits) - 1)) as u64
+}
+
+/// Write the lowest `nbits` (at most 64) of `value` as big-endian bits of `data`
+/// starting at `bit_offset`, which must still be zero
+pub(crate) fn write_bits(data: &mut [u8], bit_offset: u64, nbits: u32, value: u64) {
+    let start = (bit_offset / 8) as usize;
+    if bit_offset.is_multiple_of(8) && nbits.is_multiple_of(8) {
+        let n_bytes = (nbits / 8) as usize;
+        data[start..start + n_bytes].copy_from_slice(&value.to_be_bytes()[8 - n_bytes..]);
+        return;
+    }
+    for i in 0..nbits as u64 {
+        let bit = (value >> (nbits as u64 - 1 - i)) & 1;
+        let pos = bit_offset + i;
+        data[(pos / 8) as usize] |= (bit as u8) << (7 - pos % 8);
+    }
+}
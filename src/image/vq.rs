@@ -0,0 +1,346 @@
+//! Vector quantization decoding for `C4` and `M4` compressed images
+//!
+//! Each block is stored as a grid of codes, with each code indexing a kernel of
+//! pixels (4 x 4 for CIB) in the compression lookup tables. There is one lookup
+//! table per kernel row, holding the pixels of that row for every code.
+//!
+//! The VQ header of MIL-STD-188-199 precedes the codes in the image data (after
+//! the mask table for `M4`): the image display parameters subheader, followed by
+//! the compression section subheader and the compression lookup subsection.
+//! RPF products such as CIB keep these components in the `RPFIMG` TRE instead,
+//! where they are found through its component location table.
+//!
+//! Without a mask table the blocks follow each other after the header.
+use std::borrow::Cow;
+
+use crate::headers::ImageHeader;
+use crate::image::{BlockLayout, DecodedBlock, MaskTable};
+use crate::segments::{NitfSegment, SegmentData};
+use crate::{NitfError, NitfResult};
+
+/// Compression algorithm ID of vector quantization
+const VQ_ALGORITHM: u64 = 1;
+/// Code bit length of RPF products without an image display parameters subheader
+const DEFAULT_CODE_BITS: u32 = 12;
+/// Size of the image display parameters subheader
+const DISPLAY_PARAMETERS_SIZE: u64 = 9;
+/// Size of the compression section subheader
+const COMPRESSION_SUBHEADER_SIZE: u64 = 6;
+/// Size of the header of the compression lookup subsection
+const LOOKUP_HEADER_SIZE: usize = 6;
+/// Size of a compression lookup offset record
+const LOOKUP_RECORD_SIZE: usize = 14;
+
+/// RPF component IDs (MIL-STD-2411)
+const RPF_COMPRESSION_SECTION: u64 = 131;
+const RPF_COMPRESSION_LOOKUP: u64 = 132;
+const RPF_DISPLAY_PARAMETERS: u64 = 137;
+
+fn vq_error(reason: impl std::fmt::Display) -> NitfError {
+    NitfError::Fatal(format!("VQ image data, {reason}"))
+}
+
+/// Big-endian unsigned integer of `n` bytes at `pos`
+fn uint(bytes: &[u8], pos: usize, n: usize) -> NitfResult<u64> {
+    let field = bytes
+        .get(pos..pos + n)
+        .ok_or_else(|| vq_error(format!("truncated at byte {pos}")))?;
+    Ok(field.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+}
+
+/// Reads `len` bytes at an offset relative to the start of a VQ header component
+type Component<'a> = dyn Fn(u64, usize) -> NitfResult<Cow<'a, [u8]>> + 'a;
+
+/// Decoder for the VQ blocks of an image segment
+pub(crate) struct VqBlocks {
+    /// One table per kernel row, holding `kernel_cols` pixels per code
+    tables: Vec<Vec<u8>>,
+    kernel_cols: usize,
+    code_bits: u32,
+    /// Offset of the codes of each block, `None` if the block was omitted
+    offsets: Vec<Option<u64>>,
+}
+impl VqBlocks {
+    pub(crate) fn new(
+        segment: &NitfSegment<ImageHeader>,
+        layout: &BlockLayout,
+        mask: Option<&MaskTable>,
+    ) -> NitfResult<Self> {
+        if layout.nbands != 1 || layout.nbpp != 8 {
+            return Err(NitfError::Unsupported(format!(
+                "VQ compression of {} bands with NBPP {}",
+                layout.nbands, layout.nbpp
+            )));
+        }
+        let start = mask.map_or(0, |mask| mask.imdatoff as u64);
+        let data = &segment.data;
+        let (mut blocks, codes_start) = match rpf_components(segment)? {
+            Some(components) => {
+                let find = |id| components.iter().find(|c| c.0 == id).map(|c| c.1);
+                let Some(lookup) = find(RPF_COMPRESSION_LOOKUP) else {
+                    return Err(vq_error("RPFIMG without a compression lookup subsection"));
+                };
+                let read =
+                    |base: u64| move |pos: u64, len: usize| rpf_read(segment, base + pos, len);
+                let n_tables = match find(RPF_COMPRESSION_SECTION) {
+                    Some(section) => compression_subheader(&read(section))?,
+                    None => 4,
+                };
+                let code_bits = match find(RPF_DISPLAY_PARAMETERS) {
+                    Some(parameters) => display_parameters(&read(parameters))?,
+                    None => DEFAULT_CODE_BITS,
+                };
+                let (tables, kernel_cols, _) = lookup_tables(&read(lookup), n_tables)?;
+                (Self::with_tables(tables, kernel_cols, code_bits)?, start)
+            }
+            None => {
+                let read = |base: u64| {
+                    move |pos: u64, len: usize| data.read_range(start + base + pos, len)
+                };
+                let code_bits = display_parameters(&read(0))?;
+                let n_tables = compression_subheader(&read(DISPLAY_PARAMETERS_SIZE))?;
+                let lookup = DISPLAY_PARAMETERS_SIZE + COMPRESSION_SUBHEADER_SIZE;
+                let (tables, kernel_cols, end) = lookup_tables(&read(lookup), n_tables)?;
+                let blocks = Self::with_tables(tables, kernel_cols, code_bits)?;
+                (blocks, start + lookup + end)
+            }
+        };
+        let block_bytes = blocks.block_bytes(layout)?;
+        blocks.offsets = (0..layout.n_blocks())
+            .map(|block| match mask.filter(|mask| !mask.bmr.is_empty()) {
+                Some(mask) => mask.block_offset(layout, block, 0),
+                None => Some(codes_start + block * block_bytes),
+            })
+            .collect();
+        Ok(blocks)
+    }
+
+    fn with_tables(tables: Vec<Vec<u8>>, kernel_cols: usize, code_bits: u32) -> NitfResult<Self> {
+        if !(1..=32).contains(&code_bits) {
+            return Err(vq_error(format!("image codes of {code_bits} bits")));
+        }
+        Ok(Self {
+            tables,
+            kernel_cols,
+            code_bits,
+            offsets: vec![],
+        })
+    }
+
+    /// Number of codes per block row and column
+    fn codes(&self, layout: &BlockLayout) -> NitfResult<(usize, usize)> {
+        let (kernel_rows, kernel_cols) = (self.tables.len(), self.kernel_cols);
+        let (nppbv, nppbh) = (layout.nppbv as usize, layout.nppbh as usize);
+        if !nppbv.is_multiple_of(kernel_rows) || !nppbh.is_multiple_of(kernel_cols) {
+            return Err(vq_error(format!(
+                "{kernel_rows} x {kernel_cols} kernels in {} x {} blocks",
+                layout.nppbv, layout.nppbh
+            )));
+        }
+        Ok((nppbv / kernel_rows, nppbh / kernel_cols))
+    }
+
+    /// Size of the codes of a block in bytes
+    fn block_bytes(&self, layout: &BlockLayout) -> NitfResult<u64> {
+        let (rows, cols) = self.codes(layout)?;
+        Ok((rows as u64 * cols as u64 * self.code_bits as u64).div_ceil(8))
+    }
+
+    /// Decode `block`, or `None` if it was omitted
+    pub(crate) fn decode(
+        &self,
+        data: &SegmentData,
+        layout: &BlockLayout,
+        block: u64,
+    ) -> NitfResult<Option<DecodedBlock>> {
+        let Some(offset) = self.offsets.get(block as usize).copied().flatten() else {
+            return Ok(None);
+        };
+        let bytes = data.read_range(offset, self.block_bytes(layout)? as usize)?;
+        let (rows, cols) = self.codes(layout)?;
+        let width = layout.nppbh as usize;
+        let n_codes = self.tables[0].len() / self.kernel_cols;
+        let mut plane = vec![0; layout.block_pixels() as usize];
+        let mut bit = 0;
+        for code_row in 0..rows {
+            for code_col in 0..cols {
+                let mut code = 0;
+                for _ in 0..self.code_bits {
+                    let byte = bytes[bit / 8];
+                    code = (code << 1) | ((byte >> (7 - bit % 8)) & 1) as usize;
+                    bit += 1;
+                }
+                if code >= n_codes {
+                    return Err(vq_error(format!("code {code} of {n_codes} entry codebook")));
+                }
+                let kernel = code * self.kernel_cols..(code + 1) * self.kernel_cols;
+                for (t, table) in self.tables.iter().enumerate() {
+                    let start =
+                        (code_row * self.tables.len() + t) * width + code_col * self.kernel_cols;
+                    let row = &mut plane[start..start + self.kernel_cols];
+                    for (out, pixel) in row.iter_mut().zip(&table[kernel.clone()]) {
+                        *out = *pixel as u32;
+                    }
+                }
+            }
+        }
+        Ok(Some(DecodedBlock {
+            nbits: 8,
+            planes: vec![plane],
+        }))
+    }
+}
+
+/// Code bit length from the image display parameters subheader
+fn display_parameters(read: &Component<'_>) -> NitfResult<u32> {
+    let bytes = read(0, DISPLAY_PARAMETERS_SIZE as usize)?;
+    Ok(uint(&bytes, 8, 1)? as u32)
+}
+
+/// Number of compression lookup tables from the compression section subheader
+fn compression_subheader(read: &Component<'_>) -> NitfResult<usize> {
+    let bytes = read(0, COMPRESSION_SUBHEADER_SIZE as usize)?;
+    let algorithm = uint(&bytes, 0, 2)?;
+    if algorithm != VQ_ALGORITHM {
+        return Err(NitfError::Unsupported(format!(
+            "compression algorithm {algorithm} in VQ header"
+        )));
+    }
+    Ok(uint(&bytes, 2, 2)? as usize)
+}
+
+/// Lookup tables of the compression lookup subsection, along with the number of
+/// values per code and the end of the subsection
+fn lookup_tables(read: &Component<'_>, n_tables: usize) -> NitfResult<(Vec<Vec<u8>>, usize, u64)> {
+    if n_tables == 0 {
+        return Err(vq_error("no compression lookup tables"));
+    }
+    let header = read(0, LOOKUP_HEADER_SIZE)?;
+    let records_offset = uint(&header, 0, 4)?;
+    let record_size = uint(&header, 4, 2)? as usize;
+    if record_size < LOOKUP_RECORD_SIZE {
+        return Err(vq_error(format!(
+            "lookup offset records of {record_size} bytes"
+        )));
+    }
+    let records = read(records_offset, n_tables * record_size)?;
+    let mut tables = Vec::with_capacity(n_tables);
+    let mut kernel_cols = 0;
+    let mut end = records_offset + records.len() as u64;
+    for record in records.chunks_exact(record_size) {
+        let n_codes = uint(record, 2, 4)? as usize;
+        let n_values = uint(record, 6, 2)? as usize;
+        let value_bits = uint(record, 8, 2)?;
+        let offset = uint(record, 10, 4)?;
+        if value_bits != 8 {
+            return Err(NitfError::Unsupported(format!(
+                "VQ lookup values of {value_bits} bits"
+            )));
+        }
+        if tables.is_empty() {
+            kernel_cols = n_values;
+        }
+        if n_values != kernel_cols || n_values == 0 {
+            return Err(vq_error("lookup tables of different kernel widths"));
+        }
+        let table = read(offset, n_codes * n_values)?.into_owned();
+        if let Some(first) = tables.first().map(Vec::len) {
+            if table.len() != first {
+                return Err(vq_error("lookup tables of different lengths"));
+            }
+        }
+        end = end.max(offset + table.len() as u64);
+        tables.push(table);
+    }
+    Ok((tables, kernel_cols, end))
+}
+
+/// `(component ID, file offset)` of the RPF components in the location table of
+/// the `RPFIMG` TRE, `None` without an `RPFIMG` TRE
+fn rpf_components(segment: &NitfSegment<ImageHeader>) -> NitfResult<Option<Vec<(u64, u64)>>> {
+    let Some((_, location)) = rpfimg(segment)? else {
+        return Ok(None);
+    };
+    let table_offset = uint(location, 2, 4)? as usize;
+    let n_records = uint(location, 6, 2)? as usize;
+    let record_size = uint(location, 8, 2)? as usize;
+    let mut components = Vec::with_capacity(n_records);
+    for i in 0..n_records {
+        let record = table_offset + i * record_size;
+        components.push((uint(location, record, 2)?, uint(location, record + 6, 4)?));
+    }
+    Ok(Some(components))
+}
+
+/// File offset and data of the `RPFIMG` TRE, which starts with the location section
+fn rpfimg(segment: &NitfSegment<ImageHeader>) -> NitfResult<Option<(u64, &[u8])>> {
+    let ixshd = &segment.meta.ixshd.tre;
+    // IXSHD is the last field of the subheader
+    let ixshd_offset = segment
+        .header_offset
+        .checked_add(segment.header_size as u64)
+        .and_then(|end| end.checked_sub(ixshd.len() as u64))
+        .ok_or_else(|| {
+            NitfError::InvalidValue(
+                "IXSHDL".to_string(),
+                format!(
+                    "{} (subheader of {} bytes)",
+                    ixshd.len(),
+                    segment.header_size
+                ),
+            )
+        })?;
+    let mut pos = 0;
+    for tre in segment.meta.ixshd.iter_tres() {
+        let tre = tre?;
+        if tre.tag == "RPFIMG" {
+            let start = pos + tre.size() - tre.data.len();
+            let data = &ixshd[start..start + tre.data.len()];
+            return Ok(Some((ixshd_offset + start as u64, data)));
+        }
+        pos += tre.size();
+    }
+    Ok(None)
+}
+
+/// Read `len` bytes at file offset `pos`, from the `RPFIMG` TRE or the segment data
+fn rpf_read(segment: &NitfSegment<ImageHeader>, pos: u64, len: usize) -> NitfResult<Cow<'_, [u8]>> {
+    if let Some((start, tre)) = rpfimg(segment)? {
+        if let Some(bytes) = pos
+            .checked_sub(start)
+            .and_then(|rel| tre.get(rel as usize..rel as usize + len))
+        {
+            return Ok(Cow::Borrowed(bytes));
+        }
+    }
+    match pos.checked_sub(segment.data_offset) {
+        Some(rel) if rel + len as u64 <= segment.data.len() => segment.data.read_range(rel, len),
+        _ => Err(vq_error(format!("RPF component at file offset {pos}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+
+    #[test]
+    fn rpfimg_offset() -> NitfResult<()> {
+        let header = ImageHeaderBuilder::new()?
+            .size(1, 1)?
+            .band("", "")?
+            .ixshd(b"RPFIMG00004ABCD")?;
+        let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(header.build()?, vec![0])
+            .build()?;
+        let mut segment = nitf.image_segments.remove(0);
+        segment.header_offset = 100;
+        segment.header_size = 400;
+        assert_eq!(rpfimg(&segment)?, Some((100 + 400 - 4, &b"ABCD"[..])));
+        // A subheader too short to hold its IXSHD
+        segment.header_offset = 0;
+        segment.header_size = 10;
+        assert!(rpfimg(&segment).is_err());
+        Ok(())
+    }
+}
@@ -2,6 +2,10 @@
 
 //! Interface for NITF version 2.1
 //!
+//! NITF 2.0 and NSIF 1.0 files are also read, with the version detected from
+//! the file header (see [types::NitfVersion]). Version-specific fields are
+//! read into the same header structures, so they can be handled uniformly.
+//!
 //! Constructing a [Nitf] object parses the header and subheader information.
 //! Each segment in contains a `meta` field which stores the respective
 //! fields defined in the file standard. The primary function for constructing a
@@ -13,18 +17,22 @@
 //! let nitf = nitf_rs::read_nitf(&nitf_path).unwrap();
 //! println!("{nitf:?}");
 //! ```
+//! Fields which fail to parse are logged and given a default value. To fail on
+//! them instead, or collect them into a list, read through [parse::ParseOptions].
 //!
 //! The main feature of the [FileHeader] is its `meta` field (see (NitfHeader)
 //! [headers::NitfHeader]).
 //! All other segments use the generic [NitfSegment] to provide header fields and
-//! a memory-map of the segment data.
+//! access to the segment data. When reading from a file the data is memory-mapped,
+//! but any [Read] + [Seek] source can be used with [Nitf::from_reader()] or
+//! [Nitf::from_reader_lazy()] (see [SegmentData]).
 //! ```no_run
 //! // Get the bytes from the first image segment
 //! use std::path::Path;
 //! let nitf_path = Path::new("../example.nitf");
 //! let nitf = nitf_rs::read_nitf(&nitf_path).unwrap();
 //! let im_seg = &nitf.image_segments[0];
-//! let u8_slice = &im_seg.data[..];
+//! let u8_slice = im_seg.data.as_slice().unwrap();
 //! ```
 //! Most metadata elements are stored in a [NitfField] structure. This structure
 //! stores the `bytes` which encode the value, a `string` representation, and a
@@ -43,11 +51,28 @@
 //! let n_rows = nitf.image_segments[0].meta.nrows.val;
 //! ```
 //!
+//! A [Nitf] can be written back out with [Nitf::write_to()]. The segment counts and
+//! length fields of the file header are recomputed, everything else is written as stored.
+//! ```no_run
+//! // Copy a nitf file
+//! use std::path::Path;
+//! let nitf_path = Path::new("../example.nitf");
+//! let nitf = nitf_rs::read_nitf(&nitf_path).unwrap();
+//! let mut out_file = std::fs::File::create("../copy.nitf").unwrap();
+//! nitf.write_to(&mut out_file).unwrap();
+//! ```
+//!
+//! New files are constructed with the builders in the [builder] module.
+//! Header fields can be checked for conformance with [Nitf::validate()], and the
+//! complexity level of a file is computed by [Nitf::required_clevel()].
+//!
 //! If there is user-defined tagged-record-extension (TRE) data within a segment,
-//! it is stored in an [ExtendedSubheader] for the user to parse accordingly.
+//! it is stored in an [ExtendedSubheader]. The [tre] module splits it into
+//! individual records and parses those with a registered typed parser.
 use log::debug;
 use std::fmt::Display;
 use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use thiserror::Error;
 
@@ -56,33 +81,129 @@ pub type NitfResult<T> = Result<T, NitfError>;
 #[derive(Error, Debug)]
 pub enum NitfError {
     // Crate specific errors
-    #[error("File does not appear to be a NITF. Expected file header \"NITF\", found \"{0}\"")]
+    #[error("File does not appear to be a NITF. Expected file header \"NITF\" or \"NSIF\", found \"{0}\"")]
     FileType(String),
     #[error("error parsing {0} enum")]
     EnumError(&'static str),
     #[error("Fatal error reading {0}")]
     Fatal(String),
+    #[error("Invalid value \"{1}\" for field {0}")]
+    InvalidValue(String, String),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+    #[error("Invalid field in {0}")]
+    Diagnostic(Box<parse::Diagnostic>),
+    #[error("Unsupported {fhdr} version \"{fver}\"")]
+    UnsupportedVersion { fhdr: String, fver: String },
+    #[error("Truncated at {location}, expected {expected} bytes but {actual} remain")]
+    Truncated {
+        location: Location,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Length mismatch at {location}, {expected} bytes given but {actual} read")]
+    LengthMismatch {
+        location: Location,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Invalid numeric value \"{value}\" at {location}")]
+    InvalidNumeric { location: Location, value: String },
 
     // Wrappers for built in errors
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 }
 
+/// Position of a field in the source being read
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Location {
+    /// Segment type and index (zero indexed) the field belongs to, if known
+    pub segment: Option<(SegmentKind, usize)>,
+    /// Field name, as given in the standard
+    pub field: String,
+    /// Byte offset of the field
+    pub offset: u64,
+}
+
+impl Location {
+    /// Location of `field` at `offset`, in a segment which is not known yet
+    pub(crate) fn field(field: &str, offset: u64) -> Self {
+        Self {
+            segment: None,
+            field: field.to_string(),
+            offset,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.segment {
+            Some((SegmentKind::FileHeader, _)) => write!(f, "file header, ")?,
+            Some((segment, index)) => write!(f, "{segment} {index}, ")?,
+            None => {}
+        }
+        write!(f, "{} at byte {}", self.field, self.offset)
+    }
+}
+
+impl NitfError {
+    /// Location of the field the error occurred at, if it is known
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Self::Truncated { location, .. }
+            | Self::LengthMismatch { location, .. }
+            | Self::InvalidNumeric { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Attribute an error without a segment to segment `index` of `segment`
+    pub(crate) fn in_segment(mut self, segment: SegmentKind, index: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.segment.get_or_insert((segment, index));
+        }
+        self
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match self {
+            Self::Truncated { location, .. }
+            | Self::LengthMismatch { location, .. }
+            | Self::InvalidNumeric { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+}
+
+pub mod builder;
+pub mod clevel;
+pub mod geo;
 pub mod headers;
+pub mod image;
+pub mod parse;
+mod recover;
 pub mod segments;
+pub mod tre;
 pub mod types;
+pub mod validate;
 
 // Convenience type-defs
-use segments::NitfSegment;
+use headers::nitf_file_hdr::SubHeader;
+use headers::NitfSegmentHeader;
+use parse::{ParseMode, ParseSession};
+use segments::{NitfSegment, SegmentData, SegmentKind, SharedReader};
 type ImageSegment = NitfSegment<headers::ImageHeader>;
 type GraphicSegment = NitfSegment<headers::GraphicHeader>;
+type LabelSegment = NitfSegment<headers::LabelHeader>;
 type TextSegment = NitfSegment<headers::TextHeader>;
 type DataExtensionSegment = NitfSegment<headers::DataExtensionHeader>;
 type ReservedExtensionSegment = NitfSegment<headers::ReservedExtensionHeader>;
 
 use segments::FileHeader;
 #[allow(unused_imports)]
-use types::{ExtendedSubheader, NitfField};
+use types::{ExtendedSubheader, NitfField, NitfVersion};
 
 /// Top level NITF interface
 #[derive(Default, Debug, Eq, PartialEq)]
@@ -93,9 +214,12 @@ pub struct Nitf {
     /// Vector of image segments.
     pub image_segments: Vec<ImageSegment>,
 
-    /// Vector of graphics segments.
+    /// Vector of graphics segments, which are symbol segments in NITF 2.0 files.
     pub graphic_segments: Vec<GraphicSegment>,
 
+    /// Vector of label segments, only present in NITF 2.0 files.
+    pub label_segments: Vec<LabelSegment>,
+
     /// Vector of text segments.
     pub text_segments: Vec<TextSegment>,
 
@@ -121,59 +245,324 @@ pub fn read_nitf(path: &Path) -> NitfResult<Nitf> {
 }
 
 impl Nitf {
+    /// Read a [Nitf] from a file, memory-mapping the segment data
     pub fn from_file(file: &mut File) -> NitfResult<Self> {
+        Self::from_file_with(file, &mut ParseSession::default())
+    }
+
+    /// [from_file()](Self::from_file), handling fields which fail to parse as `session` says
+    pub(crate) fn from_file_with(file: &mut File, session: &mut ParseSession) -> NitfResult<Self> {
+        Self::read_with(file, session, &mut |file: &mut File, offset, size| {
+            SegmentData::map(file, offset, size)
+        })
+    }
+
+    /// Read a [Nitf] from any [Read] + [Seek] source, copying the segment data into memory
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Cursor;
+    /// let mut reader = Cursor::new(b"not a nitf".to_vec());
+    /// assert!(nitf_rs::Nitf::from_reader(&mut reader).is_err());
+    /// ```
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> NitfResult<Self> {
+        Self::from_reader_with(reader, &mut ParseSession::default())
+    }
+
+    /// [from_reader()](Self::from_reader), handling fields which fail to parse as `session` says
+    pub(crate) fn from_reader_with<R: Read + Seek>(
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<Self> {
+        Self::read_with(reader, session, &mut SegmentData::read)
+    }
+
+    /// Read a [Nitf] from any [Read] + [Seek] source, only parsing the headers
+    ///
+    /// The reader is kept alive by the returned object, and segment data is
+    /// fetched from it on request (see [SegmentData::Lazy]).
+    pub fn from_reader_lazy<R: Read + Seek + Send + 'static>(reader: R) -> NitfResult<Self> {
+        Self::from_reader_lazy_with(reader, &mut ParseSession::default())
+    }
+
+    /// [from_reader_lazy()](Self::from_reader_lazy), handling fields which fail
+    /// to parse as `session` says
+    pub(crate) fn from_reader_lazy_with<R: Read + Seek + Send + 'static>(
+        reader: R,
+        session: &mut ParseSession,
+    ) -> NitfResult<Self> {
+        let shared = SharedReader::new(reader);
+        Self::read_with(
+            &mut shared.clone(),
+            session,
+            &mut |_: &mut SharedReader, offset, size| {
+                Ok(SegmentData::lazy(shared.clone(), offset, size))
+            },
+        )
+    }
+
+    /// Write the [Nitf] to `writer`
+    ///
+    /// The segment counts, subheader/data length tables, header length (HL) and
+    /// file length (FL) of the file header are recomputed from the segments.
+    /// All other fields are written as they are stored, so a file which was
+    /// read in is written back out unchanged.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        let images = encode_segments(&self.image_segments)?;
+        let graphics = encode_segments(&self.graphic_segments)?;
+        let labels = encode_segments(&self.label_segments)?;
+        let texts = encode_segments(&self.text_segments)?;
+        let data_extensions = encode_segments(&self.data_extension_segments)?;
+        let reserved_extensions = encode_segments(&self.reserved_extension_segments)?;
+
+        let mut header = self.nitf_header.meta.clone();
+        header.set_segment_sizes(
+            &segment_sizes(&images),
+            &segment_sizes(&graphics),
+            &segment_sizes(&labels),
+            &segment_sizes(&texts),
+            &segment_sizes(&data_extensions),
+            &segment_sizes(&reserved_extensions),
+        )?;
+        header.write(writer)?;
+        for (subheader, data) in images
+            .iter()
+            .chain(&graphics)
+            .chain(&labels)
+            .chain(&texts)
+            .chain(&data_extensions)
+            .chain(&reserved_extensions)
+        {
+            writer.write_all(subheader)?;
+            data.write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Recompute the file header counts and lengths, along with the segment offsets
+    ///
+    /// This is done automatically by [Nitf::write_to()], but is useful for keeping
+    /// a [Nitf] consistent after segments have been added or modified.
+    pub fn update_header(&mut self) -> NitfResult<()> {
+        let image_sizes = segment_sizes(&encode_segments(&self.image_segments)?);
+        let graphic_sizes = segment_sizes(&encode_segments(&self.graphic_segments)?);
+        let label_sizes = segment_sizes(&encode_segments(&self.label_segments)?);
+        let text_sizes = segment_sizes(&encode_segments(&self.text_segments)?);
+        let data_extension_sizes = segment_sizes(&encode_segments(&self.data_extension_segments)?);
+        let reserved_extension_sizes =
+            segment_sizes(&encode_segments(&self.reserved_extension_segments)?);
+        let header = &mut self.nitf_header;
+        header.meta.set_segment_sizes(
+            &image_sizes,
+            &graphic_sizes,
+            &label_sizes,
+            &text_sizes,
+            &data_extension_sizes,
+            &reserved_extension_sizes,
+        )?;
+        header.header_size = header.meta.hl.val as u64;
+
+        let mut offset = header.header_size;
+        update_offsets(&mut self.image_segments, &image_sizes, &mut offset);
+        update_offsets(&mut self.graphic_segments, &graphic_sizes, &mut offset);
+        update_offsets(&mut self.label_segments, &label_sizes, &mut offset);
+        update_offsets(&mut self.text_segments, &text_sizes, &mut offset);
+        update_offsets(
+            &mut self.data_extension_segments,
+            &data_extension_sizes,
+            &mut offset,
+        );
+        update_offsets(
+            &mut self.reserved_extension_segments,
+            &reserved_extension_sizes,
+            &mut offset,
+        );
+        Ok(())
+    }
+
+    /// Parse all headers from `reader`, using `load` to back the data of each segment
+    fn read_with<R, F>(reader: &mut R, session: &mut ParseSession, load: &mut F) -> NitfResult<Self>
+    where
+        R: Read + Seek,
+        F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+    {
         let mut nitf = Self::default();
+        // Segment data is checked against the end of the source before it is loaded
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
         debug!("Reading NITF file header");
-        nitf.nitf_header.read(file)?;
-
-        let mut n_seg = nitf.nitf_header.meta.numi.val as usize;
-        for i_seg in 0..n_seg {
-            let seg_info = &nitf.nitf_header.meta.imheaders[i_seg];
-            let header_size = seg_info.subheader_size.val;
-            let data_size = seg_info.item_size.val;
-            let seg = ImageSegment::initialize(file, header_size, data_size)?;
-            nitf.image_segments.push(seg);
-        }
-
-        n_seg = nitf.nitf_header.meta.nums.val as usize;
-        for i_seg in 0..n_seg {
-            let seg_info = &nitf.nitf_header.meta.graphheaders[i_seg];
-            let header_size = seg_info.subheader_size.val;
-            let data_size: u64 = seg_info.item_size.val;
-            let seg = GraphicSegment::initialize(file, header_size, data_size)?;
-            nitf.graphic_segments.push(seg);
-        }
-
-        n_seg = nitf.nitf_header.meta.numt.val as usize;
-        for i_seg in 0..n_seg {
-            let seg_info = &nitf.nitf_header.meta.textheaders[i_seg];
-            let header_size = seg_info.subheader_size.val;
-            let data_size: u64 = seg_info.item_size.val;
-            let seg = TextSegment::initialize(file, header_size, data_size)?;
-            nitf.text_segments.push(seg);
-        }
-
-        n_seg = nitf.nitf_header.meta.numdes.val as usize;
-        for i_seg in 0..n_seg {
-            let seg_info = &nitf.nitf_header.meta.dextheaders[i_seg];
-            let header_size = seg_info.subheader_size.val;
-            let data_size: u64 = seg_info.item_size.val;
-            let seg = DataExtensionSegment::initialize(file, header_size, data_size)?;
-            nitf.data_extension_segments.push(seg);
-        }
-
-        n_seg = nitf.nitf_header.meta.numres.val as usize;
-        for i_seg in 0..n_seg {
-            let seg_info = &nitf.nitf_header.meta.resheaders[i_seg];
-            let header_size = seg_info.subheader_size.val;
-            let data_size = seg_info.item_size.val;
-            let seg = ReservedExtensionSegment::initialize(file, header_size, data_size)?;
-            nitf.reserved_extension_segments.push(seg);
+        nitf.nitf_header
+            .read(reader, session)
+            .map_err(|error| error.in_segment(SegmentKind::FileHeader, 0))?;
+
+        let meta = &nitf.nitf_header.meta;
+        let version = meta.version();
+        debug!("Reading {version} segments");
+        // Subheaders searched for while recovering a file start after the file header
+        let mut floor = nitf.nitf_header.header_size;
+        nitf.image_segments = read_segments(
+            reader,
+            session,
+            &meta.imheaders,
+            SegmentKind::Image,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        nitf.graphic_segments = read_segments(
+            reader,
+            session,
+            &meta.graphheaders,
+            SegmentKind::Graphic,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        nitf.label_segments = read_segments(
+            reader,
+            session,
+            &meta.labelheaders,
+            SegmentKind::Label,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        nitf.text_segments = read_segments(
+            reader,
+            session,
+            &meta.textheaders,
+            SegmentKind::Text,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        nitf.data_extension_segments = read_segments(
+            reader,
+            session,
+            &meta.dextheaders,
+            SegmentKind::DataExtension,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        nitf.reserved_extension_segments = read_segments(
+            reader,
+            session,
+            &meta.resheaders,
+            SegmentKind::ReservedExtension,
+            version,
+            end,
+            &mut floor,
+            load,
+        )?;
+        if session.mode() == ParseMode::Recover {
+            recover::fit_data(&mut nitf, reader, session, load)?;
         }
         Ok(nitf)
     }
 }
 
+/// Helper function for reading all segments of a single type
+///
+/// `floor` is only used when recovering a damaged file, see [recover::read_segments()]
+#[allow(clippy::too_many_arguments)]
+fn read_segments<R, F, T>(
+    reader: &mut R,
+    session: &mut ParseSession,
+    seg_infos: &[SubHeader],
+    kind: SegmentKind,
+    version: NitfVersion,
+    end: u64,
+    floor: &mut u64,
+    load: &mut F,
+) -> NitfResult<Vec<NitfSegment<T>>>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+    T: NitfSegmentHeader,
+{
+    if session.mode() == ParseMode::Recover {
+        return recover::read_segments(reader, session, seg_infos, kind, version, end, floor, load);
+    }
+    let mut segments = Vec::with_capacity(seg_infos.len());
+    let (sh_name, item_name) = kind.length_fields();
+    for (index, seg_info) in seg_infos.iter().enumerate() {
+        session.enter_segment(kind, index);
+        let header_size = seg_info.subheader_size.val;
+        let data_size = seg_info.item_size.val;
+        let data_offset = reader.stream_position()? + header_size as u64;
+        if data_offset + data_size > end {
+            return Err(NitfError::Truncated {
+                location: Location {
+                    segment: Some((kind, index)),
+                    field: format!("{item_name}{:03}", index + 1),
+                    offset: seg_info.item_size.offset(),
+                },
+                expected: data_size,
+                actual: end.saturating_sub(data_offset),
+            });
+        }
+        let segment =
+            NitfSegment::<T>::initialize(reader, session, header_size, data_size, version, load)
+                .map_err(|error| error.in_segment(kind, index))?;
+        // The subheader is written back as it was read, so its encoding gives the length read
+        let mut subheader = vec![];
+        segment.meta.write(&mut subheader)?;
+        session
+            .check_length(
+                &format!("{sh_name}{:03}", index + 1),
+                &seg_info.subheader_size,
+                header_size as u64,
+                subheader.len() as u64,
+            )
+            .map_err(|error| error.in_segment(kind, index))?;
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+/// Helper function for serializing the subheaders of all segments of a single type
+fn encode_segments<T: NitfSegmentHeader>(
+    segments: &[NitfSegment<T>],
+) -> NitfResult<Vec<(Vec<u8>, &SegmentData)>> {
+    let mut encoded = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let mut subheader = vec![];
+        segment.meta.write(&mut subheader)?;
+        encoded.push((subheader, &segment.data));
+    }
+    Ok(encoded)
+}
+
+/// Helper function for collecting (subheader size, data size) pairs
+fn segment_sizes(encoded: &[(Vec<u8>, &SegmentData)]) -> Vec<(u32, u64)> {
+    encoded
+        .iter()
+        .map(|(subheader, data)| (subheader.len() as u32, data.len()))
+        .collect()
+}
+
+/// Helper function for laying out segments one after another, starting at `offset`
+fn update_offsets<T: NitfSegmentHeader>(
+    segments: &mut [NitfSegment<T>],
+    sizes: &[(u32, u64)],
+    offset: &mut u64,
+) {
+    for (segment, (header_size, data_size)) in segments.iter_mut().zip(sizes) {
+        segment.header_offset = *offset;
+        segment.header_size = *header_size;
+        segment.data_offset = *offset + *header_size as u64;
+        segment.data_size = *data_size;
+        *offset = segment.data_offset + segment.data_size;
+    }
+}
+
 impl Display for Nitf {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut out_str = String::default();
@@ -184,6 +573,9 @@ impl Display for Nitf {
         for segment in &self.graphic_segments {
             out_str += format!("{}", segment).as_ref();
         }
+        for segment in &self.label_segments {
+            out_str += format!("{}", segment).as_ref();
+        }
         for segment in &self.text_segments {
             out_str += format!("{}", segment).as_ref();
         }
@@ -196,3 +588,305 @@ impl Display for Nitf {
         write!(f, "{}", out_str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{
+        DataExtensionHeaderBuilder, ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder,
+        TextHeaderBuilder,
+    };
+    use crate::headers::graphic_hdr::Format;
+    use crate::headers::image_hdr::{Compression, CoordinateRepresentation};
+    use crate::parse::ParseOptions;
+    use std::io::Cursor;
+
+    fn write(nitf: &Nitf) -> NitfResult<Vec<u8>> {
+        let mut bytes = vec![];
+        nitf.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn text(data: &[u8]) -> NitfResult<TextSegment> {
+        let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .text(
+                TextHeaderBuilder::new()?.textid("ADDED")?.build(),
+                data.to_vec(),
+            )
+            .build()?;
+        Ok(nitf.text_segments.remove(0))
+    }
+
+    #[test]
+    fn write_round_trip() -> NitfResult<()> {
+        let image = ImageHeaderBuilder::new()?
+            .size(2, 2)?
+            .band("M", "")?
+            .ic(Compression::C3, "00.2")?
+            .comment("first comment")?
+            .comment("second comment")?
+            .ixshd(b"TESTAA00005hello")?
+            .build()?;
+        let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+            .image(image, b"compressed".to_vec())
+            .text(TextHeaderBuilder::new()?.build(), b"some text".to_vec())
+            .data_extension(
+                DataExtensionHeaderBuilder::new("TEST_DES")?.build(),
+                b"des data".to_vec(),
+            )
+            .build()?;
+        let bytes = write(&nitf)?;
+        let mut read = Nitf::from_reader(&mut Cursor::new(&bytes))?;
+        assert_eq!(write(&read)?, bytes);
+
+        let image = &read.image_segments[0];
+        let icoms: Vec<&str> = image.meta.icoms.iter().map(|c| c.val.as_str()).collect();
+        assert_eq!(icoms, ["first comment", "second comment"]);
+        assert_eq!(image.meta.comrat.val, "00.2");
+        assert_eq!(image.meta.ixshd.tre, b"TESTAA00005hello");
+        assert_eq!(image.data.to_vec()?, b"compressed");
+        assert_eq!(read.text_segments[0].data.to_vec()?, b"some text");
+        assert_eq!(read.data_extension_segments[0].data.to_vec()?, b"des data");
+
+        // Length tables, HL and FL follow changes to the data and segments
+        read.image_segments[0].data = b"longer compressed data".to_vec().into();
+        read.text_segments.push(text(b"added text")?);
+        let bytes = write(&read)?;
+        let read = Nitf::from_reader(&mut Cursor::new(&bytes))?;
+        let meta = &read.nitf_header.meta;
+        assert_eq!(meta.fl.val, bytes.len() as u64);
+        assert_eq!(meta.hl.val as u64, read.image_segments[0].header_offset);
+        assert_eq!(meta.imheaders[0].item_size.val, 22);
+        assert_eq!(meta.numt.val, 2);
+        assert_eq!(meta.textheaders[1].item_size.val, 10);
+        assert_eq!(
+            read.image_segments[0].data.to_vec()?,
+            b"longer compressed data"
+        );
+        assert_eq!(read.text_segments[1].data.to_vec()?, b"added text");
+        assert_eq!(read.data_extension_segments[0].data.to_vec()?, b"des data");
+        Ok(())
+    }
+
+    /// Left-justified, space-filled field
+    fn alpha(value: &str, width: usize) -> Vec<u8> {
+        format!("{value:<width$}").into_bytes()
+    }
+
+    /// Right-justified, zero-filled field
+    fn num(value: usize, width: usize) -> Vec<u8> {
+        format!("{value:0width$}").into_bytes()
+    }
+
+    /// NITF 2.0 security fields, with a downgrading event when `event` is given
+    fn security20(clas: &str, event: Option<&str>) -> Vec<u8> {
+        let mut security = [alpha(clas, 1), alpha("", 160)].concat();
+        match event {
+            Some(event) => security.extend([alpha("999998", 6), alpha(event, 40)].concat()),
+            None => security.extend(alpha("", 6)),
+        }
+        security
+    }
+
+    fn image20(icords: &str) -> Vec<u8> {
+        let mut header = [
+            alpha("IM", 2),
+            alpha("IID", 10),
+            alpha("010000ZJAN90", 14),
+            alpha("", 17),
+            alpha("image title", 80),
+            security20("S", None),
+            alpha("0", 1),
+            alpha("source", 42),
+            num(2, 8),
+            num(3, 8),
+            alpha("INT", 3),
+            alpha("MONO", 8),
+            alpha("VIS", 8),
+            num(8, 2),
+            alpha("R", 1),
+            alpha(icords, 1),
+        ]
+        .concat();
+        if icords != "N" {
+            header.extend(b"100000N0100000E".repeat(4));
+        }
+        header.extend(
+            [
+                alpha("0NC1M", 5),
+                alpha("", 7),
+                alpha("N", 1),
+                alpha("", 3),
+                alpha("00B", 3),
+                num(1, 4),
+                num(1, 4),
+                num(3, 4),
+                num(2, 4),
+                num(8, 2),
+                num(1, 3),
+                num(0, 3),
+                num(0, 10),
+                alpha("1.0", 4),
+                num(0, 5),
+                num(0, 5),
+            ]
+            .concat(),
+        );
+        header
+    }
+
+    /// Bit-mapped symbol with a two entry look-up table
+    fn symbol20() -> Vec<u8> {
+        [
+            alpha("SY", 2),
+            alpha("SID", 10),
+            alpha("symbol name", 20),
+            security20("U", Some("on event")),
+            alpha("0B", 2),
+            num(2, 4),
+            num(4, 4),
+            num(0, 4),
+            num(1, 1),
+            num(2, 3),
+            num(0, 3),
+            num(5, 10),
+            num(0, 10),
+            alpha("C", 1),
+            alpha("", 6),
+            num(0, 3),
+            num(2, 3),
+            vec![0, 0, 0, 255, 255, 255],
+            num(0, 5),
+        ]
+        .concat()
+    }
+
+    fn label20() -> Vec<u8> {
+        [
+            alpha("LA", 2),
+            alpha("LID", 10),
+            security20("S", None),
+            alpha("0", 1),
+            alpha("", 1),
+            num(0, 2),
+            num(0, 2),
+            num(3, 3),
+            num(0, 3),
+            num(7, 10),
+            vec![255, 0, 0, 0, 0, 0],
+            num(0, 5),
+        ]
+        .concat()
+    }
+
+    fn text20() -> Vec<u8> {
+        [
+            alpha("TE", 2),
+            alpha("TEXTID0123", 10),
+            alpha("010000ZJAN90", 14),
+            alpha("text title", 80),
+            security20("S", None),
+            alpha("0STA", 4),
+            num(0, 5),
+        ]
+        .concat()
+    }
+
+    /// Widths of the subheader and data length fields of a segment type, and
+    /// the (subheader, data) of each of its segments
+    type Table = ((usize, usize), Vec<(Vec<u8>, Vec<u8>)>);
+
+    /// NITF 2.0 file with the segments of each table, in order
+    fn nitf20(tables: &[Table]) -> Vec<u8> {
+        let header = |fl: usize, hl: usize| {
+            let mut header = [
+                alpha("NITF02.0003BF01OSTAID", 25),
+                alpha("010000ZJAN90", 14),
+                alpha("file title", 80),
+                security20("U", Some("on event")),
+                num(0, 10),
+                alpha("0", 1),
+                alpha("originator", 27),
+                alpha("", 18),
+                num(fl, 12),
+                num(hl, 6),
+            ]
+            .concat();
+            for ((sh_width, item_width), segments) in tables {
+                header.extend(num(segments.len(), 3));
+                for (subheader, data) in segments {
+                    header.extend(num(subheader.len(), *sh_width));
+                    header.extend(num(data.len(), *item_width));
+                }
+            }
+            header.extend(num(0, 13));
+            header
+        };
+        let body: Vec<u8> = tables
+            .iter()
+            .flat_map(|(_, segments)| segments)
+            .flat_map(|(subheader, data)| [subheader.as_slice(), data].concat())
+            .collect();
+        let hl = header(0, 0).len();
+        [header(hl + body.len(), hl), body].concat()
+    }
+
+    #[test]
+    fn nitf20_fields_and_round_trip() -> NitfResult<()> {
+        let bytes = nitf20(&[
+            (
+                (6, 10),
+                vec![
+                    (image20("N"), (0..6).collect()),
+                    (image20("G"), (0..6).collect()),
+                ],
+            ),
+            ((4, 6), vec![(symbol20(), vec![0b0110_0000])]),
+            ((4, 3), vec![(label20(), b"HELLO".to_vec())]),
+            ((4, 5), vec![(text20(), b"text".to_vec())]),
+            ((4, 9), vec![]),
+        ]);
+        let (nitf, diagnostics) = ParseOptions::strict().from_reader(&mut Cursor::new(&bytes))?;
+        assert!(diagnostics.is_empty());
+
+        let meta = &nitf.nitf_header.meta;
+        assert_eq!(meta.version(), NitfVersion::Nitf20);
+        assert_eq!(meta.oname.val, "originator");
+        assert_eq!(meta.security.dwng.val, "999998");
+        assert_eq!(meta.security.devt.val, "on event");
+        let (llsh, ll) = (
+            &meta.labelheaders[0].subheader_size,
+            &meta.labelheaders[0].item_size,
+        );
+        assert_eq!((llsh.val as usize, ll.val), (label20().len(), 5));
+
+        // ICORDS N has no IGEOLO
+        let (first, second) = (&nitf.image_segments[0], &nitf.image_segments[1]);
+        assert_eq!(first.meta.icords.val, CoordinateRepresentation::DEFAULT);
+        assert!(first.meta.igeolo.is_empty());
+        assert!(first.meta.security.devt.val.is_empty());
+        assert_eq!(second.meta.igeolo.len(), 4);
+        assert_eq!(
+            first.read_band(0)?,
+            image::PixelBuffer::U8((0..6).collect())
+        );
+
+        let symbol = &nitf.graphic_segments[0];
+        assert_eq!(symbol.meta.sfmt.val, Format::B);
+        assert_eq!((symbol.meta.nlips.val, symbol.meta.npixpl.val), (2, 4));
+        assert_eq!(symbol.meta.dlut, [0, 0, 0, 255, 255, 255]);
+        assert_eq!(symbol.meta.security.devt.val, "on event");
+        assert_eq!(symbol.data.to_vec()?, [0b0110_0000]);
+
+        let label = &nitf.label_segments[0];
+        assert_eq!(label.meta.lid.val, "LID");
+        assert_eq!(label.meta.lloc.val.col, 7);
+        assert_eq!(label.data.to_vec()?, b"HELLO");
+        assert_eq!(nitf.text_segments[0].data.to_vec()?, b"text");
+
+        let mut written = vec![];
+        nitf.write_to(&mut written)?;
+        assert_eq!(written, bytes);
+        Ok(())
+    }
+}
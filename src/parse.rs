@@ -0,0 +1,296 @@
+//! Options for reading files, and diagnostics of fields which fail to parse
+//!
+//! By default a field which fails to parse is logged and given a default value.
+//! Reading through [ParseOptions] instead either fails on the first such field
+//! ([ParseMode::Strict]), or collects a [Diagnostic] for each of them which is
+//! returned alongside the [Nitf] ([ParseMode::Lenient]). [ParseMode::Recover]
+//! additionally salvages segments of files which are truncated or have wrong
+//! length tables. The state of a read is held by a [ParseSession], which is
+//! handed to each header and field as it is parsed.
+//! ```
+//! use std::io::Cursor;
+//! use nitf_rs::builder::{NitfBuilder, NitfHeaderBuilder};
+//! use nitf_rs::parse::ParseOptions;
+//!
+//! let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build()).build()?;
+//! let mut bytes = vec![];
+//! nitf.write_to(&mut bytes)?;
+//! let (read, diagnostics) = ParseOptions::strict().from_reader(&mut Cursor::new(&bytes))?;
+//! assert!(diagnostics.is_empty());
+//!
+//! // Corrupt the file security classification
+//! let clas = read.nitf_header.meta.security.clas.offset() as usize;
+//! bytes[clas] = b'X';
+//!
+//! let (_, diagnostics) = ParseOptions::lenient().from_reader(&mut Cursor::new(&bytes))?;
+//! assert_eq!(diagnostics[0].field, "CLAS");
+//! assert_eq!(diagnostics[0].raw_bytes, b"X");
+//! assert!(ParseOptions::strict().from_reader(&mut Cursor::new(&bytes)).is_err());
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use log::warn;
+use std::fmt::{Debug, Display};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::segments::SegmentKind;
+use crate::types::NitfField;
+use crate::{Location, Nitf, NitfError, NitfResult};
+
+/// How fields which fail to parse are handled
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum ParseMode {
+    /// Fail on the first field which does not parse
+    Strict,
+    /// Give the field a default value, and collect a [Diagnostic]
+    #[default]
+    Lenient,
+    /// As [ParseMode::Lenient], and also recover the readable segments of a
+    /// file whose length tables do not match its contents
+    ///
+    /// Subheaders which are not where the length tables place them are searched
+    /// for by their signature (such as `IM`), segment data is clamped to the end
+    /// of the file and fitted to the start of the next subheader, and segments
+    /// which cannot be found are left out. Each recovered segment whose length
+    /// table entry was wrong or whose data is cut short is marked
+    /// [damaged](crate::segments::NitfSegment::damaged), and each repair is
+    /// collected as a [Diagnostic].
+    Recover,
+}
+
+/// Options for reading a [Nitf]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+}
+
+/// Field which failed to parse
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Segment type the field belongs to
+    pub segment: SegmentKind,
+    /// Index (zero indexed) of the segment among those of its type, 0 for the file header
+    pub index: usize,
+    /// Field name, as given in the standard
+    pub field: String,
+    /// Byte offset of the field in the source
+    pub offset: u64,
+    /// Bytes of the field as read
+    pub raw_bytes: Vec<u8>,
+    /// Description of the failure
+    pub reason: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.segment {
+            SegmentKind::FileHeader => write!(f, "{}", self.segment)?,
+            _ => write!(f, "{} {}", self.segment, self.index)?,
+        }
+        write!(
+            f,
+            ", {} at byte {}: {} ({:?})",
+            self.field,
+            self.offset,
+            self.reason,
+            String::from_utf8_lossy(&self.raw_bytes)
+        )
+    }
+}
+
+impl ParseOptions {
+    /// Options failing on the first field which does not parse
+    pub fn strict() -> Self {
+        Self {
+            mode: ParseMode::Strict,
+        }
+    }
+
+    /// Options collecting a [Diagnostic] for each field which does not parse
+    pub fn lenient() -> Self {
+        Self {
+            mode: ParseMode::Lenient,
+        }
+    }
+
+    /// Options recovering what can be read of a damaged file, see [ParseMode::Recover]
+    /// ```
+    /// use std::io::Cursor;
+    /// use nitf_rs::builder::{NitfBuilder, NitfHeaderBuilder, TextHeaderBuilder};
+    /// use nitf_rs::parse::ParseOptions;
+    ///
+    /// let nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.build())
+    ///     .text(TextHeaderBuilder::new()?.build(), b"first".to_vec())
+    ///     .text(TextHeaderBuilder::new()?.build(), b"second".to_vec())
+    ///     .build()?;
+    /// let mut bytes = vec![];
+    /// nitf.write_to(&mut bytes)?;
+    ///
+    /// // Cut the file off in the middle of the second text
+    /// bytes.truncate(bytes.len() - 3);
+    /// assert!(ParseOptions::lenient().from_reader(&mut Cursor::new(&bytes)).is_err());
+    ///
+    /// let (read, diagnostics) = ParseOptions::recover().from_reader(&mut Cursor::new(&bytes))?;
+    /// assert!(!read.text_segments[0].damaged);
+    /// assert!(read.text_segments[1].damaged);
+    /// assert_eq!(read.text_segments[1].data.to_vec()?, b"sec");
+    /// assert_eq!(diagnostics[0].field, "LT002");
+    /// # Ok::<(), nitf_rs::NitfError>(())
+    /// ```
+    pub fn recover() -> Self {
+        Self {
+            mode: ParseMode::Recover,
+        }
+    }
+
+    /// Read a file, see [read_nitf()](crate::read_nitf)
+    pub fn read_nitf(&self, path: &Path) -> NitfResult<(Nitf, Vec<Diagnostic>)> {
+        let mut file = File::open(path)?;
+        let mut session = ParseSession::new(*self);
+        let nitf = Nitf::from_file_with(&mut file, &mut session)?;
+        Ok((nitf, session.into_diagnostics()))
+    }
+
+    /// Read from any [Read] + [Seek] source, see [Nitf::from_reader()]
+    pub fn from_reader<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> NitfResult<(Nitf, Vec<Diagnostic>)> {
+        let mut session = ParseSession::new(*self);
+        let nitf = Nitf::from_reader_with(reader, &mut session)?;
+        Ok((nitf, session.into_diagnostics()))
+    }
+
+    /// Read only the headers from any [Read] + [Seek] source, see [Nitf::from_reader_lazy()]
+    pub fn from_reader_lazy<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> NitfResult<(Nitf, Vec<Diagnostic>)> {
+        let mut session = ParseSession::new(*self);
+        let nitf = Nitf::from_reader_lazy_with(reader, &mut session)?;
+        Ok((nitf, session.into_diagnostics()))
+    }
+}
+
+/// State of a read, handed to each header and field as it is parsed
+///
+/// Fields which fail to parse are handled according to the [ParseMode] of the
+/// read, and a [Diagnostic] is collected for each of them. Reads which are not
+/// started through [ParseOptions] are lenient and drop their diagnostics.
+#[derive(Debug)]
+pub struct ParseSession {
+    mode: ParseMode,
+    /// Segment the fields being parsed belong to
+    segment: SegmentKind,
+    index: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for ParseSession {
+    fn default() -> Self {
+        Self::new(ParseOptions::default())
+    }
+}
+
+impl ParseSession {
+    /// Session of a read with `options`
+    pub fn new(options: ParseOptions) -> Self {
+        Self {
+            mode: options.mode,
+            segment: SegmentKind::FileHeader,
+            index: 0,
+            diagnostics: vec![],
+        }
+    }
+
+    /// How fields which fail to parse are handled
+    pub fn mode(&self) -> ParseMode {
+        self.mode
+    }
+
+    /// Diagnostics collected so far
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Diagnostics collected by the read
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    /// Session for the same segment which fails on the first field which does
+    /// not parse, for trial reads whose diagnostics are not wanted
+    pub(crate) fn strict(&self) -> Self {
+        Self {
+            mode: ParseMode::Strict,
+            segment: self.segment,
+            index: self.index,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Drop the diagnostics collected after the first `len`, those of a read
+    /// whose result is discarded
+    pub(crate) fn truncate_diagnostics(&mut self, len: usize) {
+        self.diagnostics.truncate(len);
+    }
+
+    /// Attribute the fields parsed from here on to segment `index` of `segment`
+    pub(crate) fn enter_segment(&mut self, segment: SegmentKind, index: usize) {
+        self.segment = segment;
+        self.index = index;
+    }
+
+    /// Handle a field which failed to parse or is inconsistent with the file
+    ///
+    /// In strict mode `error` turns the diagnostic into the error to fail with.
+    pub(crate) fn report(
+        &mut self,
+        field: &str,
+        offset: u64,
+        raw_bytes: &[u8],
+        reason: String,
+        error: impl FnOnce(Diagnostic) -> NitfError,
+    ) -> NitfResult<()> {
+        let diagnostic = Diagnostic {
+            segment: self.segment,
+            index: self.index,
+            field: field.to_string(),
+            offset,
+            raw_bytes: raw_bytes.to_vec(),
+            reason,
+        };
+        match self.mode {
+            ParseMode::Strict => Err(error(diagnostic)),
+            ParseMode::Lenient | ParseMode::Recover => {
+                self.diagnostics.push(diagnostic);
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle a length `field` giving `expected` bytes where `actual` were read,
+    /// failing with [NitfError::LengthMismatch] in strict mode
+    pub(crate) fn check_length<V: FromStr + Debug>(
+        &mut self,
+        name: &str,
+        field: &NitfField<V>,
+        expected: u64,
+        actual: u64,
+    ) -> NitfResult<()> {
+        if expected == actual {
+            return Ok(());
+        }
+        let reason = format!("gives {expected} bytes, but {actual} were read");
+        warn!("{name} {reason}");
+        self.report(name, field.offset(), &field.bytes, reason, |_| {
+            NitfError::LengthMismatch {
+                location: Location::field(name, field.offset()),
+                expected,
+                actual,
+            }
+        })
+    }
+}
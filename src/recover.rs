@@ -0,0 +1,450 @@
+//! Recovery of the segments of files whose length tables do not match their
+//! contents, see [ParseMode::Recover](crate::parse::ParseMode::Recover)
+use log::warn;
+use std::fmt::Debug;
+use std::io::{Read, Seek, SeekFrom::Start};
+use std::str::FromStr;
+
+use crate::headers::nitf_file_hdr::SubHeader;
+use crate::headers::NitfSegmentHeader;
+use crate::parse::ParseSession;
+use crate::segments::{NitfSegment, SegmentData, SegmentKind};
+use crate::types::{NitfField, NitfVersion};
+use crate::{Nitf, NitfError, NitfResult};
+
+/// Number of bytes read at a time while searching for a subheader
+const SCAN_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Read the segments described by `seg_infos`, searching for subheaders which
+/// are not where the length tables place them
+///
+/// `floor` is the earliest offset a subheader can start at, which is the data
+/// offset of the last segment found. Segment data is clamped to `end`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn read_segments<R, F, T>(
+    reader: &mut R,
+    session: &mut ParseSession,
+    seg_infos: &[SubHeader],
+    kind: SegmentKind,
+    version: NitfVersion,
+    end: u64,
+    floor: &mut u64,
+    load: &mut F,
+) -> NitfResult<Vec<NitfSegment<T>>>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+    T: NitfSegmentHeader,
+{
+    let mut segments = Vec::with_capacity(seg_infos.len());
+    for (index, seg_info) in seg_infos.iter().enumerate() {
+        session.enter_segment(kind, index);
+        let (sh_name, item_name) = SubHeader::field_names(kind, index);
+        let (sh_field, item_field) = (&seg_info.subheader_size, &seg_info.item_size);
+        let expected = reader.stream_position()?;
+        let Some((header_offset, meta)) =
+            find_subheader::<R, T>(reader, session, kind, version, expected, *floor, end)?
+        else {
+            // Later segments of the same type can not be found either
+            let reason = format!("subheader not found after byte {floor}");
+            report(session, &sh_name, sh_field, reason)?;
+            reader.seek(Start(expected))?;
+            break;
+        };
+        let mut damaged = false;
+        if header_offset != expected {
+            let reason = format!("subheader found at byte {header_offset} instead of {expected}");
+            // The length table entry at fault is found by fit_data()
+            report(session, &sh_name, sh_field, reason)?;
+        }
+        let header_size = reader.stream_position()? - header_offset;
+        if header_size != sh_field.val as u64 {
+            session.check_length(&sh_name, sh_field, sh_field.val as u64, header_size)?;
+            damaged = true;
+        }
+        let data_offset = header_offset + header_size;
+        let mut data_size = item_field.val;
+        if data_offset + data_size > end {
+            data_size = end.saturating_sub(data_offset);
+            let reason = format!("data clamped to the {data_size} bytes left in the file");
+            report(session, &item_name, item_field, reason)?;
+            damaged = true;
+        }
+        let data = load(reader, data_offset, data_size)?;
+        reader.seek(Start(data_offset + data_size))?;
+        *floor = data_offset;
+        segments.push(NitfSegment {
+            meta,
+            data,
+            header_offset,
+            header_size: header_size as u32,
+            data_offset,
+            data_size,
+            damaged,
+        });
+    }
+    Ok(segments)
+}
+
+/// Fit the data of every segment to end at the start of the next subheader
+///
+/// A data length which is off is only noticed once the next segment has been
+/// found, so this is done after all segments are read.
+pub(crate) fn fit_data<R, F>(
+    nitf: &mut Nitf,
+    reader: &mut R,
+    session: &mut ParseSession,
+    load: &mut F,
+) -> NitfResult<()>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+{
+    let starts: Vec<u64> = header_offsets(&nitf.image_segments)
+        .chain(header_offsets(&nitf.graphic_segments))
+        .chain(header_offsets(&nitf.label_segments))
+        .chain(header_offsets(&nitf.text_segments))
+        .chain(header_offsets(&nitf.data_extension_segments))
+        .chain(header_offsets(&nitf.reserved_extension_segments))
+        .collect();
+    let meta = &nitf.nitf_header.meta;
+    let mut fit = Fit {
+        starts: &starts,
+        reader,
+        session,
+        load,
+    };
+    fit.segments(
+        &mut nitf.image_segments,
+        &meta.imheaders,
+        SegmentKind::Image,
+    )?;
+    fit.segments(
+        &mut nitf.graphic_segments,
+        &meta.graphheaders,
+        SegmentKind::Graphic,
+    )?;
+    fit.segments(
+        &mut nitf.label_segments,
+        &meta.labelheaders,
+        SegmentKind::Label,
+    )?;
+    fit.segments(
+        &mut nitf.text_segments,
+        &meta.textheaders,
+        SegmentKind::Text,
+    )?;
+    fit.segments(
+        &mut nitf.data_extension_segments,
+        &meta.dextheaders,
+        SegmentKind::DataExtension,
+    )?;
+    fit.segments(
+        &mut nitf.reserved_extension_segments,
+        &meta.resheaders,
+        SegmentKind::ReservedExtension,
+    )
+}
+
+/// Subheader offsets of every segment, and the reader to reload fitted data from
+struct Fit<'a, R, F> {
+    starts: &'a [u64],
+    reader: &'a mut R,
+    session: &'a mut ParseSession,
+    load: &'a mut F,
+}
+
+impl<R, F> Fit<'_, R, F>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+{
+    fn segments<T: NitfSegmentHeader>(
+        &mut self,
+        segments: &mut [NitfSegment<T>],
+        seg_infos: &[SubHeader],
+        kind: SegmentKind,
+    ) -> NitfResult<()> {
+        for (index, (segment, seg_info)) in segments.iter_mut().zip(seg_infos).enumerate() {
+            let data_end = segment.data_offset + segment.data_size;
+            let next = self
+                .starts
+                .iter()
+                .copied()
+                .filter(|start| *start > segment.header_offset)
+                .min();
+            let Some(next) = next.filter(|next| *next != data_end) else {
+                continue;
+            };
+            self.session.enter_segment(kind, index);
+            segment.data_size = next.saturating_sub(segment.data_offset);
+            segment.data = (self.load)(self.reader, segment.data_offset, segment.data_size)?;
+            segment.damaged = true;
+            let reason = format!(
+                "data fitted to {} bytes, up to the subheader at byte {next}",
+                segment.data_size
+            );
+            let name = SubHeader::field_names(kind, index).1;
+            report(self.session, &name, &seg_info.item_size, reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Subheader at `expected`, or else the first one after `floor` which parses
+/// without any invalid fields, along with its offset
+///
+/// The reader is left at the end of the subheader found.
+fn find_subheader<R: Read + Seek, T: NitfSegmentHeader>(
+    reader: &mut R,
+    session: &mut ParseSession,
+    kind: SegmentKind,
+    version: NitfVersion,
+    expected: u64,
+    floor: u64,
+    end: u64,
+) -> NitfResult<Option<(u64, T)>> {
+    let Some(signature) = kind.signature() else {
+        return Ok(None);
+    };
+    let width = signature.len() as u64;
+    if expected + width <= end {
+        let mut leading = vec![0; signature.len()];
+        reader.seek(Start(expected))?;
+        reader.read_exact(&mut leading)?;
+        reader.seek(Start(expected))?;
+        if leading == signature {
+            let n_diagnostics = session.diagnostics().len();
+            match T::from_reader_version(reader, session, version) {
+                Ok(meta) => return Ok(Some((expected, meta))),
+                // The fields of a subheader which is not used are not reported
+                Err(_) => session.truncate_diagnostics(n_diagnostics),
+            }
+        }
+    }
+    // Search chunk by chunk, overlapping them so no signature is split
+    let mut chunk = vec![0; SCAN_CHUNK_SIZE as usize];
+    let mut offset = floor;
+    while offset + width <= end {
+        let length = (end - offset).min(SCAN_CHUNK_SIZE);
+        let chunk = &mut chunk[..length as usize];
+        reader.seek(Start(offset))?;
+        reader.read_exact(chunk)?;
+        let candidates = chunk
+            .windows(signature.len())
+            .enumerate()
+            .filter(|(_, window)| *window == signature)
+            .map(|(position, _)| offset + position as u64);
+        for candidate in candidates.filter(|candidate| *candidate != expected) {
+            reader.seek(Start(candidate))?;
+            // Candidates are read strictly, keeping their diagnostics out of the session
+            if let Ok(meta) = T::from_reader_version(reader, &mut session.strict(), version) {
+                return Ok(Some((candidate, meta)));
+            }
+        }
+        offset += length - width + 1;
+    }
+    Ok(None)
+}
+
+/// Subheader offsets of `segments`
+fn header_offsets<T: NitfSegmentHeader>(
+    segments: &[NitfSegment<T>],
+) -> impl Iterator<Item = u64> + '_ {
+    segments.iter().map(|segment| segment.header_offset)
+}
+
+/// Log and collect a repair of the segment the length table `field` describes
+fn report<V: FromStr + Debug>(
+    session: &mut ParseSession,
+    name: &str,
+    field: &NitfField<V>,
+    reason: String,
+) -> NitfResult<()> {
+    warn!("{name} {reason}");
+    session.report(name, field.offset(), &field.bytes, reason, |diagnostic| {
+        NitfError::Diagnostic(Box::new(diagnostic))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{NitfBuilder, NitfHeaderBuilder, TextHeaderBuilder};
+    use crate::parse::{Diagnostic, ParseOptions};
+    use std::io::Cursor;
+
+    /// Bytes of a file with a text segment holding each of `texts`, and the file read back
+    fn texts(texts: &[&[u8]]) -> NitfResult<(Vec<u8>, Nitf)> {
+        let mut builder = NitfBuilder::new(NitfHeaderBuilder::new()?.build());
+        for text in texts {
+            builder = builder.text(TextHeaderBuilder::new()?.build(), text.to_vec());
+        }
+        let mut bytes = vec![];
+        builder.build()?.write_to(&mut bytes)?;
+        let nitf = Nitf::from_reader(&mut Cursor::new(&bytes))?;
+        Ok((bytes, nitf))
+    }
+
+    /// Overwrite the length table `field` with `value`
+    fn patch<V: FromStr + Debug>(bytes: &mut [u8], field: &NitfField<V>, value: u64) {
+        let start = field.offset() as usize;
+        let width = field.bytes.len();
+        bytes[start..start + width].copy_from_slice(format!("{value:0width$}").as_bytes());
+    }
+
+    fn recover(bytes: &[u8]) -> NitfResult<(Nitf, Vec<Diagnostic>)> {
+        ParseOptions::recover().from_reader(&mut Cursor::new(bytes))
+    }
+
+    /// Field and reason of each diagnostic
+    fn repairs(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| format!("{} {}", diagnostic.field, diagnostic.reason))
+            .collect()
+    }
+
+    fn damaged(nitf: &Nitf) -> Vec<bool> {
+        nitf.text_segments.iter().map(|text| text.damaged).collect()
+    }
+
+    #[test]
+    fn data_length_too_long() -> NitfResult<()> {
+        let (mut bytes, nitf) = texts(&[b"first", b"second", b"third"])?;
+        patch(
+            &mut bytes,
+            &nitf.nitf_header.meta.textheaders[0].item_size,
+            9,
+        );
+        let (read, diagnostics) = recover(&bytes)?;
+        let second = nitf.text_segments[1].header_offset;
+        assert_eq!(
+            repairs(&diagnostics),
+            [
+                format!(
+                    "LTSH002 subheader found at byte {second} instead of {}",
+                    second + 4
+                ),
+                format!("LT001 data fitted to 5 bytes, up to the subheader at byte {second}"),
+            ]
+        );
+        assert_eq!(damaged(&read), [true, false, false]);
+        assert_eq!(read.text_segments[0].data.to_vec()?, b"first");
+        assert_eq!(read.text_segments[1].data.to_vec()?, b"second");
+        Ok(())
+    }
+
+    #[test]
+    fn data_length_too_short() -> NitfResult<()> {
+        let (mut bytes, nitf) = texts(&[b"first", b"second", b"third"])?;
+        patch(
+            &mut bytes,
+            &nitf.nitf_header.meta.textheaders[1].item_size,
+            2,
+        );
+        let (read, diagnostics) = recover(&bytes)?;
+        let third = nitf.text_segments[2].header_offset;
+        assert_eq!(
+            repairs(&diagnostics),
+            [
+                format!(
+                    "LTSH003 subheader found at byte {third} instead of {}",
+                    third - 4
+                ),
+                format!("LT002 data fitted to 6 bytes, up to the subheader at byte {third}"),
+            ]
+        );
+        assert_eq!(damaged(&read), [false, true, false]);
+        assert_eq!(read.text_segments[1].data.to_vec()?, b"second");
+        assert_eq!(read.text_segments[2].data.to_vec()?, b"third");
+        Ok(())
+    }
+
+    #[test]
+    fn subheader_length_off() -> NitfResult<()> {
+        let (mut bytes, nitf) = texts(&[b"first", b"second"])?;
+        let header_size = nitf.text_segments[0].header_size as u64;
+        patch(
+            &mut bytes,
+            &nitf.nitf_header.meta.textheaders[0].subheader_size,
+            header_size + 3,
+        );
+        let (read, diagnostics) = recover(&bytes)?;
+        assert_eq!(
+            repairs(&diagnostics),
+            [format!(
+                "LTSH001 gives {} bytes, but {header_size} were read",
+                header_size + 3
+            )]
+        );
+        assert_eq!(damaged(&read), [true, false]);
+        assert_eq!(read.text_segments[0].data.to_vec()?, b"first");
+        assert_eq!(read.text_segments[1].data.to_vec()?, b"second");
+        Ok(())
+    }
+
+    #[test]
+    fn subheader_lost() -> NitfResult<()> {
+        let (mut bytes, nitf) = texts(&[b"first", b"second", b"third"])?;
+        let second = nitf.text_segments[1].header_offset as usize;
+        bytes[second..second + 2].copy_from_slice(b"XX");
+        let (read, diagnostics) = recover(&bytes)?;
+        // The third subheader stands in for the second, with the shorter data of
+        // the third, and the third is not found
+        let third = nitf.text_segments[2].header_offset;
+        let floor = nitf.text_segments[2].data_offset;
+        assert_eq!(
+            repairs(&diagnostics),
+            [
+                format!("LTSH002 subheader found at byte {third} instead of {second}"),
+                "LT002 data clamped to the 5 bytes left in the file".to_string(),
+                format!("LTSH003 subheader not found after byte {floor}"),
+                format!(
+                    "LT001 data fitted to {} bytes, up to the subheader at byte {third}",
+                    third - nitf.text_segments[0].data_offset
+                ),
+            ]
+        );
+        assert_eq!(damaged(&read), [true, true]);
+        assert_eq!(read.text_segments[1].data.to_vec()?, b"third");
+        Ok(())
+    }
+
+    #[test]
+    fn failed_subheader_not_reported() -> NitfResult<()> {
+        // Data ending in what looks like a subheader, up to an extended
+        // subheader length running past the end of the file
+        let fake = [
+            b"TEFAKE   ABC20261018000000".as_slice(),
+            &[b' '; 80],
+            b"U",
+            &[b' '; 166],
+            b"0STA65000",
+        ]
+        .concat();
+        let first = [b"first".as_slice(), &fake].concat();
+        let (mut bytes, nitf) = texts(&[&first, b"second"])?;
+        patch(
+            &mut bytes,
+            &nitf.nitf_header.meta.textheaders[0].item_size,
+            5,
+        );
+        let (read, diagnostics) = recover(&bytes)?;
+        let second = nitf.text_segments[1].header_offset;
+        let expected = second - fake.len() as u64;
+        assert_eq!(
+            repairs(&diagnostics),
+            [
+                format!("LTSH002 subheader found at byte {second} instead of {expected}"),
+                format!(
+                    "LT001 data fitted to {} bytes, up to the subheader at byte {second}",
+                    first.len()
+                ),
+            ]
+        );
+        assert_eq!(damaged(&read), [true, false]);
+        assert_eq!(read.text_segments[0].data.to_vec()?, first);
+        Ok(())
+    }
+}
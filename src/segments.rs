@@ -1,12 +1,70 @@
 //! File header and generic segment definition
 use memmap2::{Mmap, MmapOptions};
-use std::fmt::Display;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::{Seek, SeekFrom::Start};
-use std::ops::Deref;
+use std::io::{Read, Seek, SeekFrom::Start, Write};
+use std::sync::{Arc, Mutex};
 
 use crate::headers::{NitfHeader, NitfSegmentHeader};
-use crate::NitfResult;
+use crate::parse::ParseSession;
+use crate::types::NitfVersion;
+use crate::{NitfError, NitfResult};
+
+/// Type of segment, or the file header
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SegmentKind {
+    FileHeader,
+    Image,
+    Graphic,
+    Label,
+    Text,
+    DataExtension,
+    ReservedExtension,
+}
+impl SegmentKind {
+    /// Names of the subheader and data length fields of the segment type in
+    /// the file header, or HL and FL for the file header itself
+    pub fn length_fields(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::FileHeader => ("HL", "FL"),
+            Self::Image => ("LISH", "LI"),
+            Self::Graphic => ("LSSH", "LS"),
+            Self::Label => ("LLSH", "LL"),
+            Self::Text => ("LTSH", "LT"),
+            Self::DataExtension => ("LDSH", "LD"),
+            Self::ReservedExtension => ("LRESH", "LRE"),
+        }
+    }
+
+    /// Leading bytes of the subheader of the segment type, none for the file
+    /// header which starts with either `NITF` or `NSIF`
+    pub fn signature(&self) -> Option<&'static [u8]> {
+        match self {
+            Self::FileHeader => None,
+            Self::Image => Some(b"IM"),
+            Self::Graphic => Some(b"SY"),
+            Self::Label => Some(b"LA"),
+            Self::Text => Some(b"TE"),
+            Self::DataExtension => Some(b"DE"),
+            Self::ReservedExtension => Some(b"RE"),
+        }
+    }
+}
+impl Display for SegmentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::FileHeader => "file header",
+            Self::Image => "image segment",
+            Self::Graphic => "graphic segment",
+            Self::Label => "label segment",
+            Self::Text => "text segment",
+            Self::DataExtension => "data extension segment",
+            Self::ReservedExtension => "reserved extension segment",
+        };
+        write!(f, "{name}")
+    }
+}
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct FileHeader {
@@ -16,11 +74,21 @@ pub struct FileHeader {
     pub header_size: u64,
 }
 impl FileHeader {
-    pub fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.meta.read(reader)?;
+    pub fn read<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<()> {
+        self.meta
+            .read_version(reader, session, NitfVersion::default())?;
         // Crash if cursor error
         self.header_size = reader.stream_position()?;
-        Ok(())
+        session.check_length(
+            "HL",
+            &self.meta.hl,
+            self.meta.hl.val as u64,
+            self.header_size,
+        )
     }
 }
 impl Display for FileHeader {
@@ -34,7 +102,7 @@ pub struct NitfSegment<T: NitfSegmentHeader> {
     /// Header fields defined in module
     pub meta: T,
     /// Segment data
-    pub data: Mmap,
+    pub data: SegmentData,
     /// Byte offset of header start
     pub header_offset: u64,
     /// Byte size of header
@@ -43,22 +111,34 @@ pub struct NitfSegment<T: NitfSegmentHeader> {
     pub data_offset: u64,
     /// Data size in bytes
     pub data_size: u64,
+    /// Whether the length tables were wrong for the segment, and its position
+    /// or size had to be recovered (see [ParseMode::Recover](crate::parse::ParseMode::Recover))
+    pub damaged: bool,
 }
 impl<T: NitfSegmentHeader> NitfSegment<T> {
-    pub fn initialize(reader: &mut File, header_size: u32, data_size: u64) -> NitfResult<Self> {
+    /// Parse the segment subheader at the current position of `reader`,
+    /// laid out for file format `version`
+    ///
+    /// `load` is handed the reader along with the data offset and size, and
+    /// decides how the segment data is backed (see [SegmentData]). Fields which
+    /// fail to parse are handled as `session` says.
+    pub fn initialize<R, F>(
+        reader: &mut R,
+        session: &mut ParseSession,
+        header_size: u32,
+        data_size: u64,
+        version: NitfVersion,
+        load: &mut F,
+    ) -> NitfResult<Self>
+    where
+        R: Read + Seek,
+        F: FnMut(&mut R, u64, u64) -> NitfResult<SegmentData>,
+    {
         // Crash if cursor error
         let header_offset = reader.stream_position()?;
-        let header_size = header_size;
-        let data_size = data_size;
         let data_offset = header_offset + header_size as u64;
-        let meta = T::from_reader(reader)?;
-        let mut memmap_opts = MmapOptions::new();
-        let data = unsafe {
-            memmap_opts
-                .offset(data_offset)
-                .len(data_size as usize)
-                .map(reader.deref())?
-        };
+        let meta = T::from_reader_version(reader, session, version)?;
+        let data = load(reader, data_offset, data_size)?;
         // Seek to end of data for next segment to be read
         // Crash if cursor error
         reader.seek(Start(data_offset + data_size))?;
@@ -69,6 +149,7 @@ impl<T: NitfSegmentHeader> NitfSegment<T> {
             header_size,
             data_size,
             data_offset,
+            damaged: false,
         })
     }
 }
@@ -84,7 +165,222 @@ impl<T: NitfSegmentHeader + PartialEq> PartialEq for NitfSegment<T> {
         let b2 = self.header_size == other.header_size;
         let b3 = self.data_offset == other.data_offset;
         let b4 = self.data_size == other.data_size;
-        b0 & b1 & b2 & b3 & b4
+        let b5 = self.damaged == other.damaged;
+        b0 & b1 & b2 & b3 & b4 & b5
     }
 }
 impl<T: NitfSegmentHeader + Eq> Eq for NitfSegment<T> {}
+
+/// Backing storage for the data of a segment
+///
+/// Memory-mapped and owned data can be borrowed directly with
+/// [as_slice()](SegmentData::as_slice). All variants can be accessed with
+/// [read_range()](SegmentData::read_range) or [to_vec()](SegmentData::to_vec).
+pub enum SegmentData {
+    /// Memory-mapped region of a file
+    Mmap(Mmap),
+    /// Bytes held in memory
+    Owned(Vec<u8>),
+    /// Byte range of a shared reader, fetched on request
+    Lazy(LazyData),
+}
+
+/// Byte range of a [SharedReader] which is only read when requested
+#[derive(Clone, Debug)]
+pub struct LazyData {
+    /// Reader the data is fetched from
+    pub source: SharedReader,
+    /// Byte offset of the data within `source`
+    pub offset: u64,
+    /// Size of the data in bytes
+    pub size: u64,
+}
+
+/// Helper trait for readers which can be shared between lazily-loaded segments
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Reader which can be shared between segments with [SegmentData::Lazy] data
+#[derive(Clone)]
+pub struct SharedReader(Arc<Mutex<dyn ReadSeek>>);
+
+impl SegmentData {
+    /// Memory-map `size` bytes of `file`, starting at `offset`
+    pub fn map(file: &File, offset: u64, size: u64) -> NitfResult<Self> {
+        // Zero length maps are not supported on all platforms
+        if size == 0 {
+            return Ok(Self::Owned(vec![]));
+        }
+        let mut memmap_opts = MmapOptions::new();
+        let data = unsafe { memmap_opts.offset(offset).len(size as usize).map(file)? };
+        Ok(Self::Mmap(data))
+    }
+
+    /// Read `size` bytes of `reader`, starting at `offset`, into memory
+    pub fn read<R: Read + Seek>(reader: &mut R, offset: u64, size: u64) -> NitfResult<Self> {
+        reader.seek(Start(offset))?;
+        let mut data = vec![0; size as usize];
+        reader.read_exact(&mut data)?;
+        Ok(Self::Owned(data))
+    }
+
+    /// Read everything remaining in `reader` into memory
+    pub fn from_reader<R: Read>(reader: &mut R) -> NitfResult<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        Ok(Self::Owned(data))
+    }
+
+    /// Reference `size` bytes of `source`, starting at `offset`, without reading them
+    pub fn lazy(source: SharedReader, offset: u64, size: u64) -> Self {
+        Self::Lazy(LazyData {
+            source,
+            offset,
+            size,
+        })
+    }
+
+    /// Size of the data in bytes
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Mmap(data) => data.len() as u64,
+            Self::Owned(data) => data.len() as u64,
+            Self::Lazy(data) => data.size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Borrow the data, if it is held in memory
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        match self {
+            Self::Mmap(data) => Some(&data[..]),
+            Self::Owned(data) => Some(&data[..]),
+            Self::Lazy(_) => None,
+        }
+    }
+
+    /// End of the `len` bytes starting at `offset`, checked against the data size
+    fn range_end(&self, offset: u64, len: u64) -> NitfResult<u64> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => Ok(end),
+            _ => Err(NitfError::Fatal(format!(
+                "segment data range of {len} bytes at {offset} out of bounds"
+            ))),
+        }
+    }
+
+    /// Fill `buf` with the data starting at `offset`
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> NitfResult<()> {
+        let end = self.range_end(offset, buf.len() as u64)?;
+        match self {
+            Self::Lazy(data) => data.source.read_at(data.offset + offset, buf),
+            _ => {
+                let slice = self.as_slice().unwrap_or_default();
+                buf.copy_from_slice(&slice[offset as usize..end as usize]);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get `len` bytes of data starting at `offset`
+    ///
+    /// Data held in memory is borrowed, lazy data is fetched from its source.
+    pub fn read_range(&self, offset: u64, len: usize) -> NitfResult<Cow<'_, [u8]>> {
+        // Checked before allocating, so a bad length fails instead of allocating
+        let end = self.range_end(offset, len as u64)?;
+        match self.as_slice() {
+            Some(slice) => Ok(Cow::Borrowed(&slice[offset as usize..end as usize])),
+            None => {
+                let mut buf = vec![0; len];
+                self.read_at(offset, &mut buf)?;
+                Ok(Cow::Owned(buf))
+            }
+        }
+    }
+
+    /// Write all of the data to `writer`
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        match self.as_slice() {
+            Some(slice) => writer.write_all(slice)?,
+            None => {
+                // Copy lazy data in chunks rather than all at once
+                const CHUNK_SIZE: u64 = 1 << 20;
+                let mut offset = 0;
+                while offset < self.len() {
+                    let n_bytes = CHUNK_SIZE.min(self.len() - offset);
+                    writer.write_all(&self.read_range(offset, n_bytes as usize)?)?;
+                    offset += n_bytes;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy all of the data into a new vector
+    pub fn to_vec(&self) -> NitfResult<Vec<u8>> {
+        Ok(self.read_range(0, self.len() as usize)?.into_owned())
+    }
+}
+impl Default for SegmentData {
+    fn default() -> Self {
+        Self::Owned(vec![])
+    }
+}
+impl Debug for SegmentData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mmap(data) => f.debug_tuple("Mmap").field(data).finish(),
+            Self::Owned(data) => write!(f, "Owned {{ len: {} }}", data.len()),
+            Self::Lazy(data) => f.debug_tuple("Lazy").field(data).finish(),
+        }
+    }
+}
+impl From<Vec<u8>> for SegmentData {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Owned(data)
+    }
+}
+
+impl From<&[u8]> for SegmentData {
+    fn from(data: &[u8]) -> Self {
+        Self::Owned(data.to_vec())
+    }
+}
+
+impl SharedReader {
+    pub fn new<R: ReadSeek + 'static>(reader: R) -> Self {
+        Self(Arc::new(Mutex::new(reader)))
+    }
+
+    /// Fill `buf` with bytes starting at `offset` of the underlying reader
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> NitfResult<()> {
+        let mut reader = self.lock()?;
+        reader.seek(Start(offset))?;
+        reader.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> NitfResult<std::sync::MutexGuard<'_, dyn ReadSeek + 'static>> {
+        self.0
+            .lock()
+            .or(Err(NitfError::Fatal("shared reader lock".to_string())))
+    }
+}
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.lock().map_err(std::io::Error::other)?.read(buf)
+    }
+}
+impl Seek for SharedReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.lock().map_err(std::io::Error::other)?.seek(pos)
+    }
+}
+impl Debug for SharedReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SharedReader")
+    }
+}
@@ -0,0 +1,318 @@
+//! Tagged record extensions (TREs)
+//!
+//! The user-defined and extended header data of the file header (`udhd`, `xhd`)
+//! and of image, graphic and text subheaders (`udid`, `ixshd`, `sxshd`, `txshd`)
+//! is a sequence of TREs, each made up of a 6 character tag (CETAG), a 5 digit
+//! length (CEL) and the data itself (CEDATA).
+//!
+//! [ExtendedSubheader::tres()] splits the data into [Tre] records. Typed parsers
+//! for specific tags are supplied through a [TreRegistry], tags without a parser
+//! are kept as raw bytes. Besides hand-written [TreType]s, a data-driven
+//! [TreSchema](schema::TreSchema) can be used as a registry.
+//! ```
+//! use nitf_rs::builder::ImageHeaderBuilder;
+//! use nitf_rs::tre::{Tre, TreParsers, TreType};
+//! use nitf_rs::NitfResult;
+//!
+//! #[derive(Debug)]
+//! struct Hello(String);
+//! impl TreType for Hello {
+//!     const TAG: &'static str = "HELLO";
+//!     fn parse(data: &[u8]) -> NitfResult<Self> {
+//!         Ok(Self(String::from_utf8_lossy(data).to_string()))
+//!     }
+//!     fn encode(&self) -> NitfResult<Vec<u8>> {
+//!         Ok(self.0.as_bytes().to_vec())
+//!     }
+//! }
+//!
+//! let tres = vec![Tre::new("HELLO", b"world")?, Tre::new("OTHER", b"123")?];
+//! let header = ImageHeaderBuilder::new()?
+//!     .size(1, 1)?
+//!     .ixshd(&Tre::encode_all(&tres)?)?
+//!     .build()?;
+//!
+//! let mut parsers = TreParsers::new();
+//! parsers.register::<Hello>();
+//! let decoded = header.ixshd.decode_tres(&parsers)?;
+//! assert_eq!(decoded[0].get::<Hello>().unwrap().0, "world");
+//! assert!(decoded[1].value.is_none());
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+pub mod rpc;
+pub mod schema;
+
+use log::warn;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::io::Write;
+
+use crate::types::ExtendedSubheader;
+use crate::{NitfError, NitfResult};
+
+/// Width of the CETAG field
+const TAG_WIDTH: usize = 6;
+/// Width of the CEL field
+const LENGTH_WIDTH: usize = 5;
+/// Largest length which fits in CEL
+pub const MAX_TRE_LENGTH: usize = 99_999;
+
+/// A single tagged record extension
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Tre {
+    /// Unique Extension Type Identifier (CETAG), without trailing spaces
+    pub tag: String,
+    /// Length of the extension data (CEL)
+    pub length: usize,
+    /// Extension data (CEDATA)
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the TREs in a block of bytes, see [ExtendedSubheader::iter_tres()]
+pub struct TreIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+/// Value produced by a typed TRE parser
+///
+/// Implemented for every `Debug + Send + Sync` type, use
+/// [DecodedTre::get()] to downcast.
+pub trait ParsedTre: Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: Debug + Send + Sync + 'static> ParsedTre for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// TRE with a typed representation
+pub trait TreType: Sized + Debug + Send + Sync + 'static {
+    /// Tag handled by the type
+    const TAG: &'static str;
+    /// Parse the extension data (CEDATA)
+    fn parse(data: &[u8]) -> NitfResult<Self>;
+    /// Encode the extension data (CEDATA)
+    fn encode(&self) -> NitfResult<Vec<u8>>;
+    /// Encode into a [Tre]
+    fn to_tre(&self) -> NitfResult<Tre> {
+        Tre::new(Self::TAG, &self.encode()?)
+    }
+}
+
+/// Source of typed parsers for TREs
+pub trait TreRegistry {
+    /// Parse `tre` into a typed value, `None` if its tag is not handled
+    fn parse(&self, tre: &Tre) -> Option<NitfResult<Box<dyn ParsedTre>>>;
+}
+
+/// Function which parses extension data into a typed value
+pub type TreParseFn = Box<dyn Fn(&[u8]) -> NitfResult<Box<dyn ParsedTre>> + Send + Sync>;
+
+/// [TreRegistry] holding one parser per tag
+#[derive(Default)]
+pub struct TreParsers {
+    parsers: HashMap<String, TreParseFn>,
+}
+
+/// A [Tre] along with its typed value, if its tag has a registered parser
+#[derive(Debug)]
+pub struct DecodedTre {
+    /// Raw record
+    pub raw: Tre,
+    /// Parsed value, `None` for unknown tags or if parsing failed
+    pub value: Option<Box<dyn ParsedTre>>,
+}
+
+impl Tre {
+    /// Construct a TRE, checking the tag and data fit their fields
+    pub fn new(tag: &str, data: &[u8]) -> NitfResult<Self> {
+        if tag.is_empty() || tag.len() > TAG_WIDTH {
+            return Err(NitfError::InvalidValue(
+                "CETAG".to_string(),
+                tag.to_string(),
+            ));
+        }
+        if data.len() > MAX_TRE_LENGTH {
+            return Err(NitfError::InvalidValue(
+                "CEL".to_string(),
+                data.len().to_string(),
+            ));
+        }
+        Ok(Self {
+            tag: tag.to_string(),
+            length: data.len(),
+            data: data.to_vec(),
+        })
+    }
+
+    /// Split `bytes` into TREs
+    pub fn parse_all(bytes: &[u8]) -> NitfResult<Vec<Self>> {
+        TreIter::new(bytes).collect()
+    }
+
+    /// Encode a sequence of TREs
+    pub fn encode_all(tres: &[Self]) -> NitfResult<Vec<u8>> {
+        let mut bytes = vec![];
+        for tre in tres {
+            tre.write(&mut bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Size of the encoded TRE in bytes
+    pub fn size(&self) -> usize {
+        TAG_WIDTH + LENGTH_WIDTH + self.data.len()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        if self.data.len() > MAX_TRE_LENGTH {
+            return Err(NitfError::InvalidValue(
+                "CEL".to_string(),
+                self.data.len().to_string(),
+            ));
+        }
+        write!(writer, "{:<6}{:05}", self.tag, self.data.len())?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+impl Display for Tre {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: [{}]", self.tag, String::from_utf8_lossy(&self.data))
+    }
+}
+
+impl<'a> TreIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn next_tre(&mut self) -> NitfResult<Tre> {
+        let rest = &self.bytes[self.offset..];
+        let header_size = TAG_WIDTH + LENGTH_WIDTH;
+        if rest.len() < header_size {
+            return Err(NitfError::Fatal(format!(
+                "TRE header at byte {}",
+                self.offset
+            )));
+        }
+        let tag = String::from_utf8_lossy(&rest[..TAG_WIDTH])
+            .trim_end()
+            .to_string();
+        let cel = String::from_utf8_lossy(&rest[TAG_WIDTH..header_size]).to_string();
+        let length: usize = cel
+            .parse()
+            .or(Err(NitfError::InvalidValue(format!("{tag} CEL"), cel)))?;
+        if rest.len() < header_size + length {
+            return Err(NitfError::Fatal(format!(
+                "{tag} TRE data, expected {length} bytes but {} remain",
+                rest.len() - header_size
+            )));
+        }
+        let data = rest[header_size..header_size + length].to_vec();
+        self.offset += header_size + length;
+        Ok(Tre { tag, length, data })
+    }
+}
+impl Iterator for TreIter<'_> {
+    type Item = NitfResult<Tre>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+        let tre = self.next_tre();
+        if tre.is_err() {
+            // Nothing after a malformed TRE can be located
+            self.offset = self.bytes.len();
+        }
+        Some(tre)
+    }
+}
+
+impl TreParsers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the parsers provided by this crate (see [rpc])
+    pub fn builtin() -> Self {
+        let mut parsers = Self::new();
+        rpc::RpcModel::register(&mut parsers);
+        parsers
+    }
+
+    /// Register the parser of a [TreType]
+    pub fn register<T: TreType>(&mut self) {
+        self.register_fn(T::TAG, |data| Ok(Box::new(T::parse(data)?)));
+    }
+
+    /// Register a parser function for `tag`, replacing any existing parser
+    pub fn register_fn<F>(&mut self, tag: &str, parser: F)
+    where
+        F: Fn(&[u8]) -> NitfResult<Box<dyn ParsedTre>> + Send + Sync + 'static,
+    {
+        self.parsers.insert(tag.to_string(), Box::new(parser));
+    }
+
+    /// Whether a parser is registered for `tag`
+    pub fn contains(&self, tag: &str) -> bool {
+        self.parsers.contains_key(tag)
+    }
+}
+impl TreRegistry for TreParsers {
+    fn parse(&self, tre: &Tre) -> Option<NitfResult<Box<dyn ParsedTre>>> {
+        self.parsers.get(&tre.tag).map(|parser| parser(&tre.data))
+    }
+}
+impl Debug for TreParsers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.parsers.keys()).finish()
+    }
+}
+
+impl DecodedTre {
+    /// Decode `raw` with `registry`
+    ///
+    /// A parser error is logged and leaves `value` empty, so one malformed TRE
+    /// does not prevent access to the rest.
+    pub fn new(raw: Tre, registry: &dyn TreRegistry) -> Self {
+        let value = match registry.parse(&raw) {
+            Some(Ok(value)) => Some(value),
+            Some(Err(e)) => {
+                warn!("Failed to parse {} TRE: {e}", raw.tag);
+                None
+            }
+            None => None,
+        };
+        Self { raw, value }
+    }
+
+    /// Typed value, if it was parsed as a `T`
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.value.as_deref()?.as_any().downcast_ref()
+    }
+}
+
+impl ExtendedSubheader {
+    /// Iterate over the TREs in the subheader
+    pub fn iter_tres(&self) -> TreIter<'_> {
+        TreIter::new(&self.tre)
+    }
+
+    /// Split the subheader into TREs
+    pub fn tres(&self) -> NitfResult<Vec<Tre>> {
+        self.iter_tres().collect()
+    }
+
+    /// Split the subheader into TREs and parse those known to `registry`
+    pub fn decode_tres(&self, registry: &dyn TreRegistry) -> NitfResult<Vec<DecodedTre>> {
+        Ok(self
+            .tres()?
+            .into_iter()
+            .map(|tre| DecodedTre::new(tre, registry))
+            .collect())
+    }
+}
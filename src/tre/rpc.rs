@@ -0,0 +1,336 @@
+//! Rational polynomial camera model (RPC00B and RPC00A TREs)
+//!
+//! Image coordinates are computed from ground coordinates as a ratio of two
+//! cubic polynomials of the normalized latitude, longitude and height. RPC00A
+//! only differs from RPC00B in the order of the 20 polynomial terms.
+//! ```no_run
+//! use std::path::Path;
+//! let nitf = nitf_rs::read_nitf(Path::new("../example.nitf")).unwrap();
+//! let rpc = nitf.image_segments[0].meta.rpc().unwrap().unwrap();
+//! let (line, samp) = rpc.ground_to_image(38.89, -77.03, 10.0);
+//! let (lat, lon) = rpc.image_to_ground(line, samp, 10.0).unwrap();
+//! ```
+use crate::headers::ImageHeader;
+use crate::tre::{Tre, TreParsers, TreType};
+use crate::{NitfError, NitfResult};
+
+/// Number of coefficients in each polynomial
+pub const N_COEFFICIENTS: usize = 20;
+/// Length of the RPC00A and RPC00B extension data
+pub const RPC_LENGTH: usize = 1041;
+
+/// Ordering of the polynomial terms
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum RpcOrder {
+    /// Legacy ordering of RPC00A
+    A,
+    /// Ordering of RPC00B
+    #[default]
+    B,
+}
+
+/// Rational polynomial camera model
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RpcModel {
+    /// Term ordering, and the tag of the TRE
+    pub order: RpcOrder,
+    /// Success
+    pub success: bool,
+    /// Error - Bias, in meters
+    pub err_bias: f64,
+    /// Error - Random, in meters
+    pub err_rand: f64,
+    /// Line Offset
+    pub line_off: f64,
+    /// Sample Offset
+    pub samp_off: f64,
+    /// Geodetic Latitude Offset
+    pub lat_off: f64,
+    /// Geodetic Longitude Offset
+    pub long_off: f64,
+    /// Geodetic Height Offset
+    pub height_off: f64,
+    /// Line Scale
+    pub line_scale: f64,
+    /// Sample Scale
+    pub samp_scale: f64,
+    /// Geodetic Latitude Scale
+    pub lat_scale: f64,
+    /// Geodetic Longitude Scale
+    pub long_scale: f64,
+    /// Geodetic Height Scale
+    pub height_scale: f64,
+    /// Line Numerator Coefficients
+    pub line_num_coeff: [f64; N_COEFFICIENTS],
+    /// Line Denominator Coefficients
+    pub line_den_coeff: [f64; N_COEFFICIENTS],
+    /// Sample Numerator Coefficients
+    pub samp_num_coeff: [f64; N_COEFFICIENTS],
+    /// Sample Denominator Coefficients
+    pub samp_den_coeff: [f64; N_COEFFICIENTS],
+}
+
+impl RpcModel {
+    /// Parse the extension data of a RPC00A or RPC00B TRE
+    pub fn parse(data: &[u8], order: RpcOrder) -> NitfResult<Self> {
+        if data.len() != RPC_LENGTH {
+            return Err(NitfError::InvalidValue(
+                format!("{} CEL", order.tag()),
+                data.len().to_string(),
+            ));
+        }
+        let mut reader = FieldReader { data, offset: 0 };
+        let mut model = Self {
+            order,
+            success: reader.next(1, "SUCCESS")? != 0.0,
+            err_bias: reader.next(7, "ERR_BIAS")?,
+            err_rand: reader.next(7, "ERR_RAND")?,
+            line_off: reader.next(6, "LINE_OFF")?,
+            samp_off: reader.next(5, "SAMP_OFF")?,
+            lat_off: reader.next(8, "LAT_OFF")?,
+            long_off: reader.next(9, "LONG_OFF")?,
+            height_off: reader.next(5, "HEIGHT_OFF")?,
+            line_scale: reader.next(6, "LINE_SCALE")?,
+            samp_scale: reader.next(5, "SAMP_SCALE")?,
+            lat_scale: reader.next(8, "LAT_SCALE")?,
+            long_scale: reader.next(9, "LONG_SCALE")?,
+            height_scale: reader.next(5, "HEIGHT_SCALE")?,
+            ..Default::default()
+        };
+        for (coefficients, name) in [
+            (&mut model.line_num_coeff, "LINE_NUM_COEFF"),
+            (&mut model.line_den_coeff, "LINE_DEN_COEFF"),
+            (&mut model.samp_num_coeff, "SAMP_NUM_COEFF"),
+            (&mut model.samp_den_coeff, "SAMP_DEN_COEFF"),
+        ] {
+            for coefficient in coefficients.iter_mut() {
+                *coefficient = reader.next(12, name)?;
+            }
+        }
+        Ok(model)
+    }
+
+    /// Encode the extension data
+    pub fn encode(&self) -> NitfResult<Vec<u8>> {
+        let mut out = String::with_capacity(RPC_LENGTH);
+        let mut push = |value: String, width: usize, name: &str| {
+            if value.len() != width {
+                return Err(NitfError::InvalidValue(name.to_string(), value));
+            }
+            out += &value;
+            Ok(())
+        };
+        push((self.success as u8).to_string(), 1, "SUCCESS")?;
+        push(format!("{:07.2}", self.err_bias), 7, "ERR_BIAS")?;
+        push(format!("{:07.2}", self.err_rand), 7, "ERR_RAND")?;
+        push(format!("{:06.0}", self.line_off), 6, "LINE_OFF")?;
+        push(format!("{:05.0}", self.samp_off), 5, "SAMP_OFF")?;
+        push(format!("{:+08.4}", self.lat_off), 8, "LAT_OFF")?;
+        push(format!("{:+09.4}", self.long_off), 9, "LONG_OFF")?;
+        push(format!("{:+05.0}", self.height_off), 5, "HEIGHT_OFF")?;
+        push(format!("{:06.0}", self.line_scale), 6, "LINE_SCALE")?;
+        push(format!("{:05.0}", self.samp_scale), 5, "SAMP_SCALE")?;
+        push(format!("{:+08.4}", self.lat_scale), 8, "LAT_SCALE")?;
+        push(format!("{:+09.4}", self.long_scale), 9, "LONG_SCALE")?;
+        push(format!("{:+05.0}", self.height_scale), 5, "HEIGHT_SCALE")?;
+        for (coefficients, name) in [
+            (&self.line_num_coeff, "LINE_NUM_COEFF"),
+            (&self.line_den_coeff, "LINE_DEN_COEFF"),
+            (&self.samp_num_coeff, "SAMP_NUM_COEFF"),
+            (&self.samp_den_coeff, "SAMP_DEN_COEFF"),
+        ] {
+            for coefficient in coefficients {
+                push(format_coefficient(*coefficient), 12, name)?;
+            }
+        }
+        Ok(out.into_bytes())
+    }
+
+    /// Parse `tre` if it is a RPC00A or RPC00B TRE
+    pub fn from_tre(tre: &Tre) -> Option<NitfResult<Self>> {
+        let order = match tre.tag.as_str() {
+            "RPC00A" => RpcOrder::A,
+            "RPC00B" => RpcOrder::B,
+            _ => return None,
+        };
+        Some(Self::parse(&tre.data, order))
+    }
+
+    /// Register parsers for both RPC00A and RPC00B
+    pub fn register(parsers: &mut TreParsers) {
+        for order in [RpcOrder::A, RpcOrder::B] {
+            parsers.register_fn(order.tag(), move |data| {
+                Ok(Box::new(Self::parse(data, order)?))
+            });
+        }
+    }
+
+    /// Image (line, sample) of a ground point, in decimal degrees and meters
+    /// above the ellipsoid
+    pub fn ground_to_image(&self, lat: f64, lon: f64, height: f64) -> (f64, f64) {
+        let terms = self.terms(lat, lon, height);
+        let line = ratio(&self.line_num_coeff, &self.line_den_coeff, &terms);
+        let samp = ratio(&self.samp_num_coeff, &self.samp_den_coeff, &terms);
+        (
+            line * self.line_scale + self.line_off,
+            samp * self.samp_scale + self.samp_off,
+        )
+    }
+
+    /// Ground (latitude, longitude) of an image point at `height`
+    ///
+    /// The RPC is inverted with Newton iterations starting from the ground
+    /// offsets, until the point is within 1e-6 pixels of (`line`, `samp`).
+    pub fn image_to_ground(&self, line: f64, samp: f64, height: f64) -> NitfResult<(f64, f64)> {
+        const MAX_ITERATIONS: usize = 50;
+        const TOLERANCE: f64 = 1e-6;
+        let (mut lat, mut lon) = (self.lat_off, self.long_off);
+        // Finite difference step, small relative to the extent of the model
+        let d_lat = self.lat_scale.abs().max(1e-3) * 1e-6;
+        let d_lon = self.long_scale.abs().max(1e-3) * 1e-6;
+        for _ in 0..MAX_ITERATIONS {
+            let (l0, s0) = self.ground_to_image(lat, lon, height);
+            let (dl, ds) = (line - l0, samp - s0);
+            if dl.abs() < TOLERANCE && ds.abs() < TOLERANCE {
+                return Ok((lat, lon));
+            }
+            let (l_lat, s_lat) = self.ground_to_image(lat + d_lat, lon, height);
+            let (l_lon, s_lon) = self.ground_to_image(lat, lon + d_lon, height);
+            // Jacobian of (line, samp) with respect to (lat, lon)
+            let (a, b) = ((l_lat - l0) / d_lat, (l_lon - l0) / d_lon);
+            let (c, d) = ((s_lat - s0) / d_lat, (s_lon - s0) / d_lon);
+            let det = a * d - b * c;
+            if det == 0.0 || !det.is_finite() {
+                break;
+            }
+            lat += (d * dl - b * ds) / det;
+            lon += (a * ds - c * dl) / det;
+        }
+        Err(NitfError::Fatal(format!(
+            "{} ground point for line {line}, sample {samp}, did not converge",
+            self.order.tag()
+        )))
+    }
+
+    /// Polynomial terms of a ground point, in the order of the model
+    fn terms(&self, lat: f64, lon: f64, height: f64) -> [f64; N_COEFFICIENTS] {
+        let p = (lat - self.lat_off) / self.lat_scale;
+        let l = (lon - self.long_off) / self.long_scale;
+        let h = (height - self.height_off) / self.height_scale;
+        match self.order {
+            RpcOrder::B => [
+                1.0,
+                l,
+                p,
+                h,
+                l * p,
+                l * h,
+                p * h,
+                l * l,
+                p * p,
+                h * h,
+                p * l * h,
+                l * l * l,
+                l * p * p,
+                l * h * h,
+                l * l * p,
+                p * p * p,
+                p * h * h,
+                l * l * h,
+                p * p * h,
+                h * h * h,
+            ],
+            RpcOrder::A => [
+                1.0,
+                l,
+                p,
+                h,
+                l * p,
+                l * h,
+                p * h,
+                p * l * h,
+                l * l,
+                p * p,
+                h * h,
+                l * l * l,
+                l * p * p,
+                l * h * h,
+                l * l * p,
+                p * p * p,
+                p * h * h,
+                l * l * h,
+                p * p * h,
+                h * h * h,
+            ],
+        }
+    }
+}
+
+impl TreType for RpcModel {
+    const TAG: &'static str = "RPC00B";
+    fn parse(data: &[u8]) -> NitfResult<Self> {
+        Self::parse(data, RpcOrder::B)
+    }
+    fn encode(&self) -> NitfResult<Vec<u8>> {
+        self.encode()
+    }
+    fn to_tre(&self) -> NitfResult<Tre> {
+        Tre::new(self.order.tag(), &self.encode()?)
+    }
+}
+
+impl RpcOrder {
+    /// Tag of the TRE using this ordering
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::A => "RPC00A",
+            Self::B => "RPC00B",
+        }
+    }
+}
+
+impl ImageHeader {
+    /// Rational polynomial camera model from the RPC00B (or RPC00A) TRE in `ixshd`
+    pub fn rpc(&self) -> NitfResult<Option<RpcModel>> {
+        for tre in self.ixshd.iter_tres() {
+            if let Some(model) = RpcModel::from_tre(&tre?) {
+                return model.map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn ratio(num: &[f64; N_COEFFICIENTS], den: &[f64; N_COEFFICIENTS], terms: &[f64]) -> f64 {
+    let dot = |coefficients: &[f64; N_COEFFICIENTS]| -> f64 {
+        coefficients.iter().zip(terms).map(|(c, t)| c * t).sum()
+    };
+    dot(num) / dot(den)
+}
+
+/// Format a coefficient as `±0.000000E±0`
+fn format_coefficient(value: f64) -> String {
+    let formatted = format!("{:.6E}", value.abs());
+    let (mantissa, exponent) = formatted.split_once('E').unwrap_or_default();
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    let sign = if value.is_sign_negative() { '-' } else { '+' };
+    let exponent_sign = if exponent < 0 { '-' } else { '+' };
+    // Exponents beyond one digit produce a wider string, which is rejected
+    format!("{sign}{mantissa}E{exponent_sign}{}", exponent.abs())
+}
+
+/// Sequential reader of numeric fields
+struct FieldReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+impl FieldReader<'_> {
+    fn next(&mut self, width: usize, name: &str) -> NitfResult<f64> {
+        let bytes = &self.data[self.offset..self.offset + width];
+        self.offset += width;
+        let string = String::from_utf8_lossy(bytes).trim().to_string();
+        string
+            .parse()
+            .or(Err(NitfError::InvalidValue(name.to_string(), string)))
+    }
+}
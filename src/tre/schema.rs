@@ -0,0 +1,794 @@
+//! Data-driven TRE definitions
+//!
+//! A [TreSchema] describes the fields of each TRE declaratively: field names,
+//! widths and types, loops repeated a number of times given by an earlier field
+//! (or a formula of earlier fields), and sections only present when a condition
+//! on an earlier field holds. Schemas can be loaded from the XML format used by
+//! GDAL's `nitf_spec.xml` (`xml` feature) or from JSON (`json` feature).
+//!
+//! Decoding produces a tree of [TreNode]s which holds the raw bytes of every
+//! field, so encoding it with the same schema reproduces the original TRE.
+//! ```
+//! # #[cfg(feature = "xml")]
+//! # {
+//! use nitf_rs::tre::schema::TreSchema;
+//! use nitf_rs::tre::Tre;
+//!
+//! let schema = TreSchema::from_xml(r#"
+//!     <tres>
+//!         <tre name="EXAMPL">
+//!             <field name="NPTS" length="2" type="integer"/>
+//!             <loop counter="NPTS" name="POINTS">
+//!                 <field name="X" length="4" type="real"/>
+//!             </loop>
+//!             <field name="FLAG" length="1"/>
+//!             <if cond="FLAG=Y">
+//!                 <field name="NOTE" length="5"/>
+//!             </if>
+//!         </tre>
+//!     </tres>"#)?;
+//!
+//! let tre = Tre::new("EXAMPL", b"0201.52.25Yhello")?;
+//! let decoded = schema.decode(&tre)?.unwrap();
+//! assert_eq!(decoded.field("NOTE").unwrap().as_str(), "hello");
+//! assert_eq!(decoded.fields_named("X")[1].as_f64(), Some(2.25));
+//! assert_eq!(schema.encode(&decoded)?, tre);
+//! # }
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use std::collections::HashMap;
+
+use crate::tre::{ParsedTre, Tre, TreRegistry};
+use crate::{NitfError, NitfResult};
+
+/// Collection of TRE definitions, keyed by tag
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TreSchema {
+    definitions: HashMap<String, TreDefinition>,
+}
+
+/// Layout of a single TRE
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct TreDefinition {
+    /// Tag of the TRE
+    pub name: String,
+    /// Smallest valid length of the TRE data
+    #[cfg_attr(
+        feature = "json",
+        serde(rename = "minlength", default, skip_serializing_if = "Option::is_none")
+    )]
+    pub min_length: Option<usize>,
+    /// Largest valid length of the TRE data
+    #[cfg_attr(
+        feature = "json",
+        serde(rename = "maxlength", default, skip_serializing_if = "Option::is_none")
+    )]
+    pub max_length: Option<usize>,
+    /// Contents of the TRE
+    pub items: Vec<SchemaItem>,
+}
+
+/// Element of a TRE definition
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "json",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SchemaItem {
+    Field(FieldDefinition),
+    Loop(LoopDefinition),
+    If(IfDefinition),
+}
+
+/// Fixed-width field
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldDefinition {
+    /// Field name
+    #[cfg_attr(feature = "json", serde(default))]
+    pub name: String,
+    /// Width of the field in bytes
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub length: Option<usize>,
+    /// Name of an earlier field holding the width, when it is not fixed
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub length_var: Option<String>,
+    /// Value type of the field
+    #[cfg_attr(feature = "json", serde(rename = "type", default))]
+    pub kind: FieldKind,
+}
+
+/// Value type of a field
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "json",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum FieldKind {
+    #[default]
+    String,
+    Integer,
+    Real,
+    Binary,
+}
+
+/// Items repeated a number of times
+///
+/// The count is given by `iterations`, or evaluated from `counter` or
+/// `formula`, which are expressions of earlier integer fields (e.g. `NPTS` or
+/// `NPAR*(NPAR+1)/2`).
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoopDefinition {
+    /// Loop name, used to identify the [TreLoop]
+    #[cfg_attr(feature = "json", serde(default))]
+    pub name: String,
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub counter: Option<String>,
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub iterations: Option<usize>,
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub formula: Option<String>,
+    /// Contents of each iteration
+    pub items: Vec<SchemaItem>,
+}
+
+/// Items only present when a condition holds
+///
+/// Conditions compare an earlier field to a value, `FIELD=VALUE` or
+/// `FIELD!=VALUE`, and can be combined with ` AND `.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfDefinition {
+    pub cond: String,
+    pub items: Vec<SchemaItem>,
+}
+
+/// Decoded element of a TRE
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreNode {
+    Field(TreField),
+    Loop(TreLoop),
+}
+
+/// Decoded field
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TreField {
+    /// Field name
+    pub name: String,
+    /// Value type of the field
+    pub kind: FieldKind,
+    /// Raw bytes of the field
+    pub bytes: Vec<u8>,
+}
+
+/// Decoded loop
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TreLoop {
+    /// Loop name
+    pub name: String,
+    /// Contents of each iteration
+    pub iterations: Vec<Vec<TreNode>>,
+}
+
+/// TRE decoded with a [TreSchema]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SchemaTre {
+    /// Tag of the TRE
+    pub tag: String,
+    /// Top level elements
+    pub nodes: Vec<TreNode>,
+}
+
+impl TreSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a definition, replacing any with the same name
+    pub fn insert(&mut self, definition: TreDefinition) {
+        self.definitions.insert(definition.name.clone(), definition);
+    }
+
+    /// Definition for `tag`
+    pub fn get(&self, tag: &str) -> Option<&TreDefinition> {
+        self.definitions.get(tag)
+    }
+
+    /// Iterate over all definitions
+    pub fn definitions(&self) -> impl Iterator<Item = &TreDefinition> {
+        self.definitions.values()
+    }
+
+    /// Decode `tre`, `None` if the schema has no definition for its tag
+    pub fn decode(&self, tre: &Tre) -> NitfResult<Option<SchemaTre>> {
+        self.get(&tre.tag)
+            .map(|definition| definition.decode(&tre.data))
+            .transpose()
+    }
+
+    /// Encode `tre` with the definition for its tag
+    pub fn encode(&self, tre: &SchemaTre) -> NitfResult<Tre> {
+        let definition = self
+            .get(&tre.tag)
+            .ok_or_else(|| schema_error(format!("no {} definition", tre.tag)))?;
+        Tre::new(&tre.tag, &definition.encode(&tre.nodes)?)
+    }
+
+    /// Load definitions from XML in the format of GDAL's `nitf_spec.xml`
+    ///
+    /// All `<tre>` elements are read, other elements (such as DES definitions)
+    /// are ignored.
+    #[cfg(feature = "xml")]
+    pub fn from_xml(text: &str) -> NitfResult<Self> {
+        xml::parse(text)
+    }
+
+    /// Load definitions from JSON of the form `{"tres": [<definition>, ...]}`
+    ///
+    /// Definitions are serialized field by field, with items such as
+    /// `{"field": {"name": "NPTS", "length": 2, "type": "integer"}}`,
+    /// `{"loop": {"counter": "NPTS", "items": [...]}}` and
+    /// `{"if": {"cond": "FLAG=Y", "items": [...]}}`.
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> NitfResult<Self> {
+        let file: SchemaFile =
+            serde_json::from_str(text).map_err(|e| schema_error(e.to_string()))?;
+        let mut schema = Self::new();
+        file.tres.into_iter().for_each(|tre| schema.insert(tre));
+        Ok(schema)
+    }
+
+    /// Serialize the definitions to JSON, in the form read by [from_json()](Self::from_json)
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> NitfResult<String> {
+        let mut tres: Vec<TreDefinition> = self.definitions.values().cloned().collect();
+        tres.sort_by(|a, b| a.name.cmp(&b.name));
+        serde_json::to_string_pretty(&SchemaFile { tres }).map_err(|e| schema_error(e.to_string()))
+    }
+}
+impl TreRegistry for TreSchema {
+    fn parse(&self, tre: &Tre) -> Option<NitfResult<Box<dyn ParsedTre>>> {
+        let definition = self.get(&tre.tag)?;
+        Some(
+            definition
+                .decode(&tre.data)
+                .map(|decoded| Box::new(decoded) as Box<dyn ParsedTre>),
+        )
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SchemaFile {
+    tres: Vec<TreDefinition>,
+}
+
+fn schema_error(message: String) -> NitfError {
+    NitfError::Fatal(format!("TRE schema: {message}"))
+}
+
+impl TreDefinition {
+    /// Decode the data of a TRE
+    pub fn decode(&self, data: &[u8]) -> NitfResult<SchemaTre> {
+        self.check_length(data.len())?;
+        let mut decoder = Decoder {
+            tag: &self.name,
+            data,
+            offset: 0,
+            values: HashMap::new(),
+        };
+        let nodes = decoder.items(&self.items)?;
+        if decoder.offset != data.len() {
+            return Err(NitfError::Fatal(format!(
+                "{} TRE, {} bytes left after decoding all fields",
+                self.name,
+                data.len() - decoder.offset
+            )));
+        }
+        Ok(SchemaTre {
+            tag: self.name.clone(),
+            nodes,
+        })
+    }
+
+    /// Encode decoded nodes back into TRE data
+    ///
+    /// Values narrower than their field are padded, with leading zeros for
+    /// numbers and trailing spaces otherwise.
+    pub fn encode(&self, nodes: &[TreNode]) -> NitfResult<Vec<u8>> {
+        let mut encoder = Encoder {
+            tag: &self.name,
+            out: vec![],
+            values: HashMap::new(),
+        };
+        let mut position = 0;
+        encoder.items(&self.items, nodes, &mut position)?;
+        if position != nodes.len() {
+            return Err(NitfError::Fatal(format!(
+                "{} TRE, {} nodes not in the definition",
+                self.name,
+                nodes.len() - position
+            )));
+        }
+        self.check_length(encoder.out.len())?;
+        Ok(encoder.out)
+    }
+
+    fn check_length(&self, length: usize) -> NitfResult<()> {
+        let too_short = self.min_length.is_some_and(|min| length < min);
+        let too_long = self.max_length.is_some_and(|max| length > max);
+        if too_short || too_long {
+            return Err(NitfError::InvalidValue(
+                format!("{} CEL", self.name),
+                length.to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Field values seen so far, used by counters, conditions and variable lengths
+type Values = HashMap<String, String>;
+
+fn field_width(tag: &str, field: &FieldDefinition, values: &Values) -> NitfResult<usize> {
+    match (&field.length, &field.length_var) {
+        (Some(length), _) => Ok(*length),
+        (None, Some(var)) => {
+            let width = evaluate(tag, var, values)?;
+            usize::try_from(width).or(Err(NitfError::InvalidValue(
+                format!("{tag} {} length", field.name),
+                width.to_string(),
+            )))
+        }
+        (None, None) => Err(NitfError::Fatal(format!(
+            "{tag} TRE, no length for field {}",
+            field.name
+        ))),
+    }
+}
+
+fn loop_count(tag: &str, lp: &LoopDefinition, values: &Values) -> NitfResult<usize> {
+    let count = match (&lp.iterations, &lp.counter, &lp.formula) {
+        (Some(iterations), _, _) => *iterations as i64,
+        (None, Some(expr), _) | (None, None, Some(expr)) => evaluate(tag, expr, values)?,
+        (None, None, None) => {
+            return Err(NitfError::Fatal(format!(
+                "{tag} TRE, no count for loop {}",
+                lp.name
+            )))
+        }
+    };
+    usize::try_from(count).or(Err(NitfError::InvalidValue(
+        format!("{tag} {} count", lp.name),
+        count.to_string(),
+    )))
+}
+
+fn condition_holds(tag: &str, cond: &str, values: &Values) -> NitfResult<bool> {
+    for clause in cond.split(" AND ") {
+        let (name, expected, equal) = match clause.split_once("!=") {
+            Some((name, value)) => (name, value, false),
+            None => match clause.split_once('=') {
+                Some((name, value)) => (name, value, true),
+                None => {
+                    return Err(NitfError::Fatal(format!(
+                        "{tag} TRE, unsupported condition {cond}"
+                    )))
+                }
+            },
+        };
+        let value = values
+            .get(name.trim())
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        if (value == expected.trim()) != equal {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Evaluate an integer expression of field values with `+ - * /` and parentheses
+fn evaluate(tag: &str, expression: &str, values: &Values) -> NitfResult<i64> {
+    let invalid = || NitfError::Fatal(format!("{tag} TRE, unable to evaluate \"{expression}\""));
+    let tokens = tokenize(expression);
+    let mut parser = ExpressionParser {
+        tokens: &tokens,
+        position: 0,
+        values,
+    };
+    let value = parser.sum().ok_or_else(invalid)?;
+    match parser.position == tokens.len() {
+        true => Ok(value),
+        false => Err(invalid()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(char),
+}
+
+fn tokenize(expression: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Operator(c));
+            chars.next();
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "+-*/()".contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            match word.parse() {
+                Ok(number) => tokens.push(Token::Number(number)),
+                Err(_) => tokens.push(Token::Name(word)),
+            }
+        }
+    }
+    tokens
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    values: &'a Values,
+}
+impl ExpressionParser<'_> {
+    fn operator(&mut self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(op)) if ops.contains(*op) => {
+                self.position += 1;
+                Some(*op)
+            }
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Option<i64> {
+        let mut value = self.product()?;
+        while let Some(op) = self.operator("+-") {
+            let rhs = self.product()?;
+            value = match op {
+                '+' => value.checked_add(rhs)?,
+                _ => value.checked_sub(rhs)?,
+            };
+        }
+        Some(value)
+    }
+
+    fn product(&mut self) -> Option<i64> {
+        let mut value = self.atom()?;
+        while let Some(op) = self.operator("*/") {
+            let rhs = self.atom()?;
+            value = match op {
+                '*' => value.checked_mul(rhs)?,
+                _ => value.checked_div(rhs)?,
+            };
+        }
+        Some(value)
+    }
+
+    fn atom(&mut self) -> Option<i64> {
+        if self.operator("(").is_some() {
+            let value = self.sum()?;
+            self.operator(")")?;
+            return Some(value);
+        }
+        if self.operator("-").is_some() {
+            return self.atom()?.checked_neg();
+        }
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        match token {
+            Token::Number(number) => Some(*number),
+            Token::Name(name) => self.values.get(name)?.parse().ok(),
+            Token::Operator(_) => None,
+        }
+    }
+}
+
+struct Decoder<'a> {
+    tag: &'a str,
+    data: &'a [u8],
+    offset: usize,
+    values: Values,
+}
+impl Decoder<'_> {
+    fn items(&mut self, items: &[SchemaItem]) -> NitfResult<Vec<TreNode>> {
+        let mut nodes = vec![];
+        for item in items {
+            match item {
+                SchemaItem::Field(field) => {
+                    let width = field_width(self.tag, field, &self.values)?;
+                    let end = self.offset.checked_add(width);
+                    let Some(end) = end.filter(|end| *end <= self.data.len()) else {
+                        return Err(NitfError::Fatal(format!(
+                            "{} TRE field {}, expected {width} bytes but {} remain",
+                            self.tag,
+                            field.name,
+                            self.data.len() - self.offset
+                        )));
+                    };
+                    let node = TreField {
+                        name: field.name.clone(),
+                        kind: field.kind,
+                        bytes: self.data[self.offset..end].to_vec(),
+                    };
+                    self.offset = end;
+                    self.values.insert(node.name.clone(), node.as_str());
+                    nodes.push(TreNode::Field(node));
+                }
+                SchemaItem::Loop(lp) => {
+                    let count = loop_count(self.tag, lp, &self.values)?;
+                    let mut iterations = Vec::with_capacity(count.min(1024));
+                    for _ in 0..count {
+                        iterations.push(self.items(&lp.items)?);
+                    }
+                    nodes.push(TreNode::Loop(TreLoop {
+                        name: lp.name.clone(),
+                        iterations,
+                    }));
+                }
+                SchemaItem::If(cond) => {
+                    if condition_holds(self.tag, &cond.cond, &self.values)? {
+                        nodes.extend(self.items(&cond.items)?);
+                    }
+                }
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+struct Encoder<'a> {
+    tag: &'a str,
+    out: Vec<u8>,
+    values: Values,
+}
+impl Encoder<'_> {
+    fn items(
+        &mut self,
+        items: &[SchemaItem],
+        nodes: &[TreNode],
+        position: &mut usize,
+    ) -> NitfResult<()> {
+        for item in items {
+            match item {
+                SchemaItem::Field(field) => {
+                    let node = match nodes.get(*position) {
+                        Some(TreNode::Field(node)) if node.name == field.name => node,
+                        _ => return Err(self.missing(&field.name)),
+                    };
+                    *position += 1;
+                    let width = field_width(self.tag, field, &self.values)?;
+                    let bytes = node.padded(width).ok_or(NitfError::InvalidValue(
+                        format!("{} {}", self.tag, field.name),
+                        node.as_str(),
+                    ))?;
+                    self.out.extend(bytes);
+                    self.values.insert(node.name.clone(), node.as_str());
+                }
+                SchemaItem::Loop(lp) => {
+                    let node = match nodes.get(*position) {
+                        Some(TreNode::Loop(node)) if node.name == lp.name => node,
+                        _ => return Err(self.missing(&lp.name)),
+                    };
+                    *position += 1;
+                    let count = loop_count(self.tag, lp, &self.values)?;
+                    if count != node.iterations.len() {
+                        return Err(NitfError::InvalidValue(
+                            format!("{} {} iterations", self.tag, lp.name),
+                            node.iterations.len().to_string(),
+                        ));
+                    }
+                    for iteration in &node.iterations {
+                        let mut inner = 0;
+                        self.items(&lp.items, iteration, &mut inner)?;
+                        if inner != iteration.len() {
+                            return Err(self.missing(&lp.name));
+                        }
+                    }
+                }
+                SchemaItem::If(cond) => {
+                    if condition_holds(self.tag, &cond.cond, &self.values)? {
+                        self.items(&cond.items, nodes, position)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn missing(&self, name: &str) -> NitfError {
+        NitfError::Fatal(format!(
+            "{} TRE, nodes do not match the definition at {name}",
+            self.tag
+        ))
+    }
+}
+
+impl TreField {
+    /// Field holding `value`, which is padded to the field width when encoded
+    pub fn new(name: &str, kind: FieldKind, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            bytes: value.as_bytes().to_vec(),
+        }
+    }
+
+    /// Value as a string, without surrounding spaces
+    pub fn as_str(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).trim().to_string()
+    }
+
+    /// Value as an integer, if it parses as one
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_str().parse().ok()
+    }
+
+    /// Value as a float, if it parses as one
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_str().parse().ok()
+    }
+
+    /// Bytes padded to `width`, `None` if the value is wider
+    fn padded(&self, width: usize) -> Option<Vec<u8>> {
+        let n_pad = width.checked_sub(self.bytes.len())?;
+        Some(match self.kind {
+            FieldKind::Integer | FieldKind::Real if n_pad > 0 => {
+                let (sign, digits) = match self.bytes.first() {
+                    Some(b'-' | b'+') => self.bytes.split_at(1),
+                    _ => (&[][..], &self.bytes[..]),
+                };
+                [sign, &vec![b'0'; n_pad], digits].concat()
+            }
+            FieldKind::Binary => [&self.bytes[..], &vec![0; n_pad]].concat(),
+            _ => [&self.bytes[..], &vec![b' '; n_pad]].concat(),
+        })
+    }
+}
+
+impl TreNode {
+    /// Collect the fields called `name` in this node and below it, depth first
+    fn visit<'a>(&'a self, found: &mut Vec<&'a TreField>, name: &str) {
+        match self {
+            Self::Field(field) if field.name == name => found.push(field),
+            Self::Field(_) => {}
+            Self::Loop(lp) => lp
+                .iterations
+                .iter()
+                .flatten()
+                .for_each(|node| node.visit(found, name)),
+        }
+    }
+}
+
+impl SchemaTre {
+    /// First field called `name`, searching loops depth first
+    pub fn field(&self, name: &str) -> Option<&TreField> {
+        self.fields_named(name).into_iter().next()
+    }
+
+    /// All fields called `name`, in the order they are stored
+    pub fn fields_named(&self, name: &str) -> Vec<&TreField> {
+        let mut found = vec![];
+        self.nodes
+            .iter()
+            .for_each(|node| node.visit(&mut found, name));
+        found
+    }
+
+    /// Top level loop called `name`
+    pub fn get_loop(&self, name: &str) -> Option<&TreLoop> {
+        self.nodes.iter().find_map(|node| match node {
+            TreNode::Loop(lp) if lp.name == name => Some(lp),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(feature = "xml")]
+mod xml {
+    use super::*;
+    use roxmltree::{Document, Node};
+
+    pub(super) fn parse(text: &str) -> NitfResult<TreSchema> {
+        let document = Document::parse(text).map_err(|e| schema_error(e.to_string()))?;
+        let mut schema = TreSchema::new();
+        for node in document
+            .descendants()
+            .filter(|node| node.has_tag_name("tre"))
+        {
+            schema.insert(definition(node)?);
+        }
+        Ok(schema)
+    }
+
+    fn number(node: Node, attribute: &str) -> NitfResult<Option<usize>> {
+        node.attribute(attribute)
+            .map(|value| {
+                value.trim().parse().or(Err(schema_error(format!(
+                    "invalid {attribute} \"{value}\" on line {}",
+                    node.document().text_pos_at(node.range().start).row
+                ))))
+            })
+            .transpose()
+    }
+
+    fn string(node: Node, attribute: &str) -> Option<String> {
+        node.attribute(attribute).map(|value| value.to_string())
+    }
+
+    fn definition(node: Node) -> NitfResult<TreDefinition> {
+        let length = number(node, "length")?;
+        Ok(TreDefinition {
+            name: string(node, "name").unwrap_or_default(),
+            min_length: number(node, "minlength")?.or(length),
+            max_length: number(node, "maxlength")?.or(length),
+            items: items(node)?,
+        })
+    }
+
+    fn items(node: Node) -> NitfResult<Vec<SchemaItem>> {
+        let mut parsed = vec![];
+        for child in node.children().filter(|child| child.is_element()) {
+            let item = match child.tag_name().name() {
+                "field" => SchemaItem::Field(FieldDefinition {
+                    name: string(child, "name").unwrap_or_default(),
+                    length: number(child, "length")?,
+                    length_var: string(child, "length_var"),
+                    kind: match child.attribute("type") {
+                        Some("integer") => FieldKind::Integer,
+                        Some("real") => FieldKind::Real,
+                        Some("binary") => FieldKind::Binary,
+                        _ => FieldKind::String,
+                    },
+                }),
+                "loop" => SchemaItem::Loop(LoopDefinition {
+                    name: string(child, "name").unwrap_or_default(),
+                    counter: string(child, "counter"),
+                    iterations: number(child, "iterations")?,
+                    formula: string(child, "formula"),
+                    items: items(child)?,
+                }),
+                "if" => SchemaItem::If(IfDefinition {
+                    cond: string(child, "cond").unwrap_or_default(),
+                    items: items(child)?,
+                }),
+                _ => continue,
+            };
+            parsed.push(item);
+        }
+        Ok(parsed)
+    }
+}
@@ -1,11 +1,11 @@
 //! Common types use throughout
 use log::{trace, warn};
 use std::fmt::{Debug, Display};
-use std::fs::File;
-use std::io::{Read, Seek};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::str::FromStr;
 
-use crate::{NitfError, NitfResult};
+use crate::parse::ParseSession;
+use crate::{Location, NitfError, NitfResult};
 
 /// Lowest level object for file parsing
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
@@ -22,9 +22,27 @@ pub struct NitfField<V: FromStr + Debug> {
     offset: u64,
 }
 
+/// File format version, identified by the FHDR and FVER fields
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum NitfVersion {
+    /// NITF 2.0 (MIL-STD-2500A)
+    Nitf20,
+    #[default]
+    /// NITF 2.1 (MIL-STD-2500C)
+    Nitf21,
+    /// NSIF 1.0 (STANAG 4545), laid out the same as NITF 2.1
+    Nsif10,
+}
+
 /// Standard security metadata
+///
+/// NITF 2.0 files use a shorter layout, where only `clas`, `code`, `ctlh`,
+/// `rel`, `caut` and `ctln` are shared with NITF 2.1, and the downgrade is
+/// given by `dwng` and `devt` instead.
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct Security {
+    /// File format version which determines the field layout
+    pub version: NitfVersion,
     /// File Security Classification
     pub clas: NitfField<Classification>,
     /// File Classification Security System
@@ -57,6 +75,10 @@ pub struct Security {
     pub srdt: NitfField<String>,
     /// File Security Control Number
     pub ctln: NitfField<String>,
+    /// File Security Downgrade (NITF 2.0)
+    pub dwng: NitfField<String>,
+    /// File Downgrading Event (NITF 2.0), present when `dwng` is `999998`
+    pub devt: NitfField<String>,
 }
 
 /// Classification codes
@@ -144,51 +166,194 @@ pub enum ClassificationReason {
     VALID,
 }
 
+/// Right-justify a numeric value and fill it with zeros, keeping any sign first
+pub(crate) fn zero_fill<N: Display>(value: N, length: usize) -> String {
+    let digits = value.to_string();
+    match digits.strip_prefix('-') {
+        Some(abs) => format!("-{abs:0>0$}", length.saturating_sub(1)),
+        None => format!("{digits:0>length$}"),
+    }
+}
+
+/// Fill `buf` with the field `name` at `offset`, which is the current position of `reader`
+///
+/// Running out of data is reported as [NitfError::Truncated], along with how
+/// many bytes were left.
+pub(crate) fn read_exact<R: Read + Seek>(
+    reader: &mut R,
+    buf: &mut [u8],
+    name: &str,
+    offset: u64,
+) -> NitfResult<()> {
+    match reader.read_exact(buf) {
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+            let end = reader.seek(SeekFrom::End(0))?;
+            Err(NitfError::Truncated {
+                location: Location::field(name, offset),
+                expected: buf.len() as u64,
+                actual: end.saturating_sub(offset),
+            })
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Type of the value of a [NitfField]
+///
+/// A value which fails to parse in strict mode is reported as
+/// [NitfError::InvalidNumeric] for numeric types, and as
+/// [NitfError::Diagnostic] otherwise.
+pub trait FieldValue: FromStr + Debug + Default {
+    /// Whether values are numbers
+    const NUMERIC: bool = false;
+}
+
+macro_rules! numeric_field_values {
+    ($($T:ty),*) => {
+        $(impl FieldValue for $T {
+            const NUMERIC: bool = true;
+        })*
+    };
+}
+numeric_field_values!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl FieldValue for String {}
+
 /// Use Default implementation
 impl<V> NitfField<V>
 where
-    V: FromStr + Debug + Default,
+    V: FieldValue,
     <V as FromStr>::Err: Debug,
 {
     /// Read the specified number of bytes and parse the value of a given field
-    pub fn read<T: Sized + Into<u64>>(
+    ///
+    /// A value which fails to parse is handled as `session` says.
+    pub fn read<R: Read + Seek, T: Sized + Into<u64>>(
         &mut self,
-        reader: &mut File,
+        reader: &mut R,
+        session: &mut ParseSession,
         n_bytes: T,
         field_name: &str,
     ) -> NitfResult<()> {
         self.length = n_bytes.into();
         self.bytes = vec![0; self.length as usize];
+        self.offset = reader.stream_position()?;
+        read_exact(reader, &mut self.bytes, field_name, self.offset)?;
+        if let Err(reason) = self.parse_bytes() {
+            warn!("Non-fatal error parsing {field_name}: {reason}");
+            session.report(field_name, self.offset, &self.bytes, reason, |diagnostic| {
+                match V::NUMERIC {
+                    true => NitfError::InvalidNumeric {
+                        location: Location::field(field_name, self.offset),
+                        value: self.string.clone(),
+                    },
+                    false => NitfError::Diagnostic(Box::new(diagnostic)),
+                }
+            })?;
+        }
+        Ok(())
+    }
 
-        // Crash if something goes wrong with the cursor
-        self.offset = reader
-            .stream_position()
-            .or(Err(NitfError::Fatal(field_name.to_string())))?;
-
-        // Crash if there is an error reading the bytes
-        reader
-            .read_exact(&mut self.bytes)
-            .or(Err(NitfError::Fatal(field_name.to_string())))?;
-
-        // Try to read the bytes to a string
-        match String::from_utf8(self.bytes.to_vec()) {
-            // If it's ok, trim and try to parse to enum/native representation
-            Ok(str) => {
-                self.string = str.trim().to_string();
-
-                // Warn and assign a default value if error parsing
-                self.val = self.string.parse().unwrap_or_else(|_| {
-                    warn!("Non-fatal error parsing {}", field_name);
-                    V::default()
-                });
-            }
+    /// Store raw bytes in the field, such as for binary fields like FBKGC
+    pub fn set_bytes(&mut self, bytes: &[u8], field_name: &str) {
+        self.length = bytes.len() as u64;
+        self.bytes = bytes.to_vec();
+        if let Err(reason) = self.parse_bytes() {
+            warn!("Non-fatal error parsing {field_name}: {reason}");
+        }
+    }
 
-            Err(_) => {
-                self.string = String::from("Error parsing string");
-                warn!("Failed to parse {field_name} from bytes: {:?}", self.bytes);
+    /// Parse the string and value representations from the field bytes
+    ///
+    /// If the value fails to parse it is given the default value, and the
+    /// reason is returned.
+    fn parse_bytes(&mut self) -> Result<(), String> {
+        // Text which is not UTF-8 is taken to be ECS-A, which is ISO 8859-1
+        let text = match std::str::from_utf8(&self.bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => self.bytes.iter().map(|byte| *byte as char).collect(),
+        };
+        self.string = text.trim().to_string();
+        let parsed = self.string.parse();
+        trace!("{parsed:?}");
+        // Assign a default value if error parsing
+        self.val = parsed.map_err(|_| {
+            let type_name = std::any::type_name::<V>();
+            let type_name = type_name.rsplit("::").next().unwrap_or(type_name);
+            match self.string.is_empty() {
+                true => format!("blank, expected {type_name}"),
+                false => format!("\"{}\" is not a valid {type_name}", self.string),
             }
+        })?;
+        Ok(())
+    }
+
+    /// Encode a numeric value into the field, right-justified and zero-filled
+    pub fn set_num<N: Display, T: Sized + Into<u64>>(
+        &mut self,
+        value: N,
+        n_bytes: T,
+        field_name: &str,
+    ) -> NitfResult<()> {
+        let length = n_bytes.into() as usize;
+        self.encode(zero_fill(value, length), length, field_name)
+    }
+
+    /// Encode a string value into the field, left-justified and space-filled
+    pub fn set_string<T: Sized + Into<u64>>(
+        &mut self,
+        value: &str,
+        n_bytes: T,
+        field_name: &str,
+    ) -> NitfResult<()> {
+        let length = n_bytes.into() as usize;
+        self.encode(format!("{value:<length$}"), length, field_name)
+    }
+
+    /// Store the padded representation of a value, failing if it does not fit
+    fn encode(&mut self, padded: String, length: usize, field_name: &str) -> NitfResult<()> {
+        if padded.len() != length {
+            return Err(NitfError::InvalidValue(field_name.to_string(), padded));
         }
-        trace!("{:?}", self.val);
+        let string = padded.trim().to_string();
+        // Blank fields are allowed to fall back to the default value
+        let val = match string.parse() {
+            Ok(val) => val,
+            Err(_) if string.is_empty() => V::default(),
+            Err(_) => return Err(NitfError::InvalidValue(field_name.to_string(), string)),
+        };
+        self.length = length as u64;
+        self.bytes = padded.into_bytes();
+        self.string = string;
+        self.val = val;
+        Ok(())
+    }
+}
+impl<V: FromStr + Debug> NitfField<V> {
+    /// Byte offset of the field in the source it was read from, or 0 if it was not read
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Length of the data following an extended data length field, which
+    /// counts its 3 byte overflow field as well
+    pub(crate) fn extended_data_length(&self, field_name: &str) -> NitfResult<usize>
+    where
+        V: Copy + Into<u64>,
+    {
+        let length: u64 = self.val.into();
+        match length.checked_sub(3) {
+            Some(length) => Ok(length as usize),
+            None => Err(NitfError::InvalidNumeric {
+                location: Location::field(field_name, self.offset),
+                value: self.string.clone(),
+            }),
+        }
+    }
+
+    /// Write the encoded bytes of the field
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        writer.write_all(&self.bytes)?;
         Ok(())
     }
 }
@@ -197,30 +362,161 @@ impl<V: FromStr + Debug> Display for NitfField<V> {
         write!(f, "{}", &self.string)
     }
 }
+impl NitfVersion {
+    /// Identify the version from the file profile name (FHDR) and version (FVER)
+    ///
+    /// # Example
+    /// ```
+    /// use nitf_rs::types::NitfVersion;
+    /// assert_eq!(NitfVersion::detect("NSIF", "01.00").unwrap(), NitfVersion::Nsif10);
+    /// assert!(NitfVersion::detect("NITF", "01.10").is_err());
+    /// ```
+    pub fn detect(fhdr: &str, fver: &str) -> NitfResult<Self> {
+        match (fhdr, fver) {
+            ("NITF", "02.00") => Ok(Self::Nitf20),
+            ("NITF", "02.10") => Ok(Self::Nitf21),
+            ("NSIF", "01.00") => Ok(Self::Nsif10),
+            ("NITF" | "NSIF", _) => Err(NitfError::UnsupportedVersion {
+                fhdr: fhdr.to_string(),
+                fver: fver.to_string(),
+            }),
+            _ => Err(NitfError::FileType(fhdr.to_string())),
+        }
+    }
+
+    /// File profile name (FHDR)
+    pub fn fhdr(&self) -> &'static str {
+        match self {
+            Self::Nitf20 | Self::Nitf21 => "NITF",
+            Self::Nsif10 => "NSIF",
+        }
+    }
+
+    /// File version (FVER)
+    pub fn fver(&self) -> &'static str {
+        match self {
+            Self::Nitf20 => "02.00",
+            Self::Nitf21 => "02.10",
+            Self::Nsif10 => "01.00",
+        }
+    }
+
+    /// Whether the file uses the NITF 2.0 layout
+    pub fn is_nitf20(&self) -> bool {
+        *self == Self::Nitf20
+    }
+}
+impl Display for NitfVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.fhdr(), self.fver())
+    }
+}
+
 impl Security {
-    pub fn read(&mut self, reader: &mut File) -> NitfResult<()> {
-        self.clas.read(reader, 1u8, "CLAS")?;
-        self.clsy.read(reader, 2u8, "CLSY")?;
-        self.code.read(reader, 11u8, "CODE")?;
-        self.ctlh.read(reader, 2u8, "CTLH")?;
-        self.rel.read(reader, 20u8, "REL")?;
-        self.dctp.read(reader, 2u8, "DCTP")?;
-        self.dcdt.read(reader, 8u8, "DCDT")?;
-        self.dcxm.read(reader, 4u8, "DCXM")?;
-        self.dg.read(reader, 1u8, "DG")?;
-        self.dgdt.read(reader, 8u8, "DGDT")?;
-        self.cltx.read(reader, 43u8, "CLTX")?;
-        self.catp.read(reader, 1u8, "CATP")?;
-        self.caut.read(reader, 40u8, "CAUT")?;
-        self.crsn.read(reader, 1u8, "CRSN")?;
-        self.srdt.read(reader, 8u8, "SRDT")?;
-        self.ctln.read(reader, 15u8, "CTLN")?;
+    /// Read the fields laid out for file format `version`
+    pub fn read_version<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+        version: NitfVersion,
+    ) -> NitfResult<()> {
+        self.version = version;
+        self.read(reader, session)
+    }
+
+    /// Read the fields laid out for the version set in `self.version`
+    pub fn read<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<()> {
+        if self.version.is_nitf20() {
+            return self.read_nitf20(reader, session);
+        }
+        self.clas.read(reader, session, 1u8, "CLAS")?;
+        self.clsy.read(reader, session, 2u8, "CLSY")?;
+        self.code.read(reader, session, 11u8, "CODE")?;
+        self.ctlh.read(reader, session, 2u8, "CTLH")?;
+        self.rel.read(reader, session, 20u8, "REL")?;
+        self.dctp.read(reader, session, 2u8, "DCTP")?;
+        self.dcdt.read(reader, session, 8u8, "DCDT")?;
+        self.dcxm.read(reader, session, 4u8, "DCXM")?;
+        self.dg.read(reader, session, 1u8, "DG")?;
+        self.dgdt.read(reader, session, 8u8, "DGDT")?;
+        self.cltx.read(reader, session, 43u8, "CLTX")?;
+        self.catp.read(reader, session, 1u8, "CATP")?;
+        self.caut.read(reader, session, 40u8, "CAUT")?;
+        self.crsn.read(reader, session, 1u8, "CRSN")?;
+        self.srdt.read(reader, session, 8u8, "SRDT")?;
+        self.ctln.read(reader, session, 15u8, "CTLN")?;
+        Ok(())
+    }
+
+    fn read_nitf20<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        session: &mut ParseSession,
+    ) -> NitfResult<()> {
+        self.clas.read(reader, session, 1u8, "CLAS")?;
+        self.code.read(reader, session, 40u8, "CODE")?;
+        self.ctlh.read(reader, session, 40u8, "CTLH")?;
+        self.rel.read(reader, session, 40u8, "REL")?;
+        self.caut.read(reader, session, 20u8, "CAUT")?;
+        self.ctln.read(reader, session, 20u8, "CTLN")?;
+        self.dwng.read(reader, session, 6u8, "DWNG")?;
+        if self.dwng.string == "999998" {
+            self.devt.read(reader, session, 40u8, "DEVT")?;
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        if self.version.is_nitf20() {
+            self.clas.write(writer)?;
+            self.code.write(writer)?;
+            self.ctlh.write(writer)?;
+            self.rel.write(writer)?;
+            self.caut.write(writer)?;
+            self.ctln.write(writer)?;
+            self.dwng.write(writer)?;
+            if self.dwng.string == "999998" {
+                self.devt.write(writer)?;
+            }
+            return Ok(());
+        }
+        self.clas.write(writer)?;
+        self.clsy.write(writer)?;
+        self.code.write(writer)?;
+        self.ctlh.write(writer)?;
+        self.rel.write(writer)?;
+        self.dctp.write(writer)?;
+        self.dcdt.write(writer)?;
+        self.dcxm.write(writer)?;
+        self.dg.write(writer)?;
+        self.dgdt.write(writer)?;
+        self.cltx.write(writer)?;
+        self.catp.write(writer)?;
+        self.caut.write(writer)?;
+        self.crsn.write(writer)?;
+        self.srdt.write(writer)?;
+        self.ctln.write(writer)?;
         Ok(())
     }
 }
 impl Display for Security {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut out_str = String::default();
+        if self.version.is_nitf20() {
+            out_str += format!("CLAS: {}, ", self.clas).as_ref();
+            out_str += format!("CODE: {}, ", self.code).as_ref();
+            out_str += format!("CTLH: {}, ", self.ctlh).as_ref();
+            out_str += format!("REL: {}, ", self.rel).as_ref();
+            out_str += format!("CAUT: {}, ", self.caut).as_ref();
+            out_str += format!("CTLN: {}, ", self.ctln).as_ref();
+            out_str += format!("DWNG: {}, ", self.dwng).as_ref();
+            out_str += format!("DEVT: {}", self.devt).as_ref();
+            return write!(f, "{}", out_str);
+        }
         out_str += format!("CLAS: {}, ", self.clas).as_ref();
         out_str += format!("CLSY: {}, ", self.clsy).as_ref();
         out_str += format!("CODE: {}, ", self.code).as_ref();
@@ -253,6 +549,19 @@ impl FromStr for Classification {
         }
     }
 }
+impl FieldValue for Classification {}
+impl Display for Classification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::U => "U",
+            Self::T => "T",
+            Self::S => "S",
+            Self::C => "C",
+            Self::R => "R",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for DeclassificationType {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -268,6 +577,21 @@ impl FromStr for DeclassificationType {
         }
     }
 }
+impl FieldValue for DeclassificationType {}
+impl Display for DeclassificationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::DEFAULT => "",
+            Self::DD => "DD",
+            Self::DE => "DE",
+            Self::GD => "GD",
+            Self::GE => "GE",
+            Self::O => "O",
+            Self::X => "X",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for DeclassificationExemption {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -299,6 +623,7 @@ impl FromStr for DeclassificationExemption {
         }
     }
 }
+impl FieldValue for DeclassificationExemption {}
 impl FromStr for Downgrade {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -311,6 +636,18 @@ impl FromStr for Downgrade {
         }
     }
 }
+impl FieldValue for Downgrade {}
+impl Display for Downgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::DEFAULT => "",
+            Self::S => "S",
+            Self::C => "C",
+            Self::R => "R",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for ClassificationAuthorityType {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -323,6 +660,18 @@ impl FromStr for ClassificationAuthorityType {
         }
     }
 }
+impl FieldValue for ClassificationAuthorityType {}
+impl Display for ClassificationAuthorityType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::DEFAULT => "",
+            Self::O => "O",
+            Self::D => "D",
+            Self::M => "M",
+        };
+        write!(f, "{code}")
+    }
+}
 impl FromStr for ClassificationReason {
     type Err = NitfError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -340,6 +689,7 @@ impl FromStr for ClassificationReason {
         }
     }
 }
+impl FieldValue for ClassificationReason {}
 
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct ExtendedSubheader {
@@ -349,12 +699,20 @@ pub struct ExtendedSubheader {
     pub size: usize,
 }
 impl ExtendedSubheader {
-    pub fn read(&mut self, reader: &mut File, n_bytes: usize, name: &str) -> NitfResult<()> {
+    pub fn read<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        n_bytes: usize,
+        name: &str,
+    ) -> NitfResult<()> {
         self.size = n_bytes;
         self.tre = vec![0; n_bytes];
-        reader
-            .read_exact(self.tre.as_mut_slice())
-            .or(Err(NitfError::Fatal(name.to_string())))?;
+        let offset = reader.stream_position()?;
+        read_exact(reader, &mut self.tre, name, offset)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> NitfResult<()> {
+        writer.write_all(&self.tre)?;
         Ok(())
     }
 }
@@ -365,3 +723,30 @@ impl Display for ExtendedSubheader {
         write!(f, "[{out_str}]")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::parse::ParseOptions;
+
+    #[test]
+    fn strict_parse_errors() -> NitfResult<()> {
+        let mut session = ParseSession::new(ParseOptions::strict());
+        let mut number = NitfField::<u16>::default();
+        let result = number.read(&mut Cursor::new(b"1x"), &mut session, 2u8, "NUM");
+        assert!(matches!(result, Err(NitfError::InvalidNumeric { .. })));
+        let mut clas = NitfField::<Classification>::default();
+        let result = clas.read(&mut Cursor::new(b"X"), &mut session, 1u8, "CLAS");
+        assert!(matches!(result, Err(NitfError::Diagnostic(_))));
+        let mut text = NitfField::<String>::default();
+        text.read(&mut Cursor::new(b"1x"), &mut session, 2u8, "TEXT")?;
+        assert_eq!(text.val, "1x");
+        // Lenient sessions collect the failure and fall back to the default value
+        let mut session = ParseSession::default();
+        number.read(&mut Cursor::new(b"1x"), &mut session, 2u8, "NUM")?;
+        assert_eq!((number.val, session.diagnostics().len()), (0, 1));
+        Ok(())
+    }
+}
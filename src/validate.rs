@@ -0,0 +1,806 @@
+//! Conformance checks of header fields against MIL-STD-2500C
+//!
+//! Reading a file is lenient: a field which fails to parse is logged and given
+//! a default value. [Nitf::validate()] reports such fields along with other
+//! non-conformances, checking
+//! - the character set of each field (BCS-A, ECS-A or BCS-N)
+//! - enumerated values, dates and numeric ranges
+//! - fields which must be present or blank depending on other fields
+//! - the header length (HL), file length (FL) and the subheader and data length
+//!   tables against the segments
+//! - the complexity level (CLEVEL) against the level the file requires, see [crate::clevel]
+//!
+//! NITF 2.0 files are checked against the equivalent fields of MIL-STD-2500A.
+//! ```
+//! use nitf_rs::builder::{ImageHeaderBuilder, NitfBuilder, NitfHeaderBuilder};
+//! use nitf_rs::image::ImageEncoder;
+//!
+//! let pixels = vec![0u8; 16];
+//! let (header, data) = ImageEncoder::new(4, 4, &[&pixels])?
+//!     .encode(ImageHeaderBuilder::new()?.iid1("VALID")?)?;
+//! let mut nitf = NitfBuilder::new(NitfHeaderBuilder::new()?.ostaid("STATION")?.build())
+//!     .image(header, data)
+//!     .build()?;
+//! assert!(nitf.validate().is_conformant());
+//!
+//! nitf.image_segments[0].meta.idlvl.set_num(0, 3u8, "IDLVL")?;
+//! let report = nitf.validate();
+//! assert_eq!(report.findings[0].field, "IDLVL");
+//! println!("{report}");
+//! # Ok::<(), nitf_rs::NitfError>(())
+//! ```
+use std::fmt::{Debug, Display};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::headers::nitf_file_hdr::SubHeader;
+use crate::headers::{
+    DataExtensionHeader, GraphicHeader, ImageHeader, LabelHeader, NitfHeader, NitfSegmentHeader,
+    ReservedExtensionHeader, TextHeader,
+};
+use crate::segments::{NitfSegment, SegmentKind};
+use crate::types::{NitfField, NitfVersion, Security};
+use crate::Nitf;
+
+/// Single non-conformance of a field
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Finding {
+    /// Segment type the field belongs to
+    pub segment: SegmentKind,
+    /// Index (zero indexed) of the segment among those of its type, 0 for the file header
+    pub index: usize,
+    /// Field name, as given in the standard
+    pub field: String,
+    /// Byte offset of the field in the file, or 0 if it was not read from a file
+    pub offset: u64,
+    /// Stored value of the field
+    pub value: String,
+    /// Description of the non-conformance
+    pub reason: String,
+}
+
+/// Findings of [Nitf::validate()], grouped by segment
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Whether no non-conformances were found
+    pub fn is_conformant(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.segment {
+            SegmentKind::FileHeader => write!(f, "{}", self.segment)?,
+            _ => write!(f, "{} {}", self.segment, self.index)?,
+        }
+        write!(
+            f,
+            ", {} at byte {}: {} (\"{}\")",
+            self.field, self.offset, self.reason, self.value
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Nitf {
+    /// Check the header fields of the file header and all segments for conformance
+    pub fn validate(&self) -> Report {
+        let mut findings = vec![];
+        let version = self.nitf_header.meta.version();
+        let header = &self.nitf_header.meta;
+        Checker::new(&mut findings, SegmentKind::FileHeader, 0, version).file_header(header);
+        self.check_lengths(&mut findings);
+        self.check_clevel(&mut findings);
+        for (index, segment) in self.image_segments.iter().enumerate() {
+            Checker::new(&mut findings, SegmentKind::Image, index, version).image(&segment.meta);
+        }
+        for (index, segment) in self.graphic_segments.iter().enumerate() {
+            Checker::new(&mut findings, SegmentKind::Graphic, index, version)
+                .graphic(&segment.meta);
+        }
+        for (index, segment) in self.label_segments.iter().enumerate() {
+            Checker::new(&mut findings, SegmentKind::Label, index, version).label(&segment.meta);
+        }
+        for (index, segment) in self.text_segments.iter().enumerate() {
+            Checker::new(&mut findings, SegmentKind::Text, index, version).text(&segment.meta);
+        }
+        for (index, segment) in self.data_extension_segments.iter().enumerate() {
+            Checker::new(&mut findings, SegmentKind::DataExtension, index, version)
+                .data_extension(&segment.meta);
+        }
+        for (index, segment) in self.reserved_extension_segments.iter().enumerate() {
+            Checker::new(
+                &mut findings,
+                SegmentKind::ReservedExtension,
+                index,
+                version,
+            )
+            .reserved_extension(&segment.meta);
+        }
+        Report { findings }
+    }
+
+    /// Check that CLEVEL is at least the level required by the file
+    fn check_clevel(&self, findings: &mut Vec<Finding>) {
+        let header = &self.nitf_header.meta;
+        // An unreadable CLEVEL is already reported by the file header checks
+        if header.clevel.string.parse::<u8>().is_err() {
+            return;
+        }
+        let mut check = Checker::new(findings, SegmentKind::FileHeader, 0, header.version());
+        match self.required_clevel() {
+            Ok(required) if required.level > header.clevel.val => {
+                check.finding("CLEVEL", &header.clevel, format!("lower than {required}"));
+            }
+            Ok(_) => {}
+            Err(error) => {
+                let reason = format!("required level is unknown, {error}");
+                check.finding("CLEVEL", &header.clevel, reason);
+            }
+        }
+    }
+
+    /// Check HL, FL and the length tables of the file header against the segments
+    fn check_lengths(&self, findings: &mut Vec<Finding>) {
+        let header = &self.nitf_header.meta;
+        let version = header.version();
+        let mut check = Checker::new(findings, SegmentKind::FileHeader, 0, version);
+        if let Some(length) = encoded_length(header) {
+            if length != header.hl.val as u64 {
+                check.finding("HL", &header.hl, format!("header is {length} bytes"));
+            }
+        }
+        let tables = [
+            ("NUMI", &header.numi, &header.imheaders, SegmentKind::Image),
+            (
+                "NUMS",
+                &header.nums,
+                &header.graphheaders,
+                SegmentKind::Graphic,
+            ),
+            (
+                "NUMX",
+                &header.numx,
+                &header.labelheaders,
+                SegmentKind::Label,
+            ),
+            ("NUMT", &header.numt, &header.textheaders, SegmentKind::Text),
+            (
+                "NUMDES",
+                &header.numdes,
+                &header.dextheaders,
+                SegmentKind::DataExtension,
+            ),
+            (
+                "NUMRES",
+                &header.numres,
+                &header.resheaders,
+                SegmentKind::ReservedExtension,
+            ),
+        ];
+        let sizes = [
+            segment_sizes(&self.image_segments),
+            segment_sizes(&self.graphic_segments),
+            segment_sizes(&self.label_segments),
+            segment_sizes(&self.text_segments),
+            segment_sizes(&self.data_extension_segments),
+            segment_sizes(&self.reserved_extension_segments),
+        ];
+        let mut file_length = header.hl.val as u64;
+        for ((count_name, count, table, kind), sizes) in tables.into_iter().zip(sizes) {
+            let names = kind.length_fields();
+            check.int(count_name, count, 0..=999);
+            if count.val as usize != table.len() || table.len() != sizes.len() {
+                check.finding(
+                    count_name,
+                    count,
+                    format!(
+                        "{} length table entries and {} segments",
+                        table.len(),
+                        sizes.len()
+                    ),
+                );
+            }
+            for (i, (entry, (subheader_size, data_size))) in table.iter().zip(sizes).enumerate() {
+                let SubHeader {
+                    subheader_size: sh,
+                    item_size: item,
+                } = entry;
+                let (sh_name, item_name) = (
+                    format!("{}{:03}", names.0, i + 1),
+                    format!("{}{:03}", names.1, i + 1),
+                );
+                check.int(&sh_name, sh, 1..=u32::MAX as u64);
+                check.int(&item_name, item, 0..=u64::MAX);
+                if subheader_size.is_some_and(|size| size != sh.val as u64) {
+                    let size = subheader_size.unwrap_or_default();
+                    check.finding(&sh_name, sh, format!("subheader is {size} bytes"));
+                }
+                if data_size != item.val {
+                    check.finding(&item_name, item, format!("data is {data_size} bytes"));
+                }
+                file_length += sh.val as u64 + item.val;
+            }
+        }
+        if header.fl.val != file_length {
+            check.finding(
+                "FL",
+                &header.fl,
+                format!("header and length tables give {file_length} bytes"),
+            );
+        }
+    }
+}
+
+/// Encoded subheader size, if it can be encoded, and data size of each segment
+fn segment_sizes<T: NitfSegmentHeader>(segments: &[NitfSegment<T>]) -> Vec<(Option<u64>, u64)> {
+    segments
+        .iter()
+        .map(|segment| (encoded_length(&segment.meta), segment.data.len()))
+        .collect()
+}
+
+fn encoded_length<T: NitfSegmentHeader>(header: &T) -> Option<u64> {
+    let mut encoded = vec![];
+    header.write(&mut encoded).ok()?;
+    Some(encoded.len() as u64)
+}
+
+/// Allowed values of ICAT
+const IMAGE_CATEGORIES: &[&str] = &[
+    "VIS", "SL", "TI", "FL", "RD", "EO", "OP", "HR", "HS", "CP", "BP", "SAR", "SARIQ", "IR", "MAP",
+    "MS", "FP", "MRI", "XRAY", "CAT", "VD", "PAT", "LEG", "DTEM", "MATR", "LOCG", "BARO",
+    "CURRENT", "DEPTH", "WIND",
+];
+
+/// Character set of a field
+#[derive(Debug, Clone, Copy)]
+enum Charset {
+    /// Basic Character Set-Alphanumeric, printable ASCII
+    BcsA,
+    /// Extended Character Set-Alphanumeric, BCS-A and the upper half of ISO 8859-1
+    EcsA,
+    /// Basic Character Set-Numeric, digits, signs, decimal point and slash
+    BcsN,
+}
+
+impl Charset {
+    fn contains(self, byte: u8) -> bool {
+        match self {
+            Self::BcsA => (0x20..=0x7E).contains(&byte),
+            Self::EcsA => (0x20..=0x7E).contains(&byte) || byte >= 0xA0,
+            Self::BcsN => byte.is_ascii_digit() || b"+-./".contains(&byte),
+        }
+    }
+}
+
+impl Display for Charset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::BcsA => "BCS-A",
+            Self::EcsA => "ECS-A",
+            Self::BcsN => "BCS-N",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Collects the findings of the fields of one header
+struct Checker<'a> {
+    findings: &'a mut Vec<Finding>,
+    segment: SegmentKind,
+    index: usize,
+    version: NitfVersion,
+}
+
+impl<'a> Checker<'a> {
+    fn new(
+        findings: &'a mut Vec<Finding>,
+        segment: SegmentKind,
+        index: usize,
+        version: NitfVersion,
+    ) -> Self {
+        Self {
+            findings,
+            segment,
+            index,
+            version,
+        }
+    }
+
+    fn finding<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>, reason: String) {
+        self.findings.push(Finding {
+            segment: self.segment,
+            index: self.index,
+            field: name.to_string(),
+            offset: field.offset(),
+            value: String::from_utf8_lossy(&field.bytes).into_owned(),
+            reason,
+        });
+    }
+
+    /// Check that every character is in `charset`
+    fn chars<V: FromStr + Debug>(
+        &mut self,
+        name: &str,
+        field: &NitfField<V>,
+        charset: Charset,
+    ) -> bool {
+        match field.bytes.iter().find(|byte| !charset.contains(**byte)) {
+            Some(byte) => {
+                let reason = format!("character 0x{byte:02X} is not {charset}");
+                self.finding(name, field, reason);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Free text in the Basic Character Set
+    fn bcs_a<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        self.chars(name, field, Charset::BcsA);
+    }
+
+    /// Free text in the Extended Character Set
+    fn ecs_a<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        self.chars(name, field, Charset::EcsA);
+    }
+
+    /// Text which must not be blank
+    fn required<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        if self.chars(name, field, Charset::BcsA) && field.string.is_empty() {
+            self.finding(name, field, "required field is blank".to_string());
+        }
+    }
+
+    /// Value which must parse as the type of the field, such as an enumeration
+    fn value<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        if self.chars(name, field, Charset::BcsA) && field.string.parse::<V>().is_err() {
+            self.finding(name, field, "not an allowed value".to_string());
+        }
+    }
+
+    /// Value which must be one of `values`
+    fn one_of<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>, values: &[&str]) {
+        if self.chars(name, field, Charset::BcsA) && !values.contains(&field.string.as_str()) {
+            let reason = format!("expected one of {values:?}");
+            self.finding(name, field, reason);
+        }
+    }
+
+    /// Positive integer of digits only, within `range`
+    fn int<V: FromStr + Debug>(
+        &mut self,
+        name: &str,
+        field: &NitfField<V>,
+        range: RangeInclusive<u64>,
+    ) {
+        if !field.bytes.iter().all(u8::is_ascii_digit) || field.bytes.is_empty() {
+            self.finding(name, field, "expected BCS-N digits".to_string());
+            return;
+        }
+        match field.string.parse::<u64>() {
+            Ok(value) if range.contains(&value) => {}
+            _ => {
+                let reason = format!("outside of {} to {}", range.start(), range.end());
+                self.finding(name, field, reason);
+            }
+        }
+    }
+
+    /// Signed value in BCS-N, which must parse as the type of the field
+    fn numeric<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        if self.chars(name, field, Charset::BcsN) && field.string.parse::<V>().is_err() {
+            self.finding(name, field, "not a valid number".to_string());
+        }
+    }
+
+    /// Date and time, CCYYMMDDhhmmss or DDhhmmssZMONYY in NITF 2.0
+    fn datetime<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        let reason = match self.version {
+            NitfVersion::Nitf20 => check_nitf20_datetime(&field.bytes),
+            _ => check_parts(&field.bytes, &DATETIME_PARTS),
+        };
+        if let Some(reason) = reason {
+            self.finding(name, field, reason);
+        }
+    }
+
+    /// Optional date, CCYYMMDD or blank
+    fn date<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        if field.bytes.iter().all(|byte| *byte == b' ') {
+            return;
+        }
+        if let Some(reason) = check_parts(&field.bytes, &DATETIME_PARTS[..3]) {
+            self.finding(name, field, reason);
+        }
+    }
+
+    /// Field which must be filled exactly when `condition` holds
+    fn present_if<V: FromStr + Debug>(
+        &mut self,
+        name: &str,
+        field: &NitfField<V>,
+        present: bool,
+        condition: &str,
+    ) {
+        match (present, field.string.is_empty()) {
+            (true, true) => self.finding(name, field, format!("required when {condition}")),
+            (false, false) => {
+                self.finding(name, field, format!("must be blank unless {condition}"))
+            }
+            _ => {}
+        }
+    }
+
+    /// Length of user defined or extended data, 0 or from 3 to 99999
+    fn extended_length<V: FromStr + Debug>(&mut self, name: &str, field: &NitfField<V>) {
+        self.int(name, field, 0..=99999);
+        if field
+            .string
+            .parse::<u64>()
+            .is_ok_and(|length| (1..3).contains(&length))
+        {
+            self.finding(name, field, "expected 0 or at least 3".to_string());
+        }
+    }
+
+    fn security(&mut self, security: &Security) {
+        self.value("CLAS", &security.clas);
+        if self.version.is_nitf20() {
+            self.bcs_a("CODE", &security.code);
+            self.bcs_a("CTLH", &security.ctlh);
+            self.bcs_a("REL", &security.rel);
+            self.bcs_a("CAUT", &security.caut);
+            self.bcs_a("CTLN", &security.ctln);
+            let dwng = security.dwng.string.as_str();
+            let is_date = dwng.len() == 6 && dwng.bytes().all(|byte| byte.is_ascii_digit());
+            if !(dwng.is_empty() || is_date) {
+                self.finding(
+                    "DWNG",
+                    &security.dwng,
+                    "expected YYMMDD, 999999 or 999998".into(),
+                );
+            }
+            if dwng == "999998" {
+                self.ecs_a("DEVT", &security.devt);
+            }
+            return;
+        }
+        let dctp = security.dctp.string.as_str();
+        self.bcs_a("CLSY", &security.clsy);
+        self.bcs_a("CODE", &security.code);
+        self.bcs_a("CTLH", &security.ctlh);
+        self.bcs_a("REL", &security.rel);
+        self.value("DCTP", &security.dctp);
+        self.date("DCDT", &security.dcdt);
+        self.present_if("DCDT", &security.dcdt, dctp == "DD", "DCTP is DD");
+        self.value("DCXM", &security.dcxm);
+        self.present_if("DCXM", &security.dcxm, dctp == "X", "DCTP is X");
+        self.value("DG", &security.dg);
+        let downgrade = matches!(dctp, "GD" | "GE");
+        self.present_if("DG", &security.dg, downgrade, "DCTP is GD or GE");
+        self.date("DGDT", &security.dgdt);
+        self.present_if("DGDT", &security.dgdt, dctp == "GD", "DCTP is GD");
+        self.ecs_a("CLTX", &security.cltx);
+        self.value("CATP", &security.catp);
+        self.ecs_a("CAUT", &security.caut);
+        self.value("CRSN", &security.crsn);
+        self.date("SRDT", &security.srdt);
+        self.bcs_a("CTLN", &security.ctln);
+    }
+
+    fn file_header(&mut self, header: &NitfHeader) {
+        self.one_of("FHDR", &header.fhdr, &["NITF", "NSIF"]);
+        self.one_of("FVER", &header.fver, &["02.00", "02.10", "01.00"]);
+        self.int("CLEVEL", &header.clevel, 1..=99);
+        if !self.version.is_nitf20() {
+            self.one_of("STYPE", &header.stype, &["BF01"]);
+        } else {
+            self.bcs_a("STYPE", &header.stype);
+        }
+        self.required("OSTAID", &header.ostaid);
+        self.datetime("FDT", &header.fdt);
+        self.ecs_a("FTITLE", &header.ftitle);
+        self.security(&header.security);
+        self.int("FSCOP", &header.fscop, 0..=99999);
+        self.int("FSCPYS", &header.fscpys, 0..=99999);
+        if header.fscop.val > header.fscpys.val {
+            let reason = format!("copy number above FSCPYS {}", header.fscpys.val);
+            self.finding("FSCOP", &header.fscop, reason);
+        }
+        self.one_of("ENCRYP", &header.encryp, &["0"]);
+        self.ecs_a("ONAME", &header.oname);
+        self.ecs_a("OPHONE", &header.ophone);
+        self.int("FL", &header.fl, 388..=999_999_999_999);
+        self.int("HL", &header.hl, 388..=999_999);
+        self.extended_length("UDHDL", &header.udhdl);
+        if header.udhdl.val != 0 {
+            self.int("UDHOFL", &header.udhofl, 0..=999);
+        }
+        self.extended_length("XHDL", &header.xhdl);
+        if header.xhdl.val != 0 {
+            self.int("XHDLOFL", &header.xhdlofl, 0..=999);
+        }
+    }
+
+    fn image(&mut self, header: &ImageHeader) {
+        self.one_of("IM", &header.im, &["IM"]);
+        self.required("IID1", &header.iid1);
+        self.datetime("IDATIM", &header.idatim);
+        self.bcs_a("TGTID", &header.tgtid);
+        self.ecs_a("IID2", &header.iid2);
+        self.security(&header.security);
+        self.one_of("ENCRYP", &header.encryp, &["0"]);
+        self.ecs_a("ISORCE", &header.isorce);
+        self.int("NROWS", &header.nrows, 1..=99_999_999);
+        self.int("NCOLS", &header.ncols, 1..=99_999_999);
+        self.value("PVTYPE", &header.pvtype);
+        self.value("IREP", &header.irep);
+        self.one_of("ICAT", &header.icat, IMAGE_CATEGORIES);
+        self.int("ABPP", &header.abpp, 1..=96);
+        if header.abpp.val > header.nbpp.val {
+            let reason = format!("above NBPP {}", header.nbpp.val);
+            self.finding("ABPP", &header.abpp, reason);
+        }
+        self.value("PJUST", &header.pjust);
+        self.value("ICORDS", &header.icords);
+        for igeolo in &header.igeolo {
+            self.bcs_a("IGEOLO", igeolo);
+        }
+        self.int("NICOM", &header.nicom, 0..=9);
+        for icom in &header.icoms {
+            self.ecs_a("ICOM", icom);
+        }
+        self.value("IC", &header.ic);
+        if !header.comrat.bytes.is_empty() {
+            self.bcs_a("COMRAT", &header.comrat);
+        }
+        self.int("NBANDS", &header.nbands, 0..=9);
+        if header.nbands.val == 0 {
+            self.int("XBANDS", &header.xbands, 10..=99999);
+        }
+        self.image_bands(header);
+        self.one_of("ISYNC", &header.isync, &["0"]);
+        self.value("IMODE", &header.imode);
+        if header.bands.len() == 1 && header.imode.string != "B" {
+            self.finding(
+                "IMODE",
+                &header.imode,
+                "expected B for a single band".into(),
+            );
+        }
+        self.int("NBPR", &header.nbpr, 1..=9999);
+        self.int("NBPC", &header.nbpc, 1..=9999);
+        self.block_size("NPPBH", &header.nppbh, header.nbpr.val, header.ncols.val);
+        self.block_size("NPPBV", &header.nppbv, header.nbpc.val, header.nrows.val);
+        self.int("NBPP", &header.nbpp, 1..=96);
+        self.int("IDLVL", &header.idlvl, 1..=999);
+        self.int("IALVL", &header.ialvl, 0..=998);
+        self.numeric("ILOC", &header.iloc);
+        self.bcs_a("IMAG", &header.imag);
+        self.extended_length("UDIDL", &header.udidl);
+        if header.udidl.val != 0 {
+            self.int("UDOFL", &header.udofl, 0..=999);
+        }
+        self.extended_length("IXSHDL", &header.ixshdl);
+        if header.ixshdl.val != 0 {
+            self.int("IXSOFL", &header.ixsofl, 0..=999);
+        }
+    }
+
+    /// Band fields, and the number of bands against IREP
+    fn image_bands(&mut self, header: &ImageHeader) {
+        let nbands = header.bands.len();
+        let expected = match header.irep.string.as_str() {
+            "MONO" | "RGB/LUT" => nbands == 1,
+            "RGB" | "YCbCr601" => nbands == 3,
+            "MULTI" | "NVECTOR" | "POLAR" | "VPH" => nbands >= 2,
+            _ => true,
+        };
+        if !expected {
+            let reason = format!("{nbands} bands for IREP {}", header.irep.string);
+            self.finding("IREP", &header.irep, reason);
+        }
+        for band in &header.bands {
+            self.bcs_a("IREPBAND", &band.irepband);
+            self.bcs_a("ISUBCAT", &band.isubcat);
+            self.one_of("IFC", &band.ifc, &["N"]);
+            self.one_of("IMFLT", &band.imflt, &[""]);
+            self.int("NLUTS", &band.nluts, 0..=4);
+            if band.nluts.val != 0 {
+                self.int("NELUT", &band.nelut, 1..=65536);
+            }
+        }
+        if header.irep.string == "RGB/LUT" && header.bands.iter().any(|band| band.nluts.val != 3) {
+            let reason = "expected 3 look-up tables for IREP RGB/LUT".to_string();
+            self.finding("IREP", &header.irep, reason);
+        }
+    }
+
+    /// NPPBH or NPPBV, where 0 is only allowed for a single block over 8192 pixels
+    fn block_size<V: FromStr + Debug>(
+        &mut self,
+        name: &str,
+        field: &NitfField<V>,
+        n_blocks: u16,
+        pixels: u32,
+    ) {
+        self.int(name, field, 0..=8192);
+        let Ok(size) = field.string.parse::<u64>() else {
+            return;
+        };
+        if size == 0 && !(n_blocks == 1 && pixels > 8192) {
+            let reason = "0 is only allowed for a single block of over 8192 pixels".to_string();
+            self.finding(name, field, reason);
+        }
+        if size != 0 && size * (n_blocks as u64) < pixels as u64 {
+            let reason = format!("{n_blocks} blocks do not cover {pixels} pixels");
+            self.finding(name, field, reason);
+        }
+    }
+
+    fn graphic(&mut self, header: &GraphicHeader) {
+        self.one_of("SY", &header.sy, &["SY"]);
+        self.bcs_a("SID", &header.sid);
+        self.ecs_a("SNAME", &header.sname);
+        self.security(&header.security);
+        self.one_of("ENCRYP", &header.encryp, &["0"]);
+        if self.version.is_nitf20() {
+            self.one_of("STYPE", &header.sfmt, &["C", "B", "O"]);
+            self.int("NLIPS", &header.nlips, 0..=9999);
+            self.int("NPIXPL", &header.npixpl, 0..=9999);
+            self.int("NWDTH", &header.nwdth, 0..=9999);
+            self.int("NBPP", &header.nbpp, 0..=9);
+        } else {
+            self.one_of("SFMT", &header.sfmt, &["C"]);
+            self.one_of("SSTRUCT", &header.sstruct, &["0000000000000"]);
+        }
+        self.int("SDLVL", &header.sdlvl, 1..=999);
+        self.int("SALVL", &header.salvl, 0..=998);
+        self.numeric("SLOC", &header.sloc);
+        if self.version.is_nitf20() {
+            self.bcs_a("SLOC2", &header.sloc2);
+            self.bcs_a("SNUM", &header.snum);
+            self.int("SROT", &header.srot, 0..=359);
+            self.int("NELUT", &header.nelut, 0..=999);
+        } else {
+            self.numeric("SBND1", &header.sbnd1);
+            self.value("SCOLOR", &header.scolor);
+            self.numeric("SBND2", &header.sbnd2);
+            self.one_of("SRES2", &header.sres2, &["00"]);
+        }
+        self.extended_length("SXSHDL", &header.sxshdl);
+        if header.sxshdl.val != 0 {
+            self.int("SXSOFL", &header.sxsofl, 0..=999);
+        }
+    }
+
+    fn label(&mut self, header: &LabelHeader) {
+        self.one_of("LA", &header.la, &["LA"]);
+        self.bcs_a("LID", &header.lid);
+        self.security(&header.security);
+        self.one_of("ENCRYP", &header.encryp, &["0"]);
+        self.bcs_a("LFS", &header.lfs);
+        self.int("LCW", &header.lcw, 0..=99);
+        self.int("LCH", &header.lch, 0..=99);
+        self.int("LDLVL", &header.ldlvl, 1..=999);
+        self.int("LALVL", &header.lalvl, 0..=998);
+        self.numeric("LLOC", &header.lloc);
+        self.extended_length("LXSHDL", &header.lxshdl);
+        if header.lxshdl.val != 0 {
+            self.int("LXSOFL", &header.lxsofl, 0..=999);
+        }
+    }
+
+    fn text(&mut self, header: &TextHeader) {
+        self.one_of("TE", &header.te, &["TE"]);
+        self.bcs_a("TEXTID", &header.textid);
+        if !self.version.is_nitf20() {
+            self.int("TXTALVL", &header.txtalvl, 0..=998);
+        }
+        self.datetime("TXTDT", &header.txtdt);
+        self.ecs_a("TXTTITL", &header.txttitl);
+        self.security(&header.security);
+        self.one_of("ENCRYP", &header.encryp, &["0"]);
+        self.value("TXTFMT", &header.txtfmt);
+        self.extended_length("TXSHDL", &header.txshdl);
+        if header.txshdl.val != 0 {
+            self.int("TXSOFL", &header.txsofl, 0..=999);
+        }
+    }
+
+    fn data_extension(&mut self, header: &DataExtensionHeader) {
+        self.one_of("DE", &header.de, &["DE"]);
+        self.required("DESID", &header.desid);
+        self.int("DESVER", &header.desver, 1..=99);
+        self.security(&header.security);
+        if header.is_overflow() {
+            self.value("DESOFLW", &header.desoflw);
+            self.int("DESITEM", &header.desitem, 0..=999);
+        }
+        self.int("DESSHL", &header.desshl, 0..=9999);
+    }
+
+    fn reserved_extension(&mut self, header: &ReservedExtensionHeader) {
+        self.one_of("RE", &header.re, &["RE"]);
+        self.required("RESID", &header.resid);
+        self.int("RESVER", &header.resver, 1..=99);
+        self.security(&header.security);
+        self.int("RESSHL", &header.resshl, 0..=9999);
+    }
+}
+
+/// Start, length and range of the parts of a CCYYMMDDhhmmss date and time
+const DATETIME_PARTS: [(usize, usize, RangeInclusive<u32>); 6] = [
+    (0, 4, 0..=9999),
+    (4, 2, 1..=12),
+    (6, 2, 1..=31),
+    (8, 2, 0..=23),
+    (10, 2, 0..=59),
+    (12, 2, 0..=60),
+];
+
+/// Reason the date `parts` of `bytes` are invalid, if they are
+///
+/// Unknown parts may be filled with hyphens.
+fn check_parts(bytes: &[u8], parts: &[(usize, usize, RangeInclusive<u32>)]) -> Option<String> {
+    let length = parts.last().map_or(0, |(start, len, _)| start + len);
+    if bytes.len() != length {
+        return Some(format!("expected {length} characters"));
+    }
+    for (start, len, range) in parts {
+        let part = &bytes[*start..start + len];
+        if part.iter().all(|byte| *byte == b'-') {
+            continue;
+        }
+        let value = std::str::from_utf8(part)
+            .ok()
+            .filter(|part| part.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|part| part.parse::<u32>().ok());
+        match value {
+            Some(value) if range.contains(&value) => {}
+            Some(value) => return Some(format!("date part {value} outside of {range:?}")),
+            None => return Some("expected digits or hyphens in date".to_string()),
+        }
+    }
+    None
+}
+
+/// Reason a NITF 2.0 DDhhmmssZMONYY date and time is invalid, if it is
+fn check_nitf20_datetime(bytes: &[u8]) -> Option<String> {
+    const MONTHS: [&[u8]; 12] = [
+        b"JAN", b"FEB", b"MAR", b"APR", b"MAY", b"JUN", b"JUL", b"AUG", b"SEP", b"OCT", b"NOV",
+        b"DEC",
+    ];
+    if bytes.len() != 14 {
+        return Some("expected 14 characters".to_string());
+    }
+    if bytes[8] != b'Z' {
+        return Some("expected Z after the time".to_string());
+    }
+    if !MONTHS.contains(&&bytes[9..12]) {
+        return Some("expected a three letter month".to_string());
+    }
+    let (day_time, year) = (&bytes[..8], &bytes[12..]);
+    let parts = [
+        (0, 2, 1..=31),
+        (2, 2, 0..=23),
+        (4, 2, 0..=59),
+        (6, 2, 0..=60),
+    ];
+    check_parts(day_time, &parts).or_else(|| check_parts(year, &[(0, 2, 0..=99)]))
+}